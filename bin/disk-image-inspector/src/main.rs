@@ -1,6 +1,25 @@
-use env_logger;
+#[cfg(feature = "luks-unlock")]
+use disk_image_inspector::LuksReader;
+use disk_image_inspector::{
+    assemble_md_array,
+    bsdlabel::is_bsd_slice_type,
+    check_gpt_layout, discover_device_partitions, find_ldm_privhead_lba, get_boot_code_sha256,
+    iso9660::ISO_MAX_DIRECTORIES_SEARCHED,
+    locate_grub_core,
+    partition::FIRST_LOGICAL_PARTITION_INDEX,
+    probe::ReadSeek,
+    sgi::{SGI_VOLUME_HEADER_PARTITION, SGI_VOLUME_PARTITION},
+    sun::SUN_TAG_BACKUP,
+    AhdiPartitionTable, ApmPartitionMap, BlockDevice, BootLoader, BootSector, BsdDisklabel, EbrChain,
+    FatDirectoryEntry, FatPartition, GptBackup, GptHeader, ImageError, IsoDirectoryRecord, IsoImage,
+    IsoVolumeDescriptor, LdmDatabase, LuksHeader, LvmPhysicalVolume, MbrCheck, MdSuperblock, Partition, PartitionEntry,
+    PartitionReader, PartitionType, ProbeRegistry, ProtectiveMbr, RigidDiskBlock, SgiVolumeHeader, SunVtoc,
+    BOOT_SECTOR_SIGNATURE, BOOT_SECTOR_SIZE, GPT_PRIMARY_HEADER_LBA, MBR_GPT_PARTITION_TYPE,
+};
 use getopts::Options;
 use std::{
+    cell::Cell,
+    collections::HashSet,
     env,
     error::Error,
     fs::File,
@@ -8,14 +27,58 @@ use std::{
     process::exit,
 };
 
-mod bootsector;
-use bootsector::{BootSector, BOOT_SECTOR_SIGNATURE, BOOT_SECTOR_SIZE};
-mod errors;
-use errors::ImageError;
-mod fat;
-use fat::{FatDirectoryEntry, FatPartition};
-mod gpt;
-use gpt::{GptHeader, GptPartitionEntry, MBR_GPT_PARTITION_TYPE};
+/// Command-line settings that apply to every image inspected.
+struct InspectOptions {
+    sector_size: Option<u32>,
+    strict: bool,
+    // The passphrase or key file contents used to unlock any LUKS volume found while inspecting.
+    #[cfg(feature = "luks-unlock")]
+    luks_passphrase: Option<Vec<u8>>,
+}
+
+// How many containers (LVM logical volumes, LUKS payloads) deep partition contents are inspected.
+const MAX_CONTENTS_DEPTH: usize = 8;
+
+/// State shared by everything that prints partition contents.
+#[derive(Clone, Copy)]
+struct ContentsContext<'a> {
+    registry: &'a ProbeRegistry,
+    options: &'a InspectOptions,
+    // Verification problems found so far, which fail the run under `--strict` once everything has been printed.
+    problems: &'a Cell<usize>,
+    depth: usize,
+}
+
+impl<'a> ContentsContext<'a> {
+    fn new(registry: &'a ProbeRegistry, options: &'a InspectOptions, problems: &'a Cell<usize>) -> Self {
+        Self {
+            registry,
+            options,
+            problems,
+            depth: 0,
+        }
+    }
+
+    fn add_problems(self, count: usize) {
+        self.problems.set(self.problems.get() + count);
+    }
+
+    /// Fail under `--strict` if any verification problems were found.
+    fn check_strict(self) -> Result<(), Box<dyn Error>> {
+        if self.options.strict && self.problems.get() > 0 {
+            return Err(format!("verification found {} problem(s)", self.problems.get()).into());
+        }
+        Ok(())
+    }
+
+    /// The context for the contents of a container found at this level.
+    fn nested(self) -> Self {
+        Self {
+            depth: self.depth + 1,
+            ..self
+        }
+    }
+}
 
 fn main() {
     env_logger::init();
@@ -24,6 +87,13 @@ fn main() {
 
     let mut opts = Options::new();
     opts.optflag("h", "help", "show this usage information");
+    opts.optopt("s", "sector-size", "logical sector size in bytes (default: autodetect)", "BYTES");
+    opts.optflag("", "strict", "exit with an error if any partition table, label or superblock check finds a problem");
+    #[cfg(feature = "luks-unlock")]
+    {
+        opts.optopt("", "passphrase", "unlock LUKS volumes with PASSPHRASE", "PASSPHRASE");
+        opts.optopt("", "key-file", "unlock LUKS volumes with the contents of FILE", "FILE");
+    }
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -39,19 +109,48 @@ fn main() {
         exit(0);
     }
 
-    if matches.free.len() == 0 {
+    if matches.free.is_empty() {
         eprintln!("Error: image-filename not specified");
         print_usage(&program, &opts, &mut stderr());
         exit(2);
-    } else if matches.free.len() > 1 {
-        eprintln!("Error: unknown argument {}", matches.free[2]);
-        print_usage(&program, &opts, &mut stderr());
-        exit(2);
     }
 
-    let image_filename = matches.free[0].clone();
+    let sector_size = match matches.opt_str("s").map(|s| s.parse::<u32>()) {
+        None => None,
+        Some(Ok(sector_size)) => Some(sector_size),
+        Some(Err(e)) => {
+            eprintln!("Error: invalid sector size: {}", e);
+            print_usage(&program, &opts, &mut stderr());
+            exit(2);
+        }
+    };
+
+    let options = InspectOptions {
+        sector_size,
+        strict: matches.opt_present("strict"),
+        #[cfg(feature = "luks-unlock")]
+        luks_passphrase: match (matches.opt_str("passphrase"), matches.opt_str("key-file")) {
+            (Some(passphrase), None) => Some(passphrase.into_bytes()),
+            (None, Some(key_file)) => match std::fs::read(&key_file) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    eprintln!("Error: unable to read key file {}: {}", key_file, e);
+                    exit(2);
+                }
+            },
+            (Some(_), Some(_)) => {
+                eprintln!("Error: --passphrase and --key-file are mutually exclusive");
+                print_usage(&program, &opts, &mut stderr());
+                exit(2);
+            }
+            (None, None) => None,
+        },
+    };
+
+    let result =
+        if matches.free.len() > 1 { run_md_array(&matches.free, &options) } else { run(&matches.free[0], &options) };
 
-    match run(&image_filename) {
+    match result {
         Ok(()) => (),
         Err(e) => {
             eprintln!("{}", e);
@@ -63,14 +162,15 @@ fn main() {
 fn print_usage<W: Write>(program: &str, opts: &Options, writer: &mut W) {
     let brief = format!(
         "Inspect a disk image and show boot sector and partition information.\n\
-    Usage: {} [options] <image-filename>",
+    Given several images, assemble the md RAID 0 or RAID 1 array they are members of and inspect its contents.\n\
+    Usage: {} [options] <image-filename> [<image-filename>...]",
         program
     );
     let _ = write!(writer, "{}", opts.usage(&brief));
 }
 
-fn run(image_filename: &str) -> Result<(), Box<dyn Error>> {
-    let mut image = match File::open(image_filename) {
+fn run(image_filename: &str, options: &InspectOptions) -> Result<(), Box<dyn Error>> {
+    let image = match File::open(image_filename) {
         Ok(f) => f,
         Err(e) => {
             eprintln!("Unable to open {} for reading: {}", image_filename, e);
@@ -78,7 +178,13 @@ fn run(image_filename: &str) -> Result<(), Box<dyn Error>> {
         }
     };
 
-    let boot_sector = match BootSector::from_disk_image(&mut image, 0) {
+    let mut device = match options.sector_size {
+        Some(sector_size) => BlockDevice::with_sector_size(image, sector_size)?,
+        None => BlockDevice::detect_sector_size(image)?,
+    };
+    println!("Logical sector size: {}", device.sector_size());
+
+    let boot_sector = match BootSector::from_disk_image(&mut device, 0) {
         Err(e) => {
             eprintln!("Failed to read master boot record ({} bytes) from {}: {}", BOOT_SECTOR_SIZE, image_filename, e);
             return Err(e.into());
@@ -86,7 +192,50 @@ fn run(image_filename: &str) -> Result<(), Box<dyn Error>> {
         Ok(bs) => bs,
     };
 
-    if &boot_sector.signature != BOOT_SECTOR_SIGNATURE {
+    let registry = ProbeRegistry::default();
+    let problems = Cell::new(0);
+    let context = ContentsContext::new(&registry, options, &problems);
+
+    let apm = ApmPartitionMap::detect(&mut device)?;
+    if let Some(map) = &apm {
+        print_apm_partition_map(&mut device, context, map)?;
+    }
+
+    let sun = SunVtoc::detect(&mut device)?;
+    if let Some(vtoc) = &sun {
+        print_sun_vtoc(&mut device, context, vtoc)?;
+    }
+
+    let sgi = SgiVolumeHeader::detect(&mut device)?;
+    if let Some(header) = &sgi {
+        print_sgi_volume_header(&mut device, context, header)?;
+    }
+
+    let rdb = RigidDiskBlock::detect(&mut device)?;
+    if let Some(rdb) = &rdb {
+        print_rigid_disk_block(&mut device, context, rdb)?;
+    }
+
+    let iso = match IsoImage::detect(&mut device) {
+        Ok(iso) => iso,
+        Err(e) => {
+            eprintln!("Warning: unable to read ISO 9660 volume: {}", e);
+            None
+        }
+    };
+
+    // Discovered once up front: the boot sector, LDM and El Torito output refer to the partitions before they are
+    // listed.
+    let partitions = discover_device_partitions(&mut device);
+    let discovered = partitions.as_deref().unwrap_or(&[]);
+
+    // Apple Partition Map, Sun, SGI, Amiga, Atari and ISO 9660 disks usually have no boot sector; hybrid images have
+    // both.
+    if &boot_sector.signature == BOOT_SECTOR_SIGNATURE {
+        print_boot_sector_tables(&mut device, context, &boot_sector, discovered)?;
+    } else if let Some(table) = AhdiPartitionTable::detect(&mut device)? {
+        print_ahdi_partition_table(&mut device, context, &table)?;
+    } else if apm.is_none() && sun.is_none() && sgi.is_none() && rdb.is_none() && iso.is_none() {
         eprintln!(
             "Image does not start with a boot sector: expected [0x{:02x}, 0x{:02x}], got [0x{:02x}, 0x{:02x}]",
             BOOT_SECTOR_SIGNATURE[0], BOOT_SECTOR_SIGNATURE[1], boot_sector.signature[0], boot_sector.signature[1],
@@ -94,70 +243,627 @@ fn run(image_filename: &str) -> Result<(), Box<dyn Error>> {
         return Err(ImageError::InvalidSignature(boot_sector.signature).into());
     }
 
-    if let Err(e) = print_mbr_partition_table(&mut image, &boot_sector, 0) {
-        eprintln!("Failed to get partition table: {}", e);
-        return Err(e.into());
+    if let Some(privhead_lba) = find_ldm_privhead_lba(discovered) {
+        print_ldm_database(&mut device, context, privhead_lba)?;
     }
 
-    let gpt_partition = &boot_sector.partitions[0];
-    if gpt_partition.partition_type.code == MBR_GPT_PARTITION_TYPE {
-        if let Err(e) = print_gpt_partition_table(&mut image, gpt_partition.lba_start as u64 * 512) {
-            eprintln!("Failed to get GPT partition table: {}", e);
+    if let Some(iso) = &iso {
+        print_iso_image(&mut device, context, iso, discovered)?;
+    }
+
+    match partitions {
+        Ok(partitions) => {
+            println!("Partitions:");
+            for partition in &partitions {
+                println!("    {}", partition);
+            }
+        }
+        // A plain ISO 9660 image has no partition table.
+        Err(ImageError::InvalidSignature(_)) if iso.is_some() => println!("Partitions: none"),
+        Err(e) => {
+            eprintln!("Failed to discover partitions: {}", e);
             return Err(e.into());
         }
     }
 
+    context.check_strict()
+}
+
+fn run_md_array(image_filenames: &[String], options: &InspectOptions) -> Result<(), Box<dyn Error>> {
+    let registry = ProbeRegistry::default();
+    let problems = Cell::new(0);
+    let context = ContentsContext::new(&registry, options, &problems);
+    let mut members = Vec::new();
+
+    for image_filename in image_filenames {
+        let image = match File::open(image_filename) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Unable to open {} for reading: {}", image_filename, e);
+                return Err(e.into());
+            }
+        };
+
+        let mut device = match options.sector_size {
+            Some(sector_size) => BlockDevice::with_sector_size(image, sector_size)?,
+            None => BlockDevice::detect_sector_size(image)?,
+        };
+
+        let (superblock, start, length) = match find_md_member(&mut device)? {
+            Some(member) => member,
+            None => return Err(format!("{} does not contain an md RAID member", image_filename).into()),
+        };
+
+        println!(
+            "md RAID member {} (offset {}):\n    {}",
+            image_filename,
+            start,
+            superblock.to_string().replace("\n", "\n    ")
+        );
+        if !superblock.is_checksum_valid() {
+            eprintln!("Warning: md superblock in {} has a bad checksum", image_filename);
+            context.add_problems(1);
+        }
+
+        members.push((superblock, PartitionReader::new(device, start, length)));
+    }
+
+    let mut array = assemble_md_array(members)?;
+    println!("md RAID array: {} bytes", array.len());
+    print_partition_contents(context, &mut array)?;
+
+    context.check_strict()
+}
+
+/// Find the md superblock on a whole device, or failing that in one of its partitions, and return it with the byte
+/// range of the member.
+fn find_md_member<R: Read + Seek>(device: &mut BlockDevice<R>) -> Result<Option<(MdSuperblock, u64, u64)>, ImageError> {
+    if let Some(superblock) = MdSuperblock::detect(device)? {
+        return Ok(Some((superblock, 0, device.byte_len()?)));
+    }
+
+    for partition in discover_device_partitions(device)? {
+        if let Some(superblock) = MdSuperblock::detect(&mut partition.get_partition_reader(&mut *device))? {
+            return Ok(Some((superblock, partition.get_start_offset(), partition.get_size())));
+        }
+    }
+
+    Ok(None)
+}
+
+fn print_boot_sector_tables<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    boot_sector: &BootSector,
+    partitions: &[Partition],
+) -> Result<(), Box<dyn Error>> {
+    println!("Master boot record:\n    {}", boot_sector.to_string().replace("\n", "\n    "));
+    if boot_sector.has_boot_code() {
+        println!("    Boot code SHA-256: {}", get_boot_code_sha256(&boot_sector.boot_code));
+    }
+    if let BootLoader::Grub2 { core_lba } = boot_sector.get_boot_loader() {
+        println!("    GRUB core.img location: {}", locate_grub_core(core_lba, partitions));
+    }
+
+    match print_mbr_partition_table(device, context, boot_sector) {
+        Ok(problem_count) => context.add_problems(problem_count),
+        Err(e) => {
+            eprintln!("Failed to get partition table: {}", e);
+            return Err(e.into());
+        }
+    }
+
+    if boot_sector.partitions.iter().any(|entry| entry.partition_type.code == MBR_GPT_PARTITION_TYPE) {
+        let header_pos = device.lba_to_offset(GPT_PRIMARY_HEADER_LBA)?;
+        match print_gpt_partition_table(device, context, boot_sector, header_pos) {
+            Ok(problem_count) => context.add_problems(problem_count),
+            Err(e) => {
+                eprintln!("Failed to get GPT partition table: {}", e);
+                return Err(e.into());
+            }
+        }
+    }
+
     Ok(())
 }
 
 fn print_mbr_partition_table<R: Read + Seek>(
-    mut reader: &mut R,
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
     boot_sector: &BootSector,
-    start_pos: u64,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<usize, ImageError> {
+    let mut problem_count = 0;
     for (i, ref partition) in boot_sector.partitions.iter().enumerate() {
         if partition.partition_type.code > 0 || partition.lba_start > 0 || partition.sector_count > 0 {
             println!("MBR Partition {}:\n    {}", i + 1, format!("{}", partition).replace("\n", "\n    "));
+            if partition.partition_type.code != MBR_GPT_PARTITION_TYPE {
+                println!("    PARTUUID: {}", boot_sector.get_partuuid(i + 1));
+            }
 
-            if !partition.is_extended() && partition.lba_start > 0 {
-                match FatPartition::from_partition_image(&mut reader, partition.lba_start as u64 * 512) {
-                    Ok(mut fp) => {
-                        println!(
-                            "    FAT Partition Information:\n        {}",
-                            format!("{}", fp.boot_sector).replace("\n", "\n        ")
-                        );
-
-                        match fp.get_root_directory_entries() {
-                            Ok(dir_entries) => {
-                                print_fat_directory(&mut fp, "/", dir_entries, 4);
-                            }
-                            Err(e) => {
-                                eprintln!("        Failed to get root directory entries: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => match e.downcast::<ImageError>() {
-                        Ok(ie) => match *ie {
-                            ImageError::InvalidSignature(_) => (),
-                            _ => return Err(ie.into()),
-                        },
-                        Err(e) => return Err(e.into()),
-                    },
-                }
+            if !partition.is_extended()
+                && partition.partition_type.code != MBR_GPT_PARTITION_TYPE
+                && partition.lba_start > 0
+            {
+                print_partition_contents(context, &mut partition.get_partition_reader(device, 0)?)?;
+            }
+
+            if is_bsd_slice_type(partition.partition_type.code) && partition.lba_start > 0 {
+                print_bsd_disklabel(device, context, partition)?;
             }
         }
     }
 
+    let mut logical_index = FIRST_LOGICAL_PARTITION_INDEX;
+    let mut logical_partitions = Vec::new();
     for partition in boot_sector.partitions.iter() {
         if partition.is_extended() {
-            let (new_boot_sector, new_start_pos) = partition.get_extended_boot_sector(reader, start_pos)?;
-            print_mbr_partition_table(reader, &new_boot_sector, new_start_pos)?;
+            let chain = EbrChain::read(device, partition)?;
+            problem_count += chain.warnings.len();
+            for warning in &chain.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+
+            for logical in &chain.logical_partitions {
+                println!(
+                    "Logical Partition {} (EBR at LBA {}, absolute LBA start {}):\n    {}",
+                    logical_index,
+                    logical.ebr_lba,
+                    logical.get_start_lba(),
+                    format!("{}", logical.entry).replace("\n", "\n    ")
+                );
+                println!("    PARTUUID: {}", boot_sector.get_partuuid(logical_index));
+                print_partition_contents(context, &mut logical.get_partition_reader(device)?)?;
+                logical_index += 1;
+            }
+            logical_partitions.extend(chain.logical_partitions);
+        }
+    }
+
+    // GPT disks are checked against the GPT instead.
+    if !boot_sector.partitions.iter().any(|entry| entry.partition_type.code == MBR_GPT_PARTITION_TYPE) {
+        let check = MbrCheck::check(device, boot_sector, &logical_partitions)?;
+        if let Some(geometry) = check.geometry {
+            println!("MBR geometry: {}", geometry);
+        }
+        problem_count += check.warnings.len();
+        for warning in &check.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+
+    Ok(problem_count)
+}
+
+fn print_bsd_disklabel<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    slice: &PartitionEntry,
+) -> Result<(), ImageError> {
+    let sector_size = device.sector_size();
+    let label = match BsdDisklabel::detect(&mut slice.get_partition_reader(device, 0)?, sector_size)? {
+        Some(label) => label,
+        None => {
+            eprintln!("Warning: BSD slice at LBA {} has no disklabel", slice.lba_start);
+            return Ok(());
+        }
+    };
+
+    println!("    BSD disklabel:\n        {}", label.to_string().replace("\n", "\n        "));
+    if !label.is_checksum_valid() {
+        eprintln!("Warning: BSD disklabel in slice at LBA {} has a bad checksum", slice.lba_start);
+        context.add_problems(1);
+    }
+
+    for partition in label.get_partitions(slice.lba_start as u64, sector_size) {
+        println!("    BSD Partition {}:", partition.get_index_name());
+        print_partition_contents(context, &mut partition.get_partition_reader(&mut *device))?;
+    }
+
+    Ok(())
+}
+
+fn print_apm_partition_map<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    map: &ApmPartitionMap,
+) -> Result<(), ImageError> {
+    println!(
+        "Apple Partition Map ({}-byte blocks):\n    {}",
+        map.block_size,
+        map.driver_descriptor.to_string().replace("\n", "\n    ")
+    );
+
+    for (i, entry) in map.entries.iter().enumerate() {
+        println!("APM Partition {}:\n    {}", i + 1, entry.to_string().replace("\n", "\n    "));
+    }
+
+    for partition in map.get_partitions() {
+        if let PartitionType::Apm(type_name) = &partition.partition_type {
+            if type_name == "Apple_Free" || type_name == "Apple_partition_map" {
+                continue;
+            }
         }
+
+        println!("APM Partition {} contents:", partition.index);
+        print_partition_contents(context, &mut partition.get_partition_reader(&mut *device))?;
     }
 
     Ok(())
 }
 
+fn print_sun_vtoc<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    vtoc: &SunVtoc,
+) -> Result<(), ImageError> {
+    println!("Sun disk label:\n    {}", vtoc.to_string().replace("\n", "\n    "));
+    if !vtoc.is_checksum_valid() {
+        eprintln!("Warning: Sun disk label has a bad checksum");
+        context.add_problems(1);
+    }
+
+    for partition in vtoc.get_partitions() {
+        // The backup slice overlaps every other slice.
+        if partition.partition_type == PartitionType::Sun(SUN_TAG_BACKUP) {
+            continue;
+        }
+
+        println!("Sun Slice {} contents:", partition.index);
+        print_partition_contents(context, &mut partition.get_partition_reader(&mut *device))?;
+    }
+
+    Ok(())
+}
+
+fn print_sgi_volume_header<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    header: &SgiVolumeHeader,
+) -> Result<(), ImageError> {
+    println!("SGI volume header:\n    {}", header.to_string().replace("\n", "\n    "));
+    if !header.is_checksum_valid() {
+        eprintln!("Warning: SGI volume header has a bad checksum");
+        context.add_problems(1);
+    }
+
+    for partition in header.get_partitions() {
+        // The volume header and whole-volume partitions overlap the others.
+        if partition.index == SGI_VOLUME_HEADER_PARTITION || partition.index == SGI_VOLUME_PARTITION {
+            continue;
+        }
+
+        println!("SGI Partition {} contents:", partition.index);
+        print_partition_contents(context, &mut partition.get_partition_reader(&mut *device))?;
+    }
+
+    Ok(())
+}
+
+fn print_ldm_database<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    privhead_lba: u64,
+) -> Result<(), ImageError> {
+    let database = match LdmDatabase::read(device, privhead_lba) {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("Warning: unable to read LDM database: {}", e);
+            return Ok(());
+        }
+    };
+
+    println!("LDM Database:\n    {}", database.to_string().replace("\n", "\n    "));
+    for volume in database.volumes.iter() {
+        println!("LDM Volume {}:", volume.name);
+        match database.get_volume_reader(&mut *device, &volume.name) {
+            Ok(mut reader) => print_partition_contents(context, &mut reader)?,
+            Err(e) => println!("    Contents: not readable: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_rigid_disk_block<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    rdb: &RigidDiskBlock,
+) -> Result<(), ImageError> {
+    println!("Amiga Rigid Disk Block:\n    {}", rdb.to_string().replace("\n", "\n    "));
+    for warning in rdb.warnings.iter() {
+        eprintln!("Warning: {}", warning);
+    }
+    context.add_problems(rdb.warnings.len());
+
+    for header in rdb.filesystem_headers.iter() {
+        println!("RDB Filesystem Header (block {}):\n    {}", header.block, header.to_string().replace("\n", "\n    "));
+    }
+
+    for (i, partition) in rdb.partitions.iter().enumerate() {
+        println!(
+            "RDB Partition {} (block {}):\n    {}",
+            i + 1,
+            partition.block,
+            partition.to_string().replace("\n", "\n    ")
+        );
+    }
+
+    for partition in rdb.get_partitions() {
+        println!("RDB Partition {} contents:", partition.index);
+        print_partition_contents(context, &mut partition.get_partition_reader(&mut *device))?;
+    }
+
+    Ok(())
+}
+
+fn print_ahdi_partition_table<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    table: &AhdiPartitionTable,
+) -> Result<(), ImageError> {
+    println!("Atari AHDI root sector:\n    {}", table.to_string().replace("\n", "\n    "));
+    for warning in table.warnings.iter() {
+        eprintln!("Warning: {}", warning);
+    }
+    context.add_problems(table.warnings.len());
+
+    for (i, partition) in table.partitions.iter().enumerate() {
+        println!(
+            "AHDI Partition {} (root sector {}, absolute start {}):\n    {}",
+            i + 1,
+            partition.root_sector,
+            partition.get_start_sector(),
+            partition.entry.to_string().replace("\n", "\n    ")
+        );
+    }
+
+    for partition in table.get_partitions() {
+        println!("AHDI Partition {} contents:", partition.index);
+        print_partition_contents(context, &mut partition.get_partition_reader(&mut *device))?;
+    }
+
+    Ok(())
+}
+
+fn print_partition_contents(context: ContentsContext, mut reader: &mut dyn ReadSeek) -> Result<(), ImageError> {
+    if context.depth > MAX_CONTENTS_DEPTH {
+        println!("    Contents: not inspected, nested more than {} containers deep", MAX_CONTENTS_DEPTH);
+        return Ok(());
+    }
+
+    let results = context.registry.probe(&mut reader)?;
+    if results.is_empty() {
+        println!("    Contents: unknown");
+        return Ok(());
+    }
+
+    for result in &results {
+        println!("    Contents: {}", result);
+    }
+
+    if results[0].type_name == "vfat" {
+        let mut fp = FatPartition::from_partition_image(&mut *reader, 0)?;
+        println!(
+            "    FAT Partition Information:\n        {}",
+            format!("{}", fp.boot_sector).replace("\n", "\n        ")
+        );
+
+        match fp.get_root_directory_entries() {
+            Ok(dir_entries) => print_fat_directory(&mut fp, "/", dir_entries, 4),
+            Err(e) => eprintln!("        Failed to get root directory entries: {}", e),
+        }
+    } else if results[0].type_name == "LVM2_member" {
+        print_lvm_physical_volume(context, reader)?;
+    } else if results[0].type_name == "crypto_LUKS" {
+        print_luks_header(context, reader)?;
+    }
+
+    // Members with the superblock at the end also look like the filesystem they mirror, so check every match.
+    if results.iter().any(|result| result.type_name == "linux_raid_member") {
+        if let Some(superblock) = MdSuperblock::detect(reader)? {
+            println!("    md RAID Superblock:\n        {}", superblock.to_string().replace("\n", "\n        "));
+        }
+    }
+
+    Ok(())
+}
+
+fn print_lvm_physical_volume(context: ContentsContext, reader: &mut dyn ReadSeek) -> Result<(), ImageError> {
+    let pv = match LvmPhysicalVolume::detect(reader) {
+        Ok(Some(pv)) => pv,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            eprintln!("Warning: unable to read LVM2 physical volume: {}", e);
+            return Ok(());
+        }
+    };
+
+    println!("    LVM2 Physical Volume:\n        {}", pv.to_string().replace("\n", "\n        "));
+    if !pv.label_checksum_valid {
+        eprintln!("Warning: LVM2 label has a bad checksum");
+        context.add_problems(1);
+    }
+
+    let vg = match &pv.volume_group {
+        Some(vg) => vg,
+        None => {
+            eprintln!("Warning: LVM2 physical volume has no volume group metadata");
+            return Ok(());
+        }
+    };
+
+    println!("    LVM2 Volume Group:\n        {}", vg.to_string().replace("\n", "\n        "));
+    for lv in vg.logical_volumes.iter() {
+        println!("    Logical Volume {}/{}:", vg.name, lv.name);
+        match pv.get_logical_volume_reader(&mut *reader, &lv.name) {
+            Ok(mut lv_reader) => print_partition_contents(context.nested(), &mut lv_reader)?,
+            Err(e) => println!("    Contents: not readable: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_luks_header(context: ContentsContext, reader: &mut dyn ReadSeek) -> Result<(), ImageError> {
+    let header = match LuksHeader::detect(reader) {
+        Ok(Some(header)) => header,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            eprintln!("Warning: unable to read LUKS header: {}", e);
+            return Ok(());
+        }
+    };
+
+    println!("    LUKS Header:\n        {}", header.to_string().replace("\n", "\n        "));
+    for checksum in header.checksums.iter().filter(|checksum| !checksum.is_valid()) {
+        eprintln!("Warning: LUKS2 header at offset 0x{:x} has a bad checksum", checksum.offset);
+        context.add_problems(1);
+    }
+    if header.version == 2 && header.checksums.len() < 2 {
+        eprintln!("Warning: LUKS2 secondary header is missing");
+        context.add_problems(1);
+    }
+
+    print_unlocked_luks_volume(context, reader, &header)
+}
+
+#[cfg(feature = "luks-unlock")]
+fn print_unlocked_luks_volume(
+    context: ContentsContext,
+    reader: &mut dyn ReadSeek,
+    header: &LuksHeader,
+) -> Result<(), ImageError> {
+    let passphrase = match &context.options.luks_passphrase {
+        Some(passphrase) => passphrase,
+        None => return Ok(()),
+    };
+
+    let key = match header.unlock(&mut *reader, passphrase) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("Warning: unable to unlock LUKS volume: {}", e);
+            return Ok(());
+        }
+    };
+
+    let mut volume = match LuksReader::new(reader, header, &key) {
+        Ok(volume) => volume,
+        Err(e) => {
+            eprintln!("Warning: unable to read unlocked LUKS volume: {}", e);
+            return Ok(());
+        }
+    };
+
+    println!("    LUKS Volume (unlocked with key slot {}):", key.keyslot);
+    print_partition_contents(context.nested(), &mut volume)
+}
+
+// Without the luks-unlock feature there is no passphrase, so LUKS volumes stay locked.
+#[cfg(not(feature = "luks-unlock"))]
+fn print_unlocked_luks_volume(
+    _context: ContentsContext,
+    _reader: &mut dyn ReadSeek,
+    _header: &LuksHeader,
+) -> Result<(), ImageError> {
+    Ok(())
+}
+
+fn print_iso_image<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    iso: &IsoImage,
+    partitions: &[Partition],
+) -> Result<(), ImageError> {
+    println!("ISO 9660 Volume:\n    {}", iso.to_string().replace("\n", "\n    "));
+
+    let descriptor = iso.get_preferred_descriptor();
+    print_iso_directory(device, iso, descriptor, "/", &descriptor.root_directory, 4, &mut HashSet::new());
+
+    let catalog = match iso.read_boot_catalog(&mut *device) {
+        Ok(Some(catalog)) => catalog,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            eprintln!("Warning: unable to read El Torito boot catalog: {}", e);
+            return Ok(());
+        }
+    };
+
+    println!("El Torito Boot Catalog:\n    {}", catalog.to_string().replace("\n", "\n    "));
+    if !catalog.checksum_valid {
+        eprintln!("Warning: El Torito validation entry has a bad checksum");
+        context.add_problems(1);
+    }
+
+    // Hybrid images usually also expose the EFI system image as a partition.
+    for entry in catalog.entries.iter() {
+        println!("    {}", entry);
+        if entry.load_rba == 0 {
+            continue;
+        }
+
+        let image = match iso.locate_boot_image(&mut *device, entry) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("Warning: unable to locate El Torito boot image: {}", e);
+                continue;
+            }
+        };
+        print!("        Image: offset 0x{:x}, {} bytes", image.offset, image.length);
+        if let Some(path) = &image.path {
+            print!(", file {}", path);
+        }
+        for partition in partitions.iter().filter(|partition| partition.get_start_offset() == image.offset) {
+            print!(", {} partition {}", partition.scheme, partition.get_index_name());
+        }
+        println!();
+
+        if entry.is_efi() {
+            print_partition_contents(context, &mut PartitionReader::new(&mut *device, image.offset, image.length))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_iso_directory<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    iso: &IsoImage,
+    descriptor: &IsoVolumeDescriptor,
+    dir_name: &str,
+    directory: &IsoDirectoryRecord,
+    indent: usize,
+    visited: &mut HashSet<u32>,
+) {
+    let indent_str = " ".repeat(indent);
+    println!("{}Directory {}", indent_str, dir_name);
+
+    if !visited.insert(directory.extent_lba) {
+        eprintln!("Warning: ISO 9660 directory {} is already listed at another path", dir_name);
+        return;
+    }
+    if visited.len() > ISO_MAX_DIRECTORIES_SEARCHED {
+        eprintln!("Warning: ISO 9660 volume has more than {} directories", ISO_MAX_DIRECTORIES_SEARCHED);
+        return;
+    }
+
+    let records = match iso.read_directory(&mut *device, descriptor, directory) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("{}    Failed to get directory entries for {}: {}", indent_str, dir_name, e);
+            return;
+        }
+    };
+
+    for record in records.iter().filter(|record| !record.is_self_or_parent()) {
+        println!("{}    {}", indent_str, record);
+    }
+
+    for record in records.iter().filter(|record| record.is_directory() && !record.is_self_or_parent()) {
+        let subdir_path = format!("{}{}/", dir_name, record.get_name());
+        print_iso_directory(device, iso, descriptor, &subdir_path, record, indent + 4, visited);
+    }
+}
+
 fn print_fat_directory<R: Read + Seek>(
     fp: &mut FatPartition<R>,
     dir_name: &str,
@@ -191,38 +897,69 @@ fn print_fat_directory<R: Read + Seek>(
 }
 
 fn print_gpt_partition_table<R: Read + Seek>(
-    mut reader: &mut R,
+    device: &mut BlockDevice<R>,
+    context: ContentsContext,
+    boot_sector: &BootSector,
     header_pos: u64,
-) -> Result<(), Box<dyn Error + 'static>> {
-    let gpt_header = GptHeader::new(reader, header_pos)?;
-    let gpt_entry_table_pos = gpt_header.partition_table_lba as u64 * 512;
+) -> Result<usize, ImageError> {
+    let gpt_header = GptHeader::new(device, header_pos)?;
+    let mut problem_count = 0;
+    if !gpt_header.is_crc32_valid() {
+        problem_count += 1;
+    }
 
     println!("GPT header:\n    {}", gpt_header.to_string().replace("\n", "\n    "));
+    match gpt_header.compute_partition_entry_array_crc32(device) {
+        Ok(crc32) if crc32 == gpt_header.partition_entry_array_crc32 => {
+            println!("    Partition table CRC32 check: valid")
+        }
+        Ok(crc32) => {
+            println!("    Partition table CRC32 check: mismatch, computed 0x{:08x}", crc32);
+            problem_count += 1;
+        }
+        Err(e) => eprintln!("Warning: could not read the GPT partition entry array: {}", e),
+    }
+
+    let entries = gpt_header.read_partition_entries(device)?;
 
-    for i in 0..gpt_header.partition_count {
-        let partition =
-            GptPartitionEntry::new(reader, gpt_entry_table_pos + gpt_header.partition_entry_size as u64 * i as u64)?;
+    let protective_mbr = ProtectiveMbr::check(boot_sector, &entries, device.sector_count()?);
+    println!("MBR type: {}", protective_mbr.kind);
+    for warning in protective_mbr.warnings.iter() {
+        eprintln!("Warning: {}", warning);
+    }
+    problem_count += protective_mbr.warnings.len();
 
-        if partition.partition_type.as_u128() != 0u128 {
+    let layout_warnings = check_gpt_layout(&gpt_header, &entries, device.sector_size());
+    for warning in layout_warnings.iter() {
+        if warning.is_informational() {
+            eprintln!("Note: {}", warning);
+        } else {
+            eprintln!("Warning: {}", warning);
+            problem_count += 1;
+        }
+    }
+
+    for (i, partition) in entries.iter().enumerate() {
+        if partition.is_used() {
             println!("GPT Partition {}:\n    {}", i + 1, format!("{}", partition).replace("\n", "\n    "));
 
-            match FatPartition::from_partition_image(&mut reader, partition.starting_lba as u64 * 512) {
-                Ok(fp) => {
-                    println!(
-                        "    FAT Partition Information:\n        {}",
-                        format!("{}", fp.boot_sector).replace("\n", "\n        ")
-                    );
-                }
-                Err(e) => match e.downcast::<ImageError>() {
-                    Ok(ie) => match *ie {
-                        ImageError::InvalidSignature(_) => (),
-                        _ => return Err(ie.into()),
-                    },
-                    Err(e) => return Err(e.into()),
-                },
+            match partition.get_partition_reader(device) {
+                Ok(mut reader) => print_partition_contents(context, &mut reader)?,
+                Err(e) => eprintln!("Warning: cannot read GPT partition {}: {}", i + 1, e),
             }
         }
     }
 
-    Ok(())
+    let backup = GptBackup::read(device, &gpt_header)?;
+    for warning in backup.warnings.iter() {
+        eprintln!("Warning: {}", warning);
+    }
+    problem_count += backup.warnings.len();
+    if let Some(backup_header) = backup.header {
+        if backup.warnings.is_empty() {
+            println!("Backup GPT header at LBA {} matches the primary", backup_header.current_lba);
+        }
+    }
+
+    Ok(problem_count)
 }
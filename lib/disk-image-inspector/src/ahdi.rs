@@ -0,0 +1,352 @@
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    partition::{Partition, PartitionScheme, PartitionType},
+    probe::{read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const AHDI_SECTOR_SIZE: u32 = 512;
+pub const AHDI_DISK_SIZE_OFFSET: usize = 0x1c2;
+pub const AHDI_PARTITION_TABLE_OFFSET: usize = 0x1c6;
+pub const AHDI_PARTITION_ENTRIES: usize = 4;
+pub const AHDI_PARTITION_ENTRY_SIZE: usize = 12;
+pub const AHDI_BAD_SECTOR_LIST_OFFSET: usize = 0x1f6;
+
+// The root sector is bootable when its big-endian words sum to this value.
+pub const AHDI_BOOTABLE_CHECKSUM: u16 = 0x1234;
+
+pub const AHDI_FLAG_EXISTS: u8 = 0x01;
+pub const AHDI_FLAG_BOOTABLE: u8 = 0x80;
+
+pub const AHDI_EXTENDED_ID: &str = "XGM";
+
+// Upper bound on the number of extended root sectors followed.
+pub const MAX_XGM_CHAIN_LENGTH: usize = 256;
+
+// Partition IDs used by AHDI, ICD, HDDriver and the Linux/NetBSD ports.
+pub const AHDI_PARTITION_TYPES: [(&str, &str); 11] = [
+    ("GEM", "GEMDOS (< 16 MiB)"),
+    ("BGM", "GEMDOS (>= 16 MiB)"),
+    (AHDI_EXTENDED_ID, "Extended"),
+    ("F32", "FAT32"),
+    ("LNX", "Linux"),
+    ("SWP", "Swap"),
+    ("RAW", "Raw"),
+    ("MIX", "Minix"),
+    ("MNX", "Minix"),
+    ("UNX", "Atari System V"),
+    ("MAC", "Macintosh HFS"),
+];
+
+/// The name of an AHDI partition ID, or the ID itself if it is not a known one.
+pub fn get_ahdi_partition_type_name(id: &str) -> &str {
+    AHDI_PARTITION_TYPES.iter().find(|(code, _)| *code == id).map(|(_, name)| *name).unwrap_or(id)
+}
+
+/// A 12-byte partition entry in an AHDI root sector or extended root sector.
+#[derive(Clone, Debug)]
+pub struct AhdiPartitionEntry {
+    pub flags: u8,
+    pub id: String,
+    pub start: u32,
+    pub size: u32,
+}
+
+impl AhdiPartitionEntry {
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            flags: data[0],
+            id: String::from_utf8_lossy(&data[1..4]).to_string(),
+            start: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            size: u32::from_be_bytes(data[8..12].try_into().unwrap()),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.flags & AHDI_FLAG_EXISTS != 0
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.id == AHDI_EXTENDED_ID
+    }
+
+    /// Whether the entry has a printable ID and fits on a disk of `disk_size` sectors.
+    pub fn is_valid(&self, disk_size: u32) -> bool {
+        self.id.bytes().all(|b| b.is_ascii_alphanumeric())
+            && (disk_size == 0 || self.start as u64 + self.size as u64 <= disk_size as u64)
+    }
+}
+
+impl Display for AhdiPartitionEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "ID: {} ({})\nFlags: 0x{:02x}{}\nStart: {}\nSize: {}",
+            self.id,
+            get_ahdi_partition_type_name(&self.id),
+            self.flags,
+            if self.flags & AHDI_FLAG_BOOTABLE != 0 { " (bootable)" } else { "" },
+            self.start,
+            self.size
+        )
+    }
+}
+
+/// An Atari AHDI root sector, or an extended root sector in an XGM chain.
+#[derive(Clone, Debug)]
+pub struct AhdiRootSector {
+    pub disk_size: u32,
+    pub entries: Vec<AhdiPartitionEntry>,
+    pub bad_sector_list_start: u32,
+    pub bad_sector_list_count: u32,
+    // Sum of the sector's big-endian words; AHDI_BOOTABLE_CHECKSUM if the root sector is bootable.
+    pub word_sum: u16,
+}
+
+impl AhdiRootSector {
+    pub fn new(data: &[u8]) -> Self {
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let entries = data[AHDI_PARTITION_TABLE_OFFSET..]
+            .chunks_exact(AHDI_PARTITION_ENTRY_SIZE)
+            .take(AHDI_PARTITION_ENTRIES)
+            .map(AhdiPartitionEntry::new)
+            .collect();
+        let word_sum = data[..AHDI_SECTOR_SIZE as usize]
+            .chunks_exact(2)
+            .fold(0u16, |acc, word| acc.wrapping_add(u16::from_be_bytes([word[0], word[1]])));
+
+        Self {
+            disk_size: be32(AHDI_DISK_SIZE_OFFSET),
+            entries,
+            bad_sector_list_start: be32(AHDI_BAD_SECTOR_LIST_OFFSET),
+            bad_sector_list_count: be32(AHDI_BAD_SECTOR_LIST_OFFSET + 4),
+            word_sum,
+        }
+    }
+
+    pub fn is_bootable(&self) -> bool {
+        self.word_sum == AHDI_BOOTABLE_CHECKSUM
+    }
+
+    /// Whether this looks like an AHDI root sector: a disk size, at least one partition, and only valid partitions.
+    pub fn looks_valid(&self) -> bool {
+        let mut existing = self.entries.iter().filter(|entry| entry.exists()).peekable();
+        self.disk_size > 0 && existing.peek().is_some() && existing.all(|entry| entry.is_valid(self.disk_size))
+    }
+}
+
+/// A partition found in an AHDI root sector or in an XGM chain.
+#[derive(Clone, Debug)]
+pub struct AhdiPartition {
+    // Absolute sector of the root sector that describes this partition; the entry's `start` is relative to it.
+    pub root_sector: u64,
+    pub entry: AhdiPartitionEntry,
+}
+
+impl AhdiPartition {
+    pub fn get_start_sector(&self) -> u64 {
+        self.root_sector + self.entry.start as u64
+    }
+}
+
+/// A problem found while walking an XGM chain.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AhdiWarning {
+    Loop { sector: u64 },
+    TooLong { limit: usize },
+    ReadFailed { sector: u64, error: String },
+    InvalidEntry { sector: u64, slot: usize },
+}
+
+impl Display for AhdiWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Loop { sector } => write!(f, "XGM chain loops back to sector {}", sector),
+            Self::TooLong { limit } => write!(f, "XGM chain is longer than {} entries; stopped following it", limit),
+            Self::ReadFailed { sector, error } => {
+                write!(f, "Unable to read extended root sector at sector {}: {}", sector, error)
+            }
+            Self::InvalidEntry { sector, slot } => {
+                write!(f, "Root sector at sector {} has an invalid entry in slot {}", sector, slot + 1)
+            }
+        }
+    }
+}
+
+/// An Atari AHDI partition table: the root sector and the partitions in it and in any XGM chains.
+#[derive(Clone, Debug)]
+pub struct AhdiPartitionTable {
+    pub root: AhdiRootSector,
+    pub partitions: Vec<AhdiPartition>,
+    pub warnings: Vec<AhdiWarning>,
+}
+
+impl AhdiPartitionTable {
+    /// Read the partition table from the first sector of `reader`, or return `None` if it does not look like AHDI.
+    ///
+    /// AHDI has no magic number, so detection relies on the root sector's partition entries being plausible.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        let root = match read_probe_bytes(reader, 0, AHDI_SECTOR_SIZE as usize)? {
+            Some(data) => AhdiRootSector::new(&data),
+            None => return Ok(None),
+        };
+
+        if !root.looks_valid() {
+            return Ok(None);
+        }
+
+        let mut table = Self {
+            partitions: Vec::new(),
+            warnings: Vec::new(),
+            root,
+        };
+
+        for entry in table.root.entries.clone().into_iter().filter(|entry| entry.exists()) {
+            if entry.is_extended() {
+                table.read_xgm_chain(reader, entry.start as u64);
+            } else {
+                table.partitions.push(AhdiPartition { root_sector: 0, entry });
+            }
+        }
+
+        Ok(Some(table))
+    }
+
+    /// Walk an XGM chain. The first entry of each extended root sector is relative to that sector, and the link to
+    /// the next extended root sector is relative to the start of the chain.
+    fn read_xgm_chain<R: Read + Seek + ?Sized>(&mut self, reader: &mut R, chain_start: u64) {
+        let mut visited = HashSet::new();
+        let mut sector = chain_start;
+
+        loop {
+            if !visited.insert(sector) {
+                self.warnings.push(AhdiWarning::Loop { sector });
+                return;
+            }
+
+            if visited.len() > MAX_XGM_CHAIN_LENGTH {
+                self.warnings.push(AhdiWarning::TooLong {
+                    limit: MAX_XGM_CHAIN_LENGTH,
+                });
+                return;
+            }
+
+            let mut data = [0u8; AHDI_SECTOR_SIZE as usize];
+            if let Err(e) = read_exact_at(reader, sector * AHDI_SECTOR_SIZE as u64, &mut data) {
+                self.warnings.push(AhdiWarning::ReadFailed {
+                    sector,
+                    error: e.to_string(),
+                });
+                return;
+            }
+
+            let extended = AhdiRootSector::new(&data);
+            let entry = &extended.entries[0];
+            if !entry.exists() || !entry.is_valid(self.root.disk_size) {
+                self.warnings.push(AhdiWarning::InvalidEntry { sector, slot: 0 });
+                return;
+            }
+
+            self.partitions.push(AhdiPartition {
+                root_sector: sector,
+                entry: entry.clone(),
+            });
+
+            let link = &extended.entries[1];
+            if !link.exists() || !link.is_extended() {
+                return;
+            }
+            sector = chain_start + link.start as u64;
+        }
+    }
+
+    /// The partitions in the order they were found, numbered from 1.
+    pub fn get_partitions(&self) -> Vec<Partition> {
+        self.partitions
+            .iter()
+            .filter(|partition| partition.entry.size > 0)
+            .enumerate()
+            .map(|(i, partition)| Partition {
+                index: i + 1,
+                scheme: PartitionScheme::Ahdi,
+                partition_type: PartitionType::Ahdi(partition.entry.id.clone()),
+                name: None,
+                unique_guid: None,
+                flags: partition.entry.flags as u64,
+                sector_size: AHDI_SECTOR_SIZE,
+                start_lba: partition.get_start_sector(),
+                end_lba: partition.get_start_sector() + partition.entry.size as u64 - 1,
+            })
+            .collect()
+    }
+}
+
+impl Display for AhdiPartitionTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Disk size: {} sectors\nBad sector list: sector {}, {} sectors\nRoot sector bootable: {}",
+            self.root.disk_size,
+            self.root.bad_sector_list_start,
+            self.root.bad_sector_list_count,
+            if self.root.is_bootable() { "yes" } else { "no" },
+        )
+    }
+}
+
+/// Detect an Atari AHDI partition table.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    Ok(AhdiPartitionTable::detect(reader)?.map(|table| {
+        let mut result = ProbeResult::new("atari", ProbeUsage::PartitionTable, ProbeConfidence::Low);
+        result.version = Some(format!("{} partitions", table.partitions.len()));
+        result
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_entry(sector: &mut [u8], slot: usize, flags: u8, id: &str, start: u32, size: u32) {
+        let offset = AHDI_PARTITION_TABLE_OFFSET + slot * AHDI_PARTITION_ENTRY_SIZE;
+        let entry = &mut sector[offset..offset + AHDI_PARTITION_ENTRY_SIZE];
+        entry[0] = flags;
+        entry[1..4].copy_from_slice(id.as_bytes());
+        entry[4..8].copy_from_slice(&start.to_be_bytes());
+        entry[8..12].copy_from_slice(&size.to_be_bytes());
+    }
+
+    #[test]
+    fn read_xgm_chain() {
+        let mut image = vec![0u8; 1024 * 512];
+        image[AHDI_DISK_SIZE_OFFSET..AHDI_DISK_SIZE_OFFSET + 4].copy_from_slice(&1024u32.to_be_bytes());
+        write_entry(&mut image[0..512], 0, AHDI_FLAG_EXISTS | AHDI_FLAG_BOOTABLE, "GEM", 2, 100);
+        write_entry(&mut image[0..512], 1, AHDI_FLAG_EXISTS, "XGM", 200, 600);
+
+        // Extended root sectors at 200 and 500; the second link is relative to the start of the chain.
+        write_entry(&mut image[200 * 512..201 * 512], 0, AHDI_FLAG_EXISTS, "BGM", 1, 200);
+        write_entry(&mut image[200 * 512..201 * 512], 1, AHDI_FLAG_EXISTS, "XGM", 300, 300);
+        write_entry(&mut image[500 * 512..501 * 512], 0, AHDI_FLAG_EXISTS, "LNX", 1, 250);
+        write_entry(&mut image[500 * 512..501 * 512], 1, AHDI_FLAG_EXISTS, "XGM", 0, 300);
+
+        let table = AhdiPartitionTable::detect(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(table.warnings, vec![AhdiWarning::Loop { sector: 200 }]);
+
+        let partitions = table.get_partitions();
+        assert_eq!(partitions.len(), 3);
+        assert!(partitions[0].is_bootable());
+        assert_eq!(partitions[1].start_lba, 201);
+        assert_eq!(partitions[1].partition_type.get_name(), "GEMDOS (>= 16 MiB)");
+        assert_eq!(partitions[2].start_lba, 501);
+        assert_eq!(partitions[2].end_lba, 750);
+
+        assert!(AhdiPartitionTable::detect(&mut Cursor::new(vec![0u8; 512])).unwrap().is_none());
+    }
+}
@@ -0,0 +1,333 @@
+use log::warn;
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    partition::{Partition, PartitionScheme, PartitionType},
+    probe::{decode_c_string, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const APM_DDR_SIGNATURE: &[u8; 2] = b"ER";
+pub const APM_ENTRY_SIGNATURE: &[u8; 2] = b"PM";
+pub const APM_DEFAULT_BLOCK_SIZE: u32 = 512;
+pub const APM_ENTRY_SIZE: usize = 136;
+
+// Upper bound on the number of map entries read; real maps rarely have more than a few dozen.
+pub const MAX_APM_ENTRIES: u32 = 1024;
+
+pub const APM_STATUS_BOOT_INFO: u32 = 0x0000_0008;
+
+pub const APM_STATUS_FLAGS: [(u32, &str); 12] = [
+    (0x0000_0001, "valid"),
+    (0x0000_0002, "allocated"),
+    (0x0000_0004, "in use"),
+    (APM_STATUS_BOOT_INFO, "boot info"),
+    (0x0000_0010, "readable"),
+    (0x0000_0020, "writable"),
+    (0x0000_0040, "position-independent boot code"),
+    (0x0000_0100, "chain-compatible driver"),
+    (0x0000_0200, "real driver"),
+    (0x0000_0400, "chain driver"),
+    (0x4000_0000, "automount"),
+    (0x8000_0000, "startup"),
+];
+
+/// Names of the status flags set in an APM entry's `pmPartStatus`.
+pub fn describe_apm_status(status: u32) -> Vec<&'static str> {
+    APM_STATUS_FLAGS.iter().filter(|(mask, _)| status & mask != 0).map(|(_, name)| *name).collect()
+}
+
+/// A device driver listed in the driver descriptor block.
+#[derive(Clone, Debug)]
+pub struct ApmDriver {
+    pub start_block: u32,
+    pub block_count: u16,
+    pub os_type: u16,
+}
+
+/// The driver descriptor block ("ER") at the start of an APM disk.
+#[derive(Clone, Debug)]
+pub struct ApmDriverDescriptor {
+    pub block_size: u16,
+    pub block_count: u32,
+    pub device_type: u16,
+    pub device_id: u16,
+    pub drivers: Vec<ApmDriver>,
+}
+
+impl ApmDriverDescriptor {
+    pub fn new(data: &[u8]) -> Result<Self, ImageError> {
+        if &data[0..2] != APM_DDR_SIGNATURE {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "APM driver descriptor signature is {}, not \"ER\"",
+                hex::encode(&data[0..2])
+            )));
+        }
+
+        let driver_count = u16::from_be_bytes(data[16..18].try_into().unwrap()) as usize;
+        // Each descriptor is 8 bytes, starting at offset 18; only as many as fit in the block are read.
+        let drivers = data[18..]
+            .chunks_exact(8)
+            .take(driver_count)
+            .map(|driver| ApmDriver {
+                start_block: u32::from_be_bytes(driver[0..4].try_into().unwrap()),
+                block_count: u16::from_be_bytes(driver[4..6].try_into().unwrap()),
+                os_type: u16::from_be_bytes(driver[6..8].try_into().unwrap()),
+            })
+            .collect();
+
+        Ok(Self {
+            block_size: u16::from_be_bytes(data[2..4].try_into().unwrap()),
+            block_count: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            device_type: u16::from_be_bytes(data[8..10].try_into().unwrap()),
+            device_id: u16::from_be_bytes(data[10..12].try_into().unwrap()),
+            drivers,
+        })
+    }
+}
+
+impl Display for ApmDriverDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Block size: {}\nBlock count: {}\nDevice type: 0x{:04x}\nDevice ID: 0x{:04x}\nDrivers: {}",
+            self.block_size,
+            self.block_count,
+            self.device_type,
+            self.device_id,
+            self.drivers.len()
+        )?;
+
+        for driver in self.drivers.iter() {
+            write!(
+                f,
+                "\n    Block {}, {} blocks, OS type 0x{:04x}",
+                driver.start_block, driver.block_count, driver.os_type
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A partition map entry ("PM").
+#[derive(Clone, Debug)]
+pub struct ApmEntry {
+    pub map_block_count: u32,
+    pub start_block: u32,
+    pub block_count: u32,
+    pub name: String,
+    pub partition_type: String,
+    pub data_start: u32,
+    pub data_count: u32,
+    pub status: u32,
+    pub boot_start: u32,
+    pub boot_size: u32,
+    pub processor: String,
+}
+
+impl ApmEntry {
+    pub fn new(data: &[u8]) -> Result<Self, ImageError> {
+        if &data[0..2] != APM_ENTRY_SIGNATURE {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "APM entry signature is {}, not \"PM\"",
+                hex::encode(&data[0..2])
+            )));
+        }
+
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        Ok(Self {
+            map_block_count: be32(4),
+            start_block: be32(8),
+            block_count: be32(12),
+            name: decode_c_string(&data[16..48]),
+            partition_type: decode_c_string(&data[48..80]),
+            data_start: be32(80),
+            data_count: be32(84),
+            status: be32(88),
+            boot_start: be32(92),
+            boot_size: be32(96),
+            processor: decode_c_string(&data[120..136]),
+        })
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.partition_type == "Apple_Free"
+    }
+
+    pub fn is_partition_map(&self) -> bool {
+        self.partition_type == "Apple_partition_map"
+    }
+}
+
+impl Display for ApmEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Name: {}\nType: {}\nStart block: {}\nBlock count: {}\nData: blocks {}+{}\nStatus: 0x{:08x} ({})",
+            self.name,
+            self.partition_type,
+            self.start_block,
+            self.block_count,
+            self.data_start,
+            self.data_count,
+            self.status,
+            describe_apm_status(self.status).join(", "),
+        )?;
+
+        if self.boot_size != 0 {
+            write!(f, "\nBoot code: block {}, {} bytes", self.boot_start, self.boot_size)?;
+        }
+
+        if !self.processor.is_empty() {
+            write!(f, "\nProcessor: {}", self.processor)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An Apple Partition Map: the driver descriptor block and the partition map entries that follow it.
+#[derive(Clone, Debug)]
+pub struct ApmPartitionMap {
+    pub driver_descriptor: ApmDriverDescriptor,
+    // The block size the entries were found with; partition addresses are in these units.
+    pub block_size: u32,
+    pub entries: Vec<ApmEntry>,
+}
+
+impl ApmPartitionMap {
+    /// Read the partition map from the start of `reader`, or return `None` if there is no APM.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        let ddr_bytes = match read_probe_bytes(reader, 0, APM_DEFAULT_BLOCK_SIZE as usize)? {
+            Some(ddr_bytes) if &ddr_bytes[0..2] == APM_DDR_SIGNATURE => ddr_bytes,
+            _ => return Ok(None),
+        };
+        let driver_descriptor = ApmDriverDescriptor::new(&ddr_bytes)?;
+
+        // Hybrid ISOs declare 2048-byte blocks but may place the map at 512-byte intervals, so try both.
+        let mut block_sizes = vec![APM_DEFAULT_BLOCK_SIZE];
+        let declared = driver_descriptor.block_size as u32;
+        if declared > APM_DEFAULT_BLOCK_SIZE && declared.is_power_of_two() {
+            block_sizes.insert(0, declared);
+        }
+
+        for block_size in block_sizes {
+            if let Some(first) = read_probe_bytes(reader, block_size as u64, APM_ENTRY_SIZE)? {
+                if &first[0..2] == APM_ENTRY_SIGNATURE {
+                    let entries = read_entries(reader, block_size, ApmEntry::new(&first)?)?;
+                    return Ok(Some(Self {
+                        driver_descriptor,
+                        block_size,
+                        entries,
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The map entries as partitions, numbered by their position in the map (starting from 1).
+    pub fn get_partitions(&self) -> Vec<Partition> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.block_count > 0)
+            .map(|(i, entry)| Partition {
+                index: i + 1,
+                scheme: PartitionScheme::Apm,
+                partition_type: PartitionType::Apm(entry.partition_type.clone()),
+                name: if entry.name.is_empty() { None } else { Some(entry.name.clone()) },
+                unique_guid: None,
+                flags: entry.status as u64,
+                sector_size: self.block_size,
+                start_lba: entry.start_block as u64,
+                end_lba: entry.start_block as u64 + entry.block_count as u64 - 1,
+            })
+            .collect()
+    }
+}
+
+fn read_entries<R: Read + Seek + ?Sized>(
+    reader: &mut R,
+    block_size: u32,
+    first: ApmEntry,
+) -> Result<Vec<ApmEntry>, ImageError> {
+    let map_block_count = first.map_block_count.min(MAX_APM_ENTRIES);
+    let mut entries = vec![first];
+
+    for block in 2..=map_block_count as u64 {
+        let mut entry_bytes = [0u8; APM_ENTRY_SIZE];
+        if let Err(e) = read_exact_at(reader, block * block_size as u64, &mut entry_bytes) {
+            warn!("Unable to read APM entry {}: {}", block, e);
+            break;
+        }
+
+        match ApmEntry::new(&entry_bytes) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                warn!("APM entry {} is invalid: {}", block, e);
+                break;
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Detect an Apple Partition Map.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    Ok(ApmPartitionMap::detect(reader)?.map(|map| {
+        let mut result = ProbeResult::new("apm", ProbeUsage::PartitionTable, ProbeConfidence::High);
+        result.version = Some(format!("{}-byte blocks, {} entries", map.block_size, map.entries.len()));
+        result
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_entry(image: &mut [u8], block: usize, map_blocks: u32, start: u32, count: u32, name: &str, kind: &str) {
+        let entry = &mut image[block * 512..block * 512 + APM_ENTRY_SIZE];
+        entry[0..2].copy_from_slice(APM_ENTRY_SIGNATURE);
+        entry[4..8].copy_from_slice(&map_blocks.to_be_bytes());
+        entry[8..12].copy_from_slice(&start.to_be_bytes());
+        entry[12..16].copy_from_slice(&count.to_be_bytes());
+        entry[16..16 + name.len()].copy_from_slice(name.as_bytes());
+        entry[48..48 + kind.len()].copy_from_slice(kind.as_bytes());
+        entry[88..92].copy_from_slice(&0x4000_0033u32.to_be_bytes());
+    }
+
+    #[test]
+    fn read_partition_map() {
+        let mut image = vec![0u8; 64 * 512];
+        image[0..2].copy_from_slice(APM_DDR_SIGNATURE);
+        image[2..4].copy_from_slice(&512u16.to_be_bytes());
+        image[4..8].copy_from_slice(&64u32.to_be_bytes());
+        write_entry(&mut image, 1, 2, 1, 63, "Apple", "Apple_partition_map");
+        write_entry(&mut image, 2, 2, 16, 48, "Macintosh HD", "Apple_HFS");
+
+        let map = ApmPartitionMap::detect(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(map.driver_descriptor.block_count, 64);
+        assert_eq!(map.entries.len(), 2);
+        assert!(map.entries[0].is_partition_map());
+
+        let partitions = map.get_partitions();
+        assert_eq!(partitions[1].index, 2);
+        assert_eq!(partitions[1].name.as_deref(), Some("Macintosh HD"));
+        assert_eq!(partitions[1].partition_type, PartitionType::Apm("Apple_HFS".to_string()));
+        assert_eq!(partitions[1].get_start_offset(), 16 * 512);
+        assert_eq!(partitions[1].end_lba, 63);
+        assert_eq!(
+            describe_apm_status(map.entries[1].status),
+            vec!["valid", "allocated", "readable", "writable", "automount"]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use log::debug;
+use std::io::{Read, Result as IoResult, Seek, SeekFrom};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    gpt::GPT_HEADER_SIGNATURE,
+};
+
+pub const DEFAULT_SECTOR_SIZE: u32 = 512;
+
+// Logical sector sizes probed (in order) when looking for a GPT header at LBA 1.
+pub const PROBED_SECTOR_SIZES: [u32; 2] = [512, 4096];
+
+#[derive(Debug)]
+pub struct BlockDevice<R: Read + Seek> {
+    reader: R,
+    sector_size: u32,
+}
+
+impl<R: Read + Seek> BlockDevice<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            sector_size: DEFAULT_SECTOR_SIZE,
+        }
+    }
+
+    pub fn with_sector_size(reader: R, sector_size: u32) -> Result<Self, ImageError> {
+        if sector_size < DEFAULT_SECTOR_SIZE || !sector_size.is_power_of_two() {
+            return Err(ImageError::InvalidGeometry(format!(
+                "logical sector size {} is not a power of two of at least {}",
+                sector_size, DEFAULT_SECTOR_SIZE
+            )));
+        }
+
+        Ok(Self { reader, sector_size })
+    }
+
+    /// Create a block device, detecting the logical sector size by looking for a GPT header signature at LBA 1.
+    ///
+    /// If no GPT header is found, the sector size defaults to 512 bytes.
+    pub fn detect_sector_size(mut reader: R) -> Result<Self, ImageError> {
+        for &sector_size in PROBED_SECTOR_SIZES.iter() {
+            let mut signature = [0u8; 8];
+            match read_exact_at(&mut reader, sector_size as u64, &mut signature) {
+                Ok(()) if signature == GPT_HEADER_SIGNATURE => {
+                    debug!("Found GPT header signature at LBA 1 with {}-byte sectors", sector_size);
+                    return Self::with_sector_size(reader, sector_size);
+                }
+                Ok(()) | Err(ImageError::Truncated { .. }) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(Self::new(reader))
+    }
+
+    pub fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    /// The byte offset of `lba`, or an error if it lies beyond the addressable range.
+    pub fn lba_to_offset(&self, lba: u64) -> Result<u64, ImageError> {
+        lba.checked_mul(self.sector_size as u64)
+            .ok_or_else(|| ImageError::InvalidGeometry(format!("LBA {} is beyond the addressable range", lba)))
+    }
+
+    pub fn offset_to_lba(&self, offset: u64) -> u64 {
+        offset / self.sector_size as u64
+    }
+
+    /// The size of the underlying image in bytes.
+    pub fn byte_len(&mut self) -> Result<u64, ImageError> {
+        self.reader.seek(SeekFrom::End(0)).map_err(|source| ImageError::Io { offset: 0, source })
+    }
+
+    /// The number of whole sectors in the underlying image.
+    pub fn sector_count(&mut self) -> Result<u64, ImageError> {
+        let byte_len = self.byte_len()?;
+        Ok(self.offset_to_lba(byte_len))
+    }
+
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), ImageError> {
+        read_exact_at(&mut self.reader, offset, buf)
+    }
+
+    pub fn read_sectors(&mut self, lba: u64, count: usize) -> Result<Vec<u8>, ImageError> {
+        let mut data = vec![0; count * self.sector_size as usize];
+        self.read_at(self.lba_to_offset(lba)?, &mut data)?;
+        Ok(data)
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> Read for BlockDevice<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: Read + Seek> Seek for BlockDevice<R> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.reader.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn detect_sector_size() {
+        for &sector_size in PROBED_SECTOR_SIZES.iter() {
+            let mut image = vec![0u8; 3 * sector_size as usize];
+            image[sector_size as usize..sector_size as usize + 8].copy_from_slice(&GPT_HEADER_SIGNATURE);
+            let device = BlockDevice::detect_sector_size(Cursor::new(image)).unwrap();
+            assert_eq!(device.sector_size(), sector_size);
+            assert_eq!(device.lba_to_offset(2).unwrap(), 2 * sector_size as u64);
+            assert!(device.lba_to_offset(u64::MAX / 256).is_err());
+        }
+
+        let device = BlockDevice::detect_sector_size(Cursor::new(vec![0u8; 1024])).unwrap();
+        assert_eq!(device.sector_size(), DEFAULT_SECTOR_SIZE);
+    }
+
+    #[test]
+    fn reject_invalid_sector_size() {
+        assert!(BlockDevice::with_sector_size(Cursor::new(vec![]), 1000).is_err());
+        assert!(BlockDevice::with_sector_size(Cursor::new(vec![]), 256).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use sha2::{Digest, Sha256};
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+use uuid::Uuid;
+
+use crate::partition::{Partition, PartitionType};
+
+pub const BIOS_BOOT_PARTITION_TYPE: u128 = 0x2168614864496e6f744e656564454649;
+
+// GRUB 2 boot.img stores the LBA of the first core.img sector here (GRUB_BOOT_MACHINE_KERNEL_SECTOR).
+const GRUB2_KERNEL_SECTOR_OFFSET: usize = 0x5c;
+// GRUB legacy stage1 stores its compatibility version (3.2) and the LBA of stage2 here.
+const GRUB_LEGACY_VERSION_OFFSET: usize = 0x3e;
+const GRUB_LEGACY_STAGE2_SECTOR_OFFSET: usize = 0x44;
+// LILO's first stage carries its magic right after the initial jump.
+const LILO_MAGIC_OFFSET: usize = 0x06;
+
+/// A boot loader recognized from the boot code in an MBR or a volume boot record.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BootLoader {
+    /// The boot code area is all zeroes.
+    Empty,
+    Grub2 {
+        core_lba: u64,
+    },
+    GrubLegacy {
+        stage2_lba: u32,
+    },
+    Syslinux,
+    Isolinux,
+    WindowsNt6,
+    WindowsNt5,
+    FreeDos,
+    Lilo,
+    Unknown,
+}
+
+impl Display for BootLoader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Empty => f.write_str("none (boot code is zeroed)"),
+            Self::Grub2 { core_lba } => write!(f, "GRUB 2 boot.img (core.img at LBA {})", core_lba),
+            Self::GrubLegacy { stage2_lba } => write!(f, "GRUB legacy stage1 (stage2 at LBA {})", stage2_lba),
+            Self::Syslinux => f.write_str("SYSLINUX"),
+            Self::Isolinux => f.write_str("ISOLINUX"),
+            Self::WindowsNt6 => f.write_str("Windows NT 6+ (Vista or later)"),
+            Self::WindowsNt5 => f.write_str("Windows NT 5 / MS-DOS"),
+            Self::FreeDos => f.write_str("FreeDOS"),
+            Self::Lilo => f.write_str("LILO"),
+            Self::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+/// Identify the boot loader that wrote `code`.
+///
+/// `code_offset` is the offset of `code` within its sector (0 for an MBR, the end of the BPB for a VBR), so that
+/// loader fields at fixed sector offsets can be read.
+pub fn identify_boot_code(code: &[u8], code_offset: usize) -> BootLoader {
+    if code.iter().all(|&b| b == 0) {
+        return BootLoader::Empty;
+    }
+
+    let at = |offset: usize, len: usize| -> Option<&[u8]> {
+        let start = offset.checked_sub(code_offset)?;
+        code.get(start..start + len)
+    };
+    let contains = |needle: &[u8]| code.windows(needle.len()).any(|window| window == needle);
+
+    // Both GRUB generations print the same error strings; stage1 also carries a version number.
+    if contains(b"GRUB \0") && contains(b"Geom\0") {
+        if at(GRUB_LEGACY_VERSION_OFFSET, 2) == Some(&[3, 2]) {
+            if let Some(stage2) = at(GRUB_LEGACY_STAGE2_SECTOR_OFFSET, 4) {
+                return BootLoader::GrubLegacy {
+                    stage2_lba: u32::from_le_bytes(stage2.try_into().unwrap()),
+                };
+            }
+        }
+
+        if let Some(kernel_sector) = at(GRUB2_KERNEL_SECTOR_OFFSET, 8) {
+            return BootLoader::Grub2 {
+                core_lba: u64::from_le_bytes(kernel_sector.try_into().unwrap()),
+            };
+        }
+    }
+
+    if at(LILO_MAGIC_OFFSET, 4) == Some(b"LILO") {
+        return BootLoader::Lilo;
+    }
+
+    if contains(b"ISOLINUX") || contains(b"isolinux.bin") {
+        return BootLoader::Isolinux;
+    }
+
+    // mbr.bin has no name in it, only its error message.
+    if contains(b"SYSLINUX") || contains(b"EXTLINUX") || contains(b"Missing operating system.\r\n") {
+        return BootLoader::Syslinux;
+    }
+
+    if contains(b"FreeDOS") || contains(b"KERNEL  SYS") {
+        return BootLoader::FreeDos;
+    }
+
+    // The NT 6 MBR and VBRs probe for a TPM ("TCPA") and load BOOTMGR; older ones load NTLDR.
+    if contains(b"BOOTMGR") || (contains(b"TCPA") && contains(b"Invalid partition table")) {
+        return BootLoader::WindowsNt6;
+    }
+
+    if contains(b"NTLDR") || contains(b"Invalid partition table") {
+        return BootLoader::WindowsNt5;
+    }
+
+    BootLoader::Unknown
+}
+
+/// The SHA-256 of a boot code area, so that it can be compared against a reference dump of a loader.
+pub fn get_boot_code_sha256(code: &[u8]) -> String {
+    hex::encode(Sha256::digest(code))
+}
+
+/// Where GRUB's core.img was found relative to the partitions on the disk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GrubCoreLocation {
+    /// In the gap between the MBR and the first partition.
+    EmbeddingArea,
+    BiosBootPartition {
+        index: usize,
+    },
+    Partition {
+        index: usize,
+    },
+    Unallocated,
+}
+
+impl Display for GrubCoreLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::EmbeddingArea => f.write_str("the embedding area before the first partition"),
+            Self::BiosBootPartition { index } => write!(f, "BIOS boot partition {}", index),
+            Self::Partition { index } => write!(f, "partition {}", index),
+            Self::Unallocated => f.write_str("unallocated space"),
+        }
+    }
+}
+
+pub fn locate_grub_core(core_lba: u64, partitions: &[Partition]) -> GrubCoreLocation {
+    let bios_boot = PartitionType::Gpt(Uuid::from_u128(BIOS_BOOT_PARTITION_TYPE));
+    let containing = partitions
+        .iter()
+        .filter(|partition| !partition.is_extended())
+        .find(|partition| partition.start_lba <= core_lba && core_lba <= partition.end_lba);
+
+    match containing {
+        Some(partition) if partition.partition_type == bios_boot => {
+            GrubCoreLocation::BiosBootPartition { index: partition.index }
+        }
+        Some(partition) => GrubCoreLocation::Partition { index: partition.index },
+        None if core_lba > 0 && partitions.iter().all(|partition| core_lba < partition.start_lba) => {
+            GrubCoreLocation::EmbeddingArea
+        }
+        None => GrubCoreLocation::Unallocated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::partition::PartitionScheme;
+
+    #[test]
+    fn identify_grub2_and_locate_core() {
+        let mut code = [0u8; 440];
+        code[GRUB2_KERNEL_SECTOR_OFFSET..GRUB2_KERNEL_SECTOR_OFFSET + 8].copy_from_slice(&34u64.to_le_bytes());
+        code[0x180..0x192].copy_from_slice(b"GRUB \0Geom\0Hard Di");
+        assert_eq!(identify_boot_code(&code, 0), BootLoader::Grub2 { core_lba: 34 });
+
+        let partitions = [Partition {
+            index: 1,
+            scheme: PartitionScheme::Gpt,
+            partition_type: PartitionType::Gpt(Uuid::from_u128(BIOS_BOOT_PARTITION_TYPE)),
+            name: None,
+            unique_guid: None,
+            flags: 0,
+            sector_size: 512,
+            start_lba: 34,
+            end_lba: 2047,
+        }];
+        assert_eq!(locate_grub_core(34, &partitions), GrubCoreLocation::BiosBootPartition { index: 1 });
+        assert_eq!(locate_grub_core(1, &partitions), GrubCoreLocation::EmbeddingArea);
+        assert_eq!(locate_grub_core(4096, &partitions), GrubCoreLocation::Unallocated);
+    }
+
+    #[test]
+    fn identify_other_loaders() {
+        assert_eq!(identify_boot_code(&[0u8; 440], 0), BootLoader::Empty);
+
+        let mut code = [0x90u8; 448];
+        code[0x100..0x107].copy_from_slice(b"BOOTMGR");
+        assert_eq!(identify_boot_code(&code, 0x3e), BootLoader::WindowsNt6);
+
+        let mut code = [0x90u8; 440];
+        code[LILO_MAGIC_OFFSET..LILO_MAGIC_OFFSET + 4].copy_from_slice(b"LILO");
+        assert_eq!(identify_boot_code(&code, 0), BootLoader::Lilo);
+        assert_eq!(identify_boot_code(&code[0x3e..], 0x3e), BootLoader::Unknown);
+    }
+}
@@ -0,0 +1,1120 @@
+use log::warn;
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    blockdev::BlockDevice,
+    bootcode::{identify_boot_code, BootLoader},
+    errors::{read_exact_at, ImageError},
+    partition::FIRST_LOGICAL_PARTITION_INDEX,
+    reader::PartitionReader,
+};
+
+pub const BOOT_SECTOR_SIZE: usize = 512;
+pub const BOOT_SECTOR_SIGNATURE: &[u8; 2] = b"\x55\xAA";
+pub const BOOT_SECTOR_SIGNATURE_OFFSET: usize = 510;
+
+pub const BOOT_CODE_SIZE: usize = 440;
+pub const DISK_SIGNATURE_OFFSET: usize = 0x1b8;
+pub const COPY_PROTECT_OFFSET: usize = 0x1bc;
+pub const COPY_PROTECT_MARKER: u16 = 0x5a5a;
+
+pub const CHS_SIZE: usize = 3;
+pub const PARTITION_TABLE_OFFSET: usize = 446;
+pub const PARTITION_TABLE_ENTRIES: usize = 4;
+pub const PARTITION_ENTRY_SIZE: usize = 16;
+
+// Upper bound on the number of EBRs followed in a single extended partition chain.
+pub const MAX_EBR_CHAIN_LENGTH: usize = 256;
+
+#[derive(Debug)]
+pub struct BootSector {
+    pub boot_code: [u8; BOOT_CODE_SIZE],
+    pub disk_signature: u32,
+    pub copy_protect: u16,
+    pub partitions: [PartitionEntry; PARTITION_TABLE_ENTRIES],
+    pub signature: [u8; 2],
+}
+
+impl BootSector {
+    pub fn from_disk_image<R>(reader: &mut R, start_pos: u64) -> Result<Self, ImageError>
+    where
+        R: Read + Seek,
+    {
+        let mut data: [u8; BOOT_SECTOR_SIZE] = [0; BOOT_SECTOR_SIZE];
+        read_exact_at(reader, start_pos, &mut data)?;
+
+        Ok(Self {
+            boot_code: data[..BOOT_CODE_SIZE].try_into().unwrap(),
+            disk_signature: u32::from_le_bytes(
+                data[DISK_SIGNATURE_OFFSET..DISK_SIGNATURE_OFFSET + 4].try_into().unwrap(),
+            ),
+            copy_protect: u16::from_le_bytes(data[COPY_PROTECT_OFFSET..COPY_PROTECT_OFFSET + 2].try_into().unwrap()),
+            partitions: [
+                PartitionEntry::new(&data[PARTITION_TABLE_OFFSET..]),
+                PartitionEntry::new(&data[PARTITION_TABLE_OFFSET + PARTITION_ENTRY_SIZE..]),
+                PartitionEntry::new(&data[PARTITION_TABLE_OFFSET + 2 * PARTITION_ENTRY_SIZE..]),
+                PartitionEntry::new(&data[PARTITION_TABLE_OFFSET + 3 * PARTITION_ENTRY_SIZE..]),
+            ],
+            signature: data[BOOT_SECTOR_SIGNATURE_OFFSET..BOOT_SECTOR_SIGNATURE_OFFSET + 2].try_into().unwrap(),
+        })
+    }
+
+    /// The `PARTUUID` that Linux and Windows use for a partition on this disk: the disk signature and the partition
+    /// number (1-4 for primary partitions, 5 onwards for logical partitions).
+    pub fn get_partuuid(&self, index: usize) -> String {
+        format!("{:08x}-{:02x}", self.disk_signature, index)
+    }
+
+    pub fn is_copy_protected(&self) -> bool {
+        self.copy_protect == COPY_PROTECT_MARKER
+    }
+
+    pub fn has_boot_code(&self) -> bool {
+        self.boot_code.iter().any(|&b| b != 0)
+    }
+
+    pub fn get_boot_loader(&self) -> BootLoader {
+        identify_boot_code(&self.boot_code, 0)
+    }
+}
+
+impl Display for BootSector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Disk signature: 0x{:08x}\nCopy-protect word: 0x{:04x}{}\nBoot code: {}",
+            self.disk_signature,
+            self.copy_protect,
+            if self.is_copy_protected() { " (copy-protected)" } else { "" },
+            self.get_boot_loader(),
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CHSPosition {
+    pub cylinder: u16,
+    pub head: u8,
+    pub sector: u8,
+}
+
+impl CHSPosition {
+    pub fn new(data: &[u8]) -> Self {
+        if data.len() < CHS_SIZE {
+            panic!("CHS data is too small; expected at least {} bytes, got {}", CHS_SIZE, data.len());
+        }
+
+        Self {
+            cylinder: (((data[1] & 0xc0) as u16) << 2) | data[2] as u16,
+            head: data[0],
+            sector: data[1] & 0x3f,
+        }
+    }
+
+    /// Convert to an LBA using the given geometry, or `None` if the sector number is 0 (which is never valid).
+    pub fn to_lba(&self, geometry: &ChsGeometry) -> Option<u64> {
+        if self.sector == 0 {
+            return None;
+        }
+
+        Some(
+            (self.cylinder as u64 * geometry.heads as u64 + self.head as u64) * geometry.sectors_per_track as u64
+                + self.sector as u64
+                - 1,
+        )
+    }
+
+    // Tools write 1023:254:63 or 1023:255:63 for addresses that CHS cannot express.
+    fn is_saturated(&self) -> bool {
+        self.cylinder == 1023
+    }
+}
+
+/// Disk geometry used to translate CHS addresses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChsGeometry {
+    pub heads: u32,
+    pub sectors_per_track: u32,
+}
+
+impl ChsGeometry {
+    /// Infer the geometry from the largest head and sector numbers in the end addresses of the given entries, as fdisk
+    /// does. Partitioning tools end partitions on cylinder boundaries, so these are the last head and sector.
+    pub fn infer<'a, I: IntoIterator<Item = &'a PartitionEntry>>(entries: I) -> Option<Self> {
+        let mut heads = 0;
+        let mut sectors_per_track = 0;
+        for entry in entries.into_iter().filter(|entry| !entry.is_empty()) {
+            heads = heads.max(entry.chs_end.head as u32 + 1);
+            sectors_per_track = sectors_per_track.max(entry.chs_end.sector as u32);
+        }
+
+        if heads == 0 || sectors_per_track == 0 {
+            None
+        } else {
+            Some(Self {
+                heads,
+                sectors_per_track,
+            })
+        }
+    }
+}
+
+impl Display for ChsGeometry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} heads, {} sectors/track", self.heads, self.sectors_per_track)
+    }
+}
+
+impl Display for CHSPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}:{}:{}", self.cylinder, self.head, self.sector)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PartitionStatusFlag {
+    Bootable,
+    Unknown(u8),
+}
+
+impl PartitionStatusFlag {
+    fn from_u8(value: u8) -> Vec<Self> {
+        let mut result = Vec::new();
+        if value & 0x80 != 0 {
+            result.push(Self::Bootable);
+        }
+
+        for i in 0..7 {
+            if value & (1 << i) != 0 {
+                result.push(Self::Unknown(i));
+            }
+        }
+
+        result
+    }
+
+    pub fn to_u8(flags: &[Self]) -> u8 {
+        flags.iter().fold(0, |value, flag| match flag {
+            Self::Bootable => value | 0x80,
+            Self::Unknown(bit) => value | (1 << bit),
+        })
+    }
+}
+
+impl Display for PartitionStatusFlag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Bootable => f.write_str("bootable"),
+            Self::Unknown(value) => write!(f, "unknown(bit {})", value),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MBRPartitionType {
+    pub code: u8,
+    pub name: &'static str,
+    pub is_extended: bool,
+}
+
+impl MBRPartitionType {
+    const fn regular(code: u8, name: &'static str) -> Self {
+        Self {
+            code,
+            name,
+            is_extended: false,
+        }
+    }
+
+    const fn extended(code: u8, name: &'static str) -> Self {
+        Self {
+            code,
+            name,
+            is_extended: true,
+        }
+    }
+}
+
+// See https://en.wikipedia.org/wiki/Partition_type for a list of MBR partition types.
+// A few missing entries are gleaned from util-linux/include/pt-mbr-partnames.h (noted below).
+pub const MBR_PARTITION_TYPES: [MBRPartitionType; 256] = [
+    MBRPartitionType::regular(0x00, "Empty"),
+    MBRPartitionType::regular(0x01, "FAT12"),
+    MBRPartitionType::regular(0x02, "XENIX root"),
+    MBRPartitionType::regular(0x03, "XENIX usr"),
+    MBRPartitionType::regular(0x04, "FAT16 <32 MB"),
+    MBRPartitionType::extended(0x05, "Extended"),
+    MBRPartitionType::regular(0x06, "FAT16B"),
+    MBRPartitionType::regular(0x07, "HPFS; NTFS; exFAT"),
+    MBRPartitionType::regular(0x08, "AIX boot"),
+    MBRPartitionType::regular(0x09, "AIX data"),
+    MBRPartitionType::regular(0x0a, "OS/2 Boot Manager"),
+    MBRPartitionType::regular(0x0b, "FAT32"),
+    MBRPartitionType::regular(0x0c, "FAT32 LBA"),
+    MBRPartitionType::regular(0x0d, "Unknown"),
+    MBRPartitionType::regular(0x0e, "FAT16 LBA"),
+    MBRPartitionType::extended(0x0f, "Extended LBA"),
+    MBRPartitionType::regular(0x10, "OPUS"), // from util-linux
+    MBRPartitionType::regular(0x11, "Hidden FAT12"),
+    MBRPartitionType::regular(0x12, "Compaq diag; EISA config; NCR firmware; IBM rescue"),
+    MBRPartitionType::regular(0x13, "Unknown"),
+    MBRPartitionType::regular(0x14, "OS/2 hidden FAT16"),
+    MBRPartitionType::extended(0x15, "OS/2 hidden extended CHS"),
+    MBRPartitionType::regular(0x16, "OS/2 hidden FAT16B"),
+    MBRPartitionType::regular(0x17, "OS/2 Hidden IFS; HPFS; NTFS; exFAT"),
+    MBRPartitionType::regular(0x18, "AST SmartSleep"),
+    MBRPartitionType::regular(0x19, "Willowtech Photon coS"),
+    MBRPartitionType::regular(0x1a, "Unknown"),
+    MBRPartitionType::regular(0x1b, "OS/2 hidden FAT32"),
+    MBRPartitionType::regular(0x1c, "OS/2 hidden FAT32 LBA"),
+    MBRPartitionType::regular(0x1d, "Unknown"),
+    MBRPartitionType::regular(0x1e, "OS/2 hidden FAT16 LBA"),
+    MBRPartitionType::extended(0x1f, "OS/2 hidden extended LBA"),
+    MBRPartitionType::regular(0x20, "Windows Mobile update"),
+    MBRPartitionType::regular(0x21, "Oxygen FSo2"),
+    MBRPartitionType::regular(0x22, "Oxygen extended"),
+    MBRPartitionType::regular(0x23, "Windows Mobile boot"),
+    MBRPartitionType::regular(0x24, "NEC DOS"),
+    MBRPartitionType::regular(0x25, "Unknown"),
+    MBRPartitionType::regular(0x26, "Unknown"),
+    MBRPartitionType::regular(0x27, "WinRE (hidden NTFS)"),
+    MBRPartitionType::regular(0x28, "Unknown"),
+    MBRPartitionType::regular(0x29, "Unknown"),
+    MBRPartitionType::regular(0x2a, "AtheOS AthFS"),
+    MBRPartitionType::regular(0x2b, "Syllable Secure"),
+    MBRPartitionType::regular(0x2c, "Unknown"),
+    MBRPartitionType::regular(0x2d, "Unknown"),
+    MBRPartitionType::regular(0x2e, "Unknown"),
+    MBRPartitionType::regular(0x2f, "Unknown"),
+    MBRPartitionType::regular(0x30, "Unknown"),
+    MBRPartitionType::regular(0x31, "Unknown"),
+    MBRPartitionType::regular(0x32, "Unknown"),
+    MBRPartitionType::regular(0x33, "Unknown"),
+    MBRPartitionType::regular(0x34, "Unknown"),
+    MBRPartitionType::regular(0x35, "JFS"),
+    MBRPartitionType::regular(0x36, "Unknown"),
+    MBRPartitionType::regular(0x37, "Unknown"),
+    MBRPartitionType::regular(0x38, "THEOSv3.2"),
+    MBRPartitionType::regular(0x39, "Plan 9; THEOSv4 spanned"),
+    MBRPartitionType::regular(0x3a, "THEOSv4 4 GB"),
+    MBRPartitionType::regular(0x3b, "THEOSv4 extended"),
+    MBRPartitionType::regular(0x3c, "PartitionMagic recovery"),
+    MBRPartitionType::regular(0x3d, "PartitionMagic hidden NetWare"),
+    MBRPartitionType::regular(0x3e, "Unknown"),
+    MBRPartitionType::regular(0x3f, "Unknown"),
+    MBRPartitionType::regular(0x40, "Venix 80286; PICK R83"),
+    MBRPartitionType::regular(0x41, "Minix; PPC PReP Boot"),
+    MBRPartitionType::regular(0x42, "Windows dynamic disk (LDM); Secure File System; Old Linux swap"),
+    MBRPartitionType::regular(0x43, "Old Linux native"),
+    MBRPartitionType::regular(0x44, "Norton GoBack"),
+    MBRPartitionType::regular(0x45, "Boot-US boot manager; Priam; EUMEL-ELAN (L2)"),
+    MBRPartitionType::regular(0x46, "EUMEL-ELAN (L2)"),
+    MBRPartitionType::regular(0x47, "EUMEL-ELAN (L2)"),
+    MBRPartitionType::regular(0x48, "EUMEL-ELAN (L2); ERGOS (L3)"),
+    MBRPartitionType::regular(0x49, "Unknown"),
+    MBRPartitionType::regular(0x4a, "AdaOS Aquila; ALFS-THIN"),
+    MBRPartitionType::regular(0x4b, "Unknown"),
+    MBRPartitionType::regular(0x4c, "Oberon Aos (A2)"),
+    MBRPartitionType::regular(0x4d, "QNX4.x primary"),
+    MBRPartitionType::regular(0x4e, "QNX4.x secondary"),
+    MBRPartitionType::regular(0x4f, "QNX4.x tertiary; Oberon boot"),
+    MBRPartitionType::regular(0x50, "OnTrack Disk Manager; Oberon alternative; Lynx"),
+    MBRPartitionType::regular(0x51, "OnTrack Disk Manager aux 1; Novell"),
+    MBRPartitionType::regular(0x52, "CP/M-80; Microport SysV/AT"),
+    MBRPartitionType::regular(0x53, "OnTrack Disk Manager aux 3"),
+    MBRPartitionType::regular(0x54, "OnTrack Disk Manager dynamic driver overlay"),
+    MBRPartitionType::regular(0x55, "EZ-Drive"),
+    MBRPartitionType::regular(0x56, "AT&T DOS; EZ-Drive BIOS; Golden Bow VFeature"),
+    MBRPartitionType::regular(0x57, "DrivePro; Novell VNDI"),
+    MBRPartitionType::regular(0x58, "Unknown"),
+    MBRPartitionType::regular(0x59, "yocOS yocFS"),
+    MBRPartitionType::regular(0x5a, "Unknown"),
+    MBRPartitionType::regular(0x5b, "Unknown"),
+    MBRPartitionType::regular(0x5c, "Priam EDisk"),
+    MBRPartitionType::regular(0x5d, "Unknown"),
+    MBRPartitionType::regular(0x5e, "Unknown"),
+    MBRPartitionType::regular(0x5f, "Unknown"),
+    MBRPartitionType::regular(0x60, "Unknown"),
+    MBRPartitionType::regular(0x61, "SpeedStor hidden FAT12"),
+    MBRPartitionType::regular(0x62, "Unknown"),
+    MBRPartitionType::regular(0x63, "SysV; GNU HURD; SpeedStor hidden read-only FAT12"),
+    MBRPartitionType::regular(0x64, "Novell Netware 286; SpeedStor hidden FAT16; PC-ARMOUR"),
+    MBRPartitionType::regular(0x65, "Novell Netware 386"),
+    MBRPartitionType::regular(0x66, "Novell Netware Storage Management Services; Speedstor hidden read-only FAT16"),
+    MBRPartitionType::regular(0x67, "Novell Netware Wolf Mountain"),
+    MBRPartitionType::regular(0x68, "Novell Netware"),
+    MBRPartitionType::regular(0x69, "Novell Netware 5"),
+    MBRPartitionType::regular(0x6a, "Unknown"),
+    MBRPartitionType::regular(0x6b, "Unknown"),
+    MBRPartitionType::regular(0x6c, "DragonFly BSD slice"),
+    MBRPartitionType::regular(0x6d, "Unknown"),
+    MBRPartitionType::regular(0x6e, "Unknown"),
+    MBRPartitionType::regular(0x6f, "Unknown"),
+    MBRPartitionType::regular(0x70, "DiskSecure multiboot"),
+    MBRPartitionType::regular(0x71, "Unknown"),
+    MBRPartitionType::regular(0x72, "V7/x86; APTI alternative FAT12"),
+    MBRPartitionType::regular(0x73, "Unknown"),
+    MBRPartitionType::regular(0x74, "SpeedStor hidden FAT16B; V7/x86"),
+    MBRPartitionType::regular(0x75, "PC/IX"),
+    MBRPartitionType::regular(0x76, "SpeedStor hidden read-only FAT16B"),
+    MBRPartitionType::regular(0x77, "Novell VNDI/M2FS/M2CS"),
+    MBRPartitionType::regular(0x78, "XOSL bootloader"),
+    MBRPartitionType::regular(0x79, "APTI alternative FAT16"),
+    MBRPartitionType::regular(0x7a, "APTI alternative FAT16 LBA"),
+    MBRPartitionType::regular(0x7b, "APTI alternative FAT16B"),
+    MBRPartitionType::regular(0x7c, "APTI alternative FAT32 LBA"),
+    MBRPartitionType::regular(0x7d, "APTI alternative FAT32"),
+    MBRPartitionType::regular(0x7e, "PrimoCache L2"),
+    MBRPartitionType::regular(0x7f, "User-defined"),
+    MBRPartitionType::regular(0x80, "Minix old"),
+    MBRPartitionType::regular(0x81, "Minix; Linux old"),
+    MBRPartitionType::regular(0x82, "Linux swap; Solaris"),
+    MBRPartitionType::regular(0x83, "Linux"),
+    MBRPartitionType::regular(0x84, "OS/2 hidden; Intel hibernation"),
+    MBRPartitionType::extended(0x85, "Linux extended"),
+    MBRPartitionType::regular(0x86, "FAT16B volume set; Linux RAID"),
+    MBRPartitionType::regular(0x87, "NTFS volume set"),
+    MBRPartitionType::regular(0x88, "Linux plaintext"),
+    MBRPartitionType::regular(0x89, "Unknown"),
+    MBRPartitionType::regular(0x8a, "Unknown"),
+    MBRPartitionType::regular(0x8b, "Unknown"),
+    MBRPartitionType::regular(0x8c, "Unknown"),
+    MBRPartitionType::regular(0x8d, "Unknown"),
+    MBRPartitionType::regular(0x8e, "Linux LVM"),
+    MBRPartitionType::regular(0x8f, "Unknown"),
+    MBRPartitionType::regular(0x90, "FreeDOS hidden FAT16"),
+    MBRPartitionType::extended(0x91, "FreeDOS hidden extended"),
+    MBRPartitionType::regular(0x92, "FreeDOS hidden FAT16B"),
+    MBRPartitionType::regular(0x93, "Amoeba; Linux hidden"),
+    MBRPartitionType::regular(0x94, "Amoeba bad block table"),
+    MBRPartitionType::regular(0x95, "EXOPC native"),
+    MBRPartitionType::regular(0x96, "ISO-9660"),
+    MBRPartitionType::regular(0x97, "FreeDOS FAT32"),
+    MBRPartitionType::regular(0x98, "FreeDOS FAT32 LBA; Service"),
+    MBRPartitionType::regular(0x99, "Unknown"),
+    MBRPartitionType::regular(0x9a, "FreeDOS hidden FAT16B LBA"),
+    MBRPartitionType::extended(0x9b, "FreeDOS hidden extended LBA"),
+    MBRPartitionType::regular(0x9c, "Unknown"),
+    MBRPartitionType::regular(0x9d, "Unknown"),
+    MBRPartitionType::regular(0x9e, "ForthOS"),
+    MBRPartitionType::regular(0x9f, "BSD/OS"),
+    MBRPartitionType::regular(0xa0, "Hibernation"),
+    MBRPartitionType::regular(0xa1, "Hibernation"),
+    MBRPartitionType::regular(0xa2, "Hard Processor System preloader"),
+    MBRPartitionType::regular(0xa3, "HP Volume Expansion"),
+    MBRPartitionType::regular(0xa4, "HP Volume Expansion"),
+    MBRPartitionType::regular(0xa5, "FreeBSD slice"),
+    MBRPartitionType::regular(0xa6, "OpenBSD slice; HP Volume Expansion"),
+    MBRPartitionType::regular(0xa7, "NeXTSTEP"),
+    MBRPartitionType::regular(0xa8, "Darwin UFS"),
+    MBRPartitionType::regular(0xa9, "NetBSD slice"),
+    MBRPartitionType::regular(0xaa, "Olivetti FAT12"),
+    MBRPartitionType::regular(0xab, "Darwin boot"),
+    MBRPartitionType::regular(0xac, "Apple RAID"),
+    MBRPartitionType::regular(0xad, "RiscOS ADFS"),
+    MBRPartitionType::regular(0xae, "ShagOS"),
+    MBRPartitionType::regular(0xaf, "HFS; HFS+"),
+    MBRPartitionType::regular(0xb0, "BootStar dummy"),
+    MBRPartitionType::regular(0xb1, "QNX Neutrino; HP Volume Expansion"),
+    MBRPartitionType::regular(0xb2, "QNX Neutrino"),
+    MBRPartitionType::regular(0xb3, "QNX Neutrino; HP Volume Expansion"),
+    MBRPartitionType::regular(0xb4, "HP Volume Expansion"),
+    MBRPartitionType::regular(0xb5, "Unknown"),
+    MBRPartitionType::regular(0xb6, "FAT16B corrupted primary volume; HP Volume Expansion"),
+    MBRPartitionType::regular(0xb7, "BSDI; HPFS/NTFS corrupted primary volume"),
+    MBRPartitionType::regular(0xb8, "BSDI swap"),
+    MBRPartitionType::regular(0xb9, "Unknown"),
+    MBRPartitionType::regular(0xba, "Unknown"),
+    MBRPartitionType::regular(0xbb, "Boot Wizard hidden; Acronis OEM Secure Zone; FAT32 corrupted primary volume"),
+    MBRPartitionType::regular(
+        0xbc,
+        "Acronis Secure Zone; FAT32 LBA corrupted primary volume set; Paragon Backup Capsule",
+    ),
+    MBRPartitionType::regular(0xbd, "BonnyDOS/286"),
+    MBRPartitionType::regular(0xbe, "Solaris boot"),
+    MBRPartitionType::regular(0xbf, "Solaris"),
+    MBRPartitionType::regular(0xc0, "DR-DOS secured FAT <32 MB"),
+    MBRPartitionType::regular(0xc1, "DR-DOS secured FAT12"),
+    MBRPartitionType::regular(0xc2, "Power Boot hidden Linux"),
+    MBRPartitionType::regular(0xc3, "Power Boot hidden Linux swap"),
+    MBRPartitionType::regular(0xc4, "DR-DOS secured FAT16"),
+    MBRPartitionType::extended(0xc5, "DR-DOS secured extended"),
+    MBRPartitionType::regular(0xc6, "DR-DOS secured FAT16B; FAT16B corrupted secondary volume"),
+    MBRPartitionType::regular(0xc7, "Syrinx boot; HPFS/NTFS corrupted secondary volume"),
+    MBRPartitionType::regular(0xc8, "Unknown"),
+    MBRPartitionType::regular(0xc9, "Unknown"),
+    MBRPartitionType::regular(0xca, "Unknown"),
+    MBRPartitionType::regular(0xcb, "DR-DOS secured FAT32"),
+    MBRPartitionType::regular(0xcc, "DR-DOS secured FAT32 LBA; FAT32 corrupted secondary volume"),
+    MBRPartitionType::regular(0xcd, "CTOS memory dump"),
+    MBRPartitionType::regular(0xce, "DR-DOS secured FAT16B LBA"),
+    MBRPartitionType::regular(0xcf, "DR-DOS secured extended LBA"),
+    MBRPartitionType::regular(0xd0, "Multiuser DOS secured FAT >32 MB"),
+    MBRPartitionType::regular(0xd1, "Multiuser DOS secured FAT12"),
+    MBRPartitionType::regular(0xd2, "Unknown"),
+    MBRPartitionType::regular(0xd3, "Unknown"),
+    MBRPartitionType::regular(0xd4, "Multiuser DOS secured FAT16"),
+    MBRPartitionType::extended(0xd5, "Multiuser DOS secured extended CHS"),
+    MBRPartitionType::regular(0xd6, "Multiuser DOS secured FAT16B"),
+    MBRPartitionType::regular(0xd7, "Unknown"),
+    MBRPartitionType::regular(0xd8, "CP/M-86"),
+    MBRPartitionType::regular(0xd9, "Unknown"),
+    MBRPartitionType::regular(0xda, "Non-filesystem data"),
+    MBRPartitionType::regular(0xdb, "CP/M-86"),
+    MBRPartitionType::regular(0xdc, "Unknown"),
+    MBRPartitionType::regular(0xdd, "CTOS hidden memory dump"),
+    MBRPartitionType::regular(0xde, "Dell FAT16 diagnostic/utility"),
+    MBRPartitionType::regular(0xdf, "BootIt EMBRM; DG/UX"),
+    MBRPartitionType::regular(0xe0, "ST AVFS"),
+    MBRPartitionType::regular(0xe1, "SpeedStor FAT12 <16 MB"),
+    MBRPartitionType::regular(0xe2, "Unknown"),
+    MBRPartitionType::regular(0xe3, "SpeedStor read-only FAT12 <16 MB"),
+    MBRPartitionType::regular(0xe4, "SpeedStor FAT16 <32 MB"),
+    MBRPartitionType::regular(0xe5, "Tandy MS/DOS"),
+    MBRPartitionType::regular(0xe6, "SpeedStor read-only FAT16 <32 MB"),
+    MBRPartitionType::regular(0xe7, "Unknown"),
+    MBRPartitionType::regular(0xe8, "Linux Unified Key Setup (LUKS)"),
+    MBRPartitionType::regular(0xe9, "Unknown"),
+    MBRPartitionType::extended(0xea, "Linux extended boot"), // util-linux
+    MBRPartitionType::regular(0xeb, "BeOS/Haiku BFS"),
+    MBRPartitionType::regular(0xec, "SkyOS SkyFS"),
+    MBRPartitionType::regular(0xed, "Sprytix EDC loader"),
+    MBRPartitionType::regular(0xee, "GPT"),
+    MBRPartitionType::regular(0xef, "EFI system"),
+    MBRPartitionType::regular(0xf0, "Linux/PA-RISC boot"),
+    MBRPartitionType::regular(0xf1, "SpeedStor"), // util-linux
+    MBRPartitionType::regular(0xf2, "Unisys FAT12/FAT16 secondary"),
+    MBRPartitionType::regular(0xf3, "Unknown"),
+    MBRPartitionType::regular(0xf4, "SpeedStor FAT16; Prologue single-volume NGF/TwinFS"),
+    MBRPartitionType::regular(0xf5, "Prologue multi-volume NGF/TwinFS"),
+    MBRPartitionType::regular(0xf6, "SpeedStor read-only FAT16B"),
+    MBRPartitionType::regular(0xf7, "OSG EFAT; X1 solid state"),
+    MBRPartitionType::regular(0xf8, "Arm EBBR system firmware"),
+    MBRPartitionType::regular(0xf9, "Linux pCache EXT2/EXT3"),
+    MBRPartitionType::regular(0xfa, "Unknown"),
+    MBRPartitionType::regular(0xfb, "VMware VMFS"),
+    MBRPartitionType::regular(0xfc, "VMware swap/VMKCORE"),
+    MBRPartitionType::regular(0xfd, "Linux raid autodetect"),
+    MBRPartitionType::regular(0xfe, "PS/2 recovery; Old Linux LVM"),
+    MBRPartitionType::regular(0xff, "Xenix bad block table"),
+];
+
+impl Display for MBRPartitionType {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "0x{:02x} ({})", self.code, self.name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PartitionEntry {
+    pub status: Vec<PartitionStatusFlag>,
+    pub chs_start: CHSPosition,
+    pub partition_type: &'static MBRPartitionType,
+    pub chs_end: CHSPosition,
+    pub lba_start: u32,
+    pub sector_count: u32,
+}
+
+impl PartitionEntry {
+    pub fn new(data: &[u8]) -> Self {
+        if data.len() < PARTITION_ENTRY_SIZE {
+            panic!(
+                "Partition entry data is too small; expected at least {} bytes, got {}",
+                PARTITION_ENTRY_SIZE,
+                data.len()
+            );
+        }
+
+        Self {
+            status: PartitionStatusFlag::from_u8(data[0]),
+            chs_start: CHSPosition::new(&data[1..4]),
+            partition_type: &MBR_PARTITION_TYPES[data[4] as usize],
+            chs_end: CHSPosition::new(&data[5..8]),
+            lba_start: u32::from_le_bytes(data[8..12].try_into().unwrap()),
+            sector_count: u32::from_le_bytes(data[12..16].try_into().unwrap()),
+        }
+    }
+
+    /// Read the EBR this extended entry points to.
+    ///
+    /// Per the EBR spec, links found in the MBR are absolute (`extended_base_pos` is 0), while links found in an EBR
+    /// are relative to the start of the primary extended partition (`extended_base_pos` is the first EBR's position).
+    pub fn get_extended_boot_sector<R>(
+        &self,
+        device: &mut BlockDevice<R>,
+        extended_base_pos: u64,
+    ) -> Result<(BootSector, u64), ImageError>
+    where
+        R: Read + Seek,
+    {
+        if !self.partition_type.is_extended {
+            let mut extended_types = Vec::new();
+            MBR_PARTITION_TYPES.iter().for_each(|t| {
+                if t.is_extended {
+                    extended_types.push(format!("{:02x}", t.code));
+                }
+            });
+            return Err(ImageError::InvalidPartitionType {
+                expected: extended_types.join("/"),
+                actual: format!("{:02x}", self.partition_type.code),
+            });
+        }
+
+        if self.lba_start == 0 {
+            return Err(ImageError::InvalidPartitionEntry("Cannot handle CHS extended partitions".into()));
+        }
+
+        let start_pos = extended_base_pos + self.get_start_offset(device)?;
+        Ok((BootSector::from_disk_image(device, start_pos)?, start_pos))
+    }
+
+    pub fn get_start_offset<R: Read + Seek>(&self, device: &BlockDevice<R>) -> Result<u64, ImageError> {
+        device.lba_to_offset(self.lba_start as u64)
+    }
+
+    pub fn get_size<R: Read + Seek>(&self, device: &BlockDevice<R>) -> Result<u64, ImageError> {
+        device.lba_to_offset(self.sector_count as u64)
+    }
+
+    pub fn get_partition_reader<'a, R: Read + Seek>(
+        &self,
+        device: &'a mut BlockDevice<R>,
+        my_boot_sector_start_pos: u64,
+    ) -> Result<PartitionReader<&'a mut BlockDevice<R>>, ImageError> {
+        let start = my_boot_sector_start_pos + self.get_start_offset(device)?;
+        let length = self.get_size(device)?;
+        Ok(PartitionReader::new(device, start, length))
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.partition_type.is_extended
+    }
+
+    pub fn is_bootable(&self) -> bool {
+        self.status.iter().any(|flag| matches!(flag, PartitionStatusFlag::Bootable))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partition_type.code == 0 && self.lba_start == 0 && self.sector_count == 0
+    }
+}
+
+impl Display for PartitionEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let status_str = self.status.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(" ");
+        write!(
+            f,
+            "Partition Type: {}\nStatus: {}\nStart CHS: {}\nEnd CHS: {}\n\
+            LBA Start: {} (0x{:x})\nSector Count: {} (0x{:x})",
+            self.partition_type,
+            status_str,
+            self.chs_start,
+            self.chs_end,
+            self.lba_start,
+            self.lba_start,
+            self.sector_count,
+            self.sector_count
+        )
+    }
+}
+
+/// A logical partition found in an EBR.
+#[derive(Clone, Debug)]
+pub struct LogicalPartition {
+    // Absolute LBA of the EBR that describes this partition; the entry's `lba_start` is relative to it.
+    pub ebr_lba: u64,
+    pub entry: PartitionEntry,
+}
+
+impl LogicalPartition {
+    pub fn get_start_lba(&self) -> u64 {
+        self.ebr_lba + self.entry.lba_start as u64
+    }
+
+    pub fn get_partition_reader<'a, R: Read + Seek>(
+        &self,
+        device: &'a mut BlockDevice<R>,
+    ) -> Result<PartitionReader<&'a mut BlockDevice<R>>, ImageError> {
+        let ebr_pos = device.lba_to_offset(self.ebr_lba)?;
+        self.entry.get_partition_reader(device, ebr_pos)
+    }
+}
+
+/// A problem found while walking an extended partition chain.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EbrChainWarning {
+    Loop { ebr_lba: u64 },
+    TooLong { limit: usize },
+    ReadFailed { ebr_lba: u64, error: String },
+    InvalidSignature { ebr_lba: u64, signature: [u8; 2] },
+    MultipleLinks { ebr_lba: u64 },
+    UnexpectedEntry { ebr_lba: u64, slot: usize },
+    LinkOutsideExtended { ebr_lba: u64, next_ebr_lba: u64 },
+    LogicalOutsideExtended { ebr_lba: u64, start_lba: u64, end_lba: u64 },
+}
+
+impl Display for EbrChainWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Loop { ebr_lba } => write!(f, "EBR chain loops back to LBA {}", ebr_lba),
+            Self::TooLong { limit } => write!(f, "EBR chain is longer than {} entries; stopped following it", limit),
+            Self::ReadFailed { ebr_lba, error } => write!(f, "Unable to read EBR at LBA {}: {}", ebr_lba, error),
+            Self::InvalidSignature { ebr_lba, signature } => {
+                write!(f, "EBR at LBA {} has an invalid signature: {}", ebr_lba, hex::encode(signature))
+            }
+            Self::MultipleLinks { ebr_lba } => {
+                write!(f, "EBR at LBA {} has more than one next-EBR link; using the first", ebr_lba)
+            }
+            Self::UnexpectedEntry { ebr_lba, slot } => {
+                write!(f, "EBR at LBA {} has a non-empty entry in slot {}", ebr_lba, slot + 1)
+            }
+            Self::LinkOutsideExtended { ebr_lba, next_ebr_lba } => write!(
+                f,
+                "EBR at LBA {} links to LBA {}, which is outside the extended partition",
+                ebr_lba, next_ebr_lba
+            ),
+            Self::LogicalOutsideExtended {
+                ebr_lba,
+                start_lba,
+                end_lba,
+            } => write!(
+                f,
+                "Logical partition in EBR at LBA {} (LBA {}-{}) extends outside the extended partition",
+                ebr_lba, start_lba, end_lba
+            ),
+        }
+    }
+}
+
+/// The logical partitions in a primary extended partition, found by following its chain of EBRs.
+#[derive(Clone, Debug)]
+pub struct EbrChain {
+    pub extended_lba: u64,
+    pub extended_sector_count: u64,
+    pub logical_partitions: Vec<LogicalPartition>,
+    pub warnings: Vec<EbrChainWarning>,
+}
+
+impl EbrChain {
+    /// Walk the EBR chain of a primary extended partition entry taken from the MBR.
+    ///
+    /// Malformed chains are not errors; the walk stops and the problem is recorded in `warnings`.
+    pub fn read<R: Read + Seek>(device: &mut BlockDevice<R>, extended: &PartitionEntry) -> Result<Self, ImageError> {
+        let extended_lba = extended.lba_start as u64;
+        let extended_end_lba = extended_lba + extended.sector_count as u64;
+        let mut chain = Self {
+            extended_lba,
+            extended_sector_count: extended.sector_count as u64,
+            logical_partitions: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        let (mut ebr, extended_base_pos) = match extended.get_extended_boot_sector(device, 0) {
+            Ok(result) => result,
+            Err(e @ ImageError::Truncated { .. }) => {
+                chain.warn(EbrChainWarning::ReadFailed {
+                    ebr_lba: extended_lba,
+                    error: e.to_string(),
+                });
+                return Ok(chain);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut visited = HashSet::new();
+        let mut ebr_lba = extended_lba;
+
+        loop {
+            if visited.len() >= MAX_EBR_CHAIN_LENGTH {
+                chain.warn(EbrChainWarning::TooLong {
+                    limit: MAX_EBR_CHAIN_LENGTH,
+                });
+                break;
+            }
+
+            visited.insert(ebr_lba);
+
+            if &ebr.signature != BOOT_SECTOR_SIGNATURE {
+                chain.warn(EbrChainWarning::InvalidSignature {
+                    ebr_lba,
+                    signature: ebr.signature,
+                });
+                break;
+            }
+
+            let mut next_link = None;
+            for (slot, entry) in ebr.partitions.iter().enumerate() {
+                if entry.is_empty() {
+                    continue;
+                }
+
+                if slot >= 2 {
+                    chain.warn(EbrChainWarning::UnexpectedEntry { ebr_lba, slot });
+                }
+
+                if entry.is_extended() {
+                    if next_link.is_some() {
+                        chain.warn(EbrChainWarning::MultipleLinks { ebr_lba });
+                    } else {
+                        next_link = Some(entry.clone());
+                    }
+                } else {
+                    let logical = LogicalPartition {
+                        ebr_lba,
+                        entry: entry.clone(),
+                    };
+                    let start_lba = logical.get_start_lba();
+                    let end_lba = start_lba + (entry.sector_count as u64).max(1) - 1;
+                    if start_lba < extended_lba || end_lba >= extended_end_lba {
+                        chain.warn(EbrChainWarning::LogicalOutsideExtended {
+                            ebr_lba,
+                            start_lba,
+                            end_lba,
+                        });
+                    }
+                    chain.logical_partitions.push(logical);
+                }
+            }
+
+            let next_link = match next_link {
+                Some(next_link) => next_link,
+                None => break,
+            };
+
+            let next_ebr_lba = extended_lba + next_link.lba_start as u64;
+            if next_ebr_lba >= extended_end_lba {
+                chain.warn(EbrChainWarning::LinkOutsideExtended { ebr_lba, next_ebr_lba });
+                break;
+            }
+
+            if visited.contains(&next_ebr_lba) {
+                chain.warn(EbrChainWarning::Loop { ebr_lba: next_ebr_lba });
+                break;
+            }
+
+            ebr = match next_link.get_extended_boot_sector(device, extended_base_pos) {
+                Ok((next_ebr, _)) => next_ebr,
+                Err(e @ ImageError::Truncated { .. }) => {
+                    chain.warn(EbrChainWarning::ReadFailed {
+                        ebr_lba: next_ebr_lba,
+                        error: e.to_string(),
+                    });
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            ebr_lba = next_ebr_lba;
+        }
+
+        Ok(chain)
+    }
+
+    fn warn(&mut self, warning: EbrChainWarning) {
+        warn!("{}", warning);
+        self.warnings.push(warning);
+    }
+}
+
+/// A problem found in an MBR partition table and its logical partitions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MbrWarning {
+    ChsStartMismatch {
+        index: usize,
+        chs_lba: u64,
+        lba: u64,
+    },
+    ChsEndMismatch {
+        index: usize,
+        chs_lba: u64,
+        lba: u64,
+    },
+    Overlap {
+        index: usize,
+        other_index: usize,
+    },
+    PastEndOfImage {
+        index: usize,
+        end_lba: u64,
+        sector_count: u64,
+    },
+    MultipleBootable {
+        indices: Vec<usize>,
+    },
+}
+
+impl Display for MbrWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::ChsStartMismatch { index, chs_lba, lba } => write!(
+                f,
+                "Partition {} starts at CHS address LBA {} but at LBA {} in the LBA field",
+                index, chs_lba, lba
+            ),
+            Self::ChsEndMismatch { index, chs_lba, lba } => write!(
+                f,
+                "Partition {} ends at CHS address LBA {} but at LBA {} according to the LBA fields",
+                index, chs_lba, lba
+            ),
+            Self::Overlap { index, other_index } => write!(f, "Partitions {} and {} overlap", other_index, index),
+            Self::PastEndOfImage {
+                index,
+                end_lba,
+                sector_count,
+            } => write!(
+                f,
+                "Partition {} ends at LBA {}, past the end of the image ({} sectors)",
+                index, end_lba, sector_count
+            ),
+            Self::MultipleBootable { indices } => {
+                let indices: Vec<String> = indices.iter().map(|index| index.to_string()).collect();
+                write!(f, "More than one partition is marked bootable ({})", indices.join(", "))
+            }
+        }
+    }
+}
+
+/// Consistency checks for an MBR partition table, including the logical partitions in its extended partitions.
+///
+/// Partitions are numbered as in [crate::Partition]: 1-4 for primary partitions, and from 5 for logical partitions.
+#[derive(Clone, Debug)]
+pub struct MbrCheck {
+    pub geometry: Option<ChsGeometry>,
+    pub warnings: Vec<MbrWarning>,
+}
+
+impl MbrCheck {
+    /// Check the primary partitions of `boot_sector` and the logical partitions already read from its EBR chains,
+    /// in chain order.
+    pub fn check<R: Read + Seek>(
+        device: &mut BlockDevice<R>,
+        boot_sector: &BootSector,
+        logical_partitions: &[LogicalPartition],
+    ) -> Result<Self, ImageError> {
+        // (index, absolute start LBA, entry, is logical)
+        let mut partitions = Vec::new();
+        for (slot, entry) in boot_sector.partitions.iter().enumerate() {
+            if !entry.is_empty() {
+                partitions.push((slot + 1, entry.lba_start as u64, entry.clone(), false));
+            }
+        }
+
+        for (i, logical) in logical_partitions.iter().enumerate() {
+            partitions.push((FIRST_LOGICAL_PARTITION_INDEX + i, logical.get_start_lba(), logical.entry.clone(), true));
+        }
+
+        let mut check = Self {
+            geometry: ChsGeometry::infer(partitions.iter().map(|(_, _, entry, _)| entry)),
+            warnings: Vec::new(),
+        };
+        let image_sectors = device.sector_count()?;
+
+        for (position, (index, start_lba, entry, is_logical)) in partitions.iter().enumerate() {
+            let end_lba = start_lba + (entry.sector_count as u64).max(1) - 1;
+
+            if let Some(geometry) = check.geometry {
+                // CHS addresses are absolute, even in EBRs.
+                let chs_limit = 1023 * geometry.heads as u64 * geometry.sectors_per_track as u64;
+                for (chs, lba, is_start) in [(&entry.chs_start, *start_lba, true), (&entry.chs_end, end_lba, false)] {
+                    let chs_lba = match chs.to_lba(&geometry) {
+                        Some(chs_lba) => chs_lba,
+                        None => continue,
+                    };
+                    if chs_lba == lba || (chs.is_saturated() && lba >= chs_limit) {
+                        continue;
+                    }
+
+                    let index = *index;
+                    check.warn(if is_start {
+                        MbrWarning::ChsStartMismatch { index, chs_lba, lba }
+                    } else {
+                        MbrWarning::ChsEndMismatch { index, chs_lba, lba }
+                    });
+                }
+            }
+
+            if end_lba >= image_sectors {
+                check.warn(MbrWarning::PastEndOfImage {
+                    index: *index,
+                    end_lba,
+                    sector_count: image_sectors,
+                });
+            }
+
+            for (other_index, other_start_lba, other, other_is_logical) in partitions[..position].iter() {
+                // Logical partitions are expected to lie inside the extended partitions.
+                if (*is_logical && other.is_extended()) || (*other_is_logical && entry.is_extended()) {
+                    continue;
+                }
+
+                let other_end_lba = other_start_lba + (other.sector_count as u64).max(1) - 1;
+                if *start_lba <= other_end_lba && end_lba >= *other_start_lba {
+                    check.warn(MbrWarning::Overlap {
+                        index: *index,
+                        other_index: *other_index,
+                    });
+                }
+            }
+        }
+
+        let bootable: Vec<usize> = boot_sector
+            .partitions
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_bootable())
+            .map(|(slot, _)| slot + 1)
+            .collect();
+        if bootable.len() > 1 {
+            check.warn(MbrWarning::MultipleBootable { indices: bootable });
+        }
+
+        Ok(check)
+    }
+
+    fn warn(&mut self, warning: MbrWarning) {
+        warn!("{}", warning);
+        self.warnings.push(warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn check_mbr_partition_entries() {
+        for i in 0..=255 {
+            assert_eq!(MBR_PARTITION_TYPES[i as usize].code, i);
+        }
+    }
+
+    fn write_entry(image: &mut [u8], sector_lba: u64, slot: usize, partition_type: u8, lba_start: u32, count: u32) {
+        let pos = sector_lba as usize * 512 + PARTITION_TABLE_OFFSET + slot * PARTITION_ENTRY_SIZE;
+        image[pos + 4] = partition_type;
+        image[pos + 8..pos + 12].copy_from_slice(&lba_start.to_le_bytes());
+        image[pos + 12..pos + 16].copy_from_slice(&count.to_le_bytes());
+        let sig_pos = sector_lba as usize * 512 + BOOT_SECTOR_SIGNATURE_OFFSET;
+        image[sig_pos..sig_pos + 2].copy_from_slice(BOOT_SECTOR_SIGNATURE);
+    }
+
+    fn read_chain(image: Vec<u8>) -> EbrChain {
+        let mut device = BlockDevice::new(Cursor::new(image));
+        let mbr = BootSector::from_disk_image(&mut device, 0).unwrap();
+        EbrChain::read(&mut device, &mbr.partitions[0]).unwrap()
+    }
+
+    #[test]
+    fn ebr_links_are_relative_to_extended_base() {
+        let mut image = vec![0u8; 2048 * 512];
+        write_entry(&mut image, 0, 0, 0x05, 1000, 1000);
+        for (i, ebr_offset) in [0u32, 200, 400].iter().enumerate() {
+            let ebr_lba = 1000 + *ebr_offset as u64;
+            write_entry(&mut image, ebr_lba, 0, 0x83, 10, 100);
+            if i < 2 {
+                write_entry(&mut image, ebr_lba, 1, 0x05, ebr_offset + 200, 200);
+            }
+        }
+
+        let chain = read_chain(image);
+        assert!(chain.warnings.is_empty(), "{:?}", chain.warnings);
+        let starts: Vec<u64> = chain.logical_partitions.iter().map(|lp| lp.get_start_lba()).collect();
+        assert_eq!(starts, vec![1010, 1210, 1410]);
+    }
+
+    #[test]
+    fn ebr_loop_is_detected() {
+        let mut image = vec![0u8; 2048 * 512];
+        write_entry(&mut image, 0, 0, 0x05, 1000, 1000);
+        write_entry(&mut image, 1000, 0, 0x83, 10, 100);
+        write_entry(&mut image, 1000, 1, 0x05, 200, 200);
+        write_entry(&mut image, 1200, 0, 0x83, 10, 100);
+        write_entry(&mut image, 1200, 1, 0x05, 0, 200);
+
+        let chain = read_chain(image);
+        assert_eq!(chain.logical_partitions.len(), 2);
+        assert_eq!(chain.warnings, vec![EbrChainWarning::Loop { ebr_lba: 1000 }]);
+    }
+
+    fn write_chs(image: &mut [u8], slot: usize, status: u8, start: (u16, u8, u8), end: (u16, u8, u8)) {
+        let pos = PARTITION_TABLE_OFFSET + slot * PARTITION_ENTRY_SIZE;
+        image[pos] = status;
+        for (offset, (cylinder, head, sector)) in [(1, start), (5, end)] {
+            image[pos + offset] = head;
+            image[pos + offset + 1] = sector | ((cylinder >> 2) as u8 & 0xc0);
+            image[pos + offset + 2] = cylinder as u8;
+        }
+    }
+
+    #[test]
+    fn mbr_consistency_checks() {
+        // 16 heads, 63 sectors per track: cylinder c starts at LBA c * 1008.
+        let mut image = vec![0u8; 3024 * 512];
+        write_entry(&mut image, 0, 0, 0x83, 63, 945);
+        write_chs(&mut image, 0, 0x80, (0, 1, 1), (0, 15, 63));
+        write_entry(&mut image, 0, 1, 0x83, 1008, 1008);
+        write_chs(&mut image, 1, 0x80, (1, 0, 2), (1, 15, 63));
+        write_entry(&mut image, 0, 2, 0x83, 2000, 2000);
+        write_chs(&mut image, 2, 0x00, (1, 15, 63), (3, 15, 63));
+
+        let mut device = BlockDevice::new(Cursor::new(image));
+        let mbr = BootSector::from_disk_image(&mut device, 0).unwrap();
+        let check = MbrCheck::check(&mut device, &mbr, &[]).unwrap();
+        assert_eq!(
+            check.geometry,
+            Some(ChsGeometry {
+                heads: 16,
+                sectors_per_track: 63
+            })
+        );
+        assert_eq!(
+            check.warnings,
+            vec![
+                MbrWarning::ChsStartMismatch {
+                    index: 2,
+                    chs_lba: 1009,
+                    lba: 1008
+                },
+                MbrWarning::ChsStartMismatch {
+                    index: 3,
+                    chs_lba: 2015,
+                    lba: 2000
+                },
+                MbrWarning::ChsEndMismatch {
+                    index: 3,
+                    chs_lba: 4031,
+                    lba: 3999
+                },
+                MbrWarning::PastEndOfImage {
+                    index: 3,
+                    end_lba: 3999,
+                    sector_count: 3024
+                },
+                MbrWarning::Overlap {
+                    index: 3,
+                    other_index: 2
+                },
+                MbrWarning::MultipleBootable { indices: vec![1, 2] },
+            ]
+        );
+    }
+
+    #[test]
+    fn disk_signature_and_boot_code() {
+        let mut image = vec![0u8; 512];
+        image[0] = 0xeb;
+        image[DISK_SIGNATURE_OFFSET..DISK_SIGNATURE_OFFSET + 4].copy_from_slice(&0x1234abcdu32.to_le_bytes());
+        image[COPY_PROTECT_OFFSET..COPY_PROTECT_OFFSET + 2].copy_from_slice(&COPY_PROTECT_MARKER.to_le_bytes());
+
+        let mbr = BootSector::from_disk_image(&mut Cursor::new(image), 0).unwrap();
+        assert_eq!(mbr.disk_signature, 0x1234abcd);
+        assert_eq!(mbr.get_partuuid(5), "1234abcd-05");
+        assert!(mbr.is_copy_protected());
+        assert!(mbr.has_boot_code());
+    }
+}
@@ -0,0 +1,373 @@
+use log::warn;
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::ImageError,
+    partition::{Partition, PartitionScheme, PartitionType},
+    probe::{decode_c_string, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const BSD_DISKLABEL_MAGIC: u32 = 0x8256_4557;
+
+// The label lives in sector 1 of the slice, in the same sector size as its partition offsets.
+pub const BSD_DISKLABEL_SECTOR: u64 = 1;
+pub const BSD_DISKLABEL_HEADER_SIZE: usize = 148;
+pub const BSD_PARTITION_ENTRY_SIZE: usize = 16;
+pub const BSD_MAX_PARTITIONS: usize = 16;
+pub const BSD_DISKLABEL_SIZE: usize = BSD_DISKLABEL_HEADER_SIZE + BSD_MAX_PARTITIONS * BSD_PARTITION_ENTRY_SIZE;
+
+// Partition `c` conventionally covers the whole slice (or, on OpenBSD and NetBSD, the whole disk).
+pub const BSD_RAW_PARTITION: usize = 2;
+
+pub const BSD_FS_UNUSED: u8 = 0;
+
+// MBR slice types that carry a BSD disklabel: FreeBSD, OpenBSD, NetBSD.
+pub const MBR_BSD_SLICE_TYPES: [u8; 3] = [0xa5, 0xa6, 0xa9];
+
+// From FreeBSD sys/sys/disklabel.h (fstypenames).
+pub const BSD_FS_TYPES: [&str; 28] = [
+    "unused",
+    "swap",
+    "Version 6",
+    "Version 7",
+    "System V",
+    "4.1BSD",
+    "Eighth Edition",
+    "4.2BSD",
+    "MSDOS",
+    "4.4LFS",
+    "unknown",
+    "HPFS",
+    "ISO9660",
+    "boot",
+    "vinum",
+    "raid",
+    "Filecore",
+    "EXT2FS",
+    "NTFS",
+    "?",
+    "ccd",
+    "jfs",
+    "HAMMER",
+    "HAMMER2",
+    "UDF",
+    "?",
+    "EFS",
+    "ZFS",
+];
+
+/// The name of a BSD disklabel filesystem type.
+pub fn get_bsd_fstype_name(fstype: u8) -> &'static str {
+    BSD_FS_TYPES.get(fstype as usize).copied().unwrap_or("Unknown")
+}
+
+/// Whether an MBR partition type is a BSD slice that should contain a disklabel.
+pub fn is_bsd_slice_type(code: u8) -> bool {
+    MBR_BSD_SLICE_TYPES.contains(&code)
+}
+
+/// A lettered partition in a BSD disklabel.
+#[derive(Clone, Debug)]
+pub struct BsdPartition {
+    pub letter: char,
+    pub size: u32,
+    pub offset: u32,
+    pub fragment_size: u32,
+    pub fstype: u8,
+    pub fragments_per_block: u8,
+    pub cylinders_per_group: u16,
+}
+
+impl BsdPartition {
+    pub fn is_used(&self) -> bool {
+        self.fstype != BSD_FS_UNUSED || self.size != 0
+    }
+}
+
+impl Display for BsdPartition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{}: fstype {} ({}), offset {}, size {}",
+            self.letter,
+            self.fstype,
+            get_bsd_fstype_name(self.fstype),
+            self.offset,
+            self.size
+        )
+    }
+}
+
+/// A BSD disklabel (`struct disklabel`) found inside a FreeBSD, OpenBSD or NetBSD MBR slice.
+#[derive(Clone, Debug)]
+pub struct BsdDisklabel {
+    pub drive_type: u16,
+    pub drive_subtype: u16,
+    pub type_name: String,
+    pub pack_name: String,
+    pub sector_size: u32,
+    pub sectors_per_track: u32,
+    pub tracks_per_cylinder: u32,
+    pub cylinder_count: u32,
+    pub sectors_per_cylinder: u32,
+    pub sectors_per_unit: u32,
+    pub rpm: u16,
+    pub flags: u32,
+    pub checksum: u16,
+    /// Checksum computed over the label as read, with the `checksum` field zeroed.
+    pub computed_checksum: u16,
+    pub boot_area_size: u32,
+    pub superblock_size: u32,
+    pub partitions: Vec<BsdPartition>,
+}
+
+impl BsdDisklabel {
+    pub fn new(data: &[u8]) -> Result<Self, ImageError> {
+        if data.len() < BSD_DISKLABEL_HEADER_SIZE {
+            return Err(ImageError::Truncated {
+                offset: 0,
+                expected: BSD_DISKLABEL_HEADER_SIZE,
+                actual: data.len(),
+            });
+        }
+
+        let le16 = |offset: usize| u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap());
+        let le32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        if le32(0) != BSD_DISKLABEL_MAGIC || le32(132) != BSD_DISKLABEL_MAGIC {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "BSD disklabel magic is 0x{:08x}/0x{:08x}, not 0x{:08x}",
+                le32(0),
+                le32(132),
+                BSD_DISKLABEL_MAGIC
+            )));
+        }
+
+        let partition_count = le16(138) as usize;
+        if partition_count > BSD_MAX_PARTITIONS {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "BSD disklabel has {} partitions; at most {} are supported",
+                partition_count, BSD_MAX_PARTITIONS
+            )));
+        }
+
+        let label_size = BSD_DISKLABEL_HEADER_SIZE + partition_count * BSD_PARTITION_ENTRY_SIZE;
+        if data.len() < label_size {
+            return Err(ImageError::Truncated {
+                offset: 0,
+                expected: label_size,
+                actual: data.len(),
+            });
+        }
+
+        // The checksum is the XOR of every 16-bit word in the label, including the partitions in use.
+        let computed_checksum = data[..label_size]
+            .chunks_exact(2)
+            .enumerate()
+            .filter(|(i, _)| *i != 136 / 2)
+            .fold(0, |acc, (_, word)| acc ^ u16::from_le_bytes([word[0], word[1]]));
+
+        let partitions = data[BSD_DISKLABEL_HEADER_SIZE..label_size]
+            .chunks_exact(BSD_PARTITION_ENTRY_SIZE)
+            .enumerate()
+            .map(|(i, entry)| BsdPartition {
+                letter: (b'a' + i as u8) as char,
+                size: u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                offset: u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+                fragment_size: u32::from_le_bytes(entry[8..12].try_into().unwrap()),
+                fstype: entry[12],
+                fragments_per_block: entry[13],
+                cylinders_per_group: u16::from_le_bytes(entry[14..16].try_into().unwrap()),
+            })
+            .collect();
+
+        Ok(Self {
+            drive_type: le16(4),
+            drive_subtype: le16(6),
+            type_name: decode_c_string(&data[8..24]),
+            pack_name: decode_c_string(&data[24..40]),
+            sector_size: le32(40),
+            sectors_per_track: le32(44),
+            tracks_per_cylinder: le32(48),
+            cylinder_count: le32(52),
+            sectors_per_cylinder: le32(56),
+            sectors_per_unit: le32(60),
+            rpm: le16(72),
+            flags: le32(88),
+            checksum: le16(136),
+            computed_checksum,
+            boot_area_size: le32(140),
+            superblock_size: le32(144),
+            partitions,
+        })
+    }
+
+    /// Read the disklabel from sector 1 of a slice, or return `None` if there is no consistent label there.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R, sector_size: u32) -> Result<Option<Self>, ImageError> {
+        let offset = BSD_DISKLABEL_SECTOR * sector_size as u64;
+        match read_probe_bytes(reader, offset, BSD_DISKLABEL_SIZE)? {
+            Some(data) if u32::from_le_bytes(data[0..4].try_into().unwrap()) == BSD_DISKLABEL_MAGIC => {
+                match Self::new(&data) {
+                    Ok(label) => Ok(Some(label)),
+                    Err(e) => {
+                        warn!("Ignoring BSD disklabel at offset {}: {}", offset, e);
+                        Ok(None)
+                    }
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn is_checksum_valid(&self) -> bool {
+        self.checksum == self.computed_checksum
+    }
+
+    /// Whether partition offsets are absolute disk sectors rather than relative to the slice.
+    ///
+    /// Older FreeBSD labels and OpenBSD/NetBSD labels use absolute offsets; newer FreeBSD labels are relative. A label
+    /// is taken to be absolute if its raw partition starts at the slice, or if no used partition starts before it.
+    pub fn has_absolute_offsets(&self, slice_start_lba: u64) -> bool {
+        if slice_start_lba == 0 {
+            return false;
+        }
+
+        if let Some(raw) = self.partitions.get(BSD_RAW_PARTITION) {
+            if raw.offset as u64 == slice_start_lba {
+                return true;
+            }
+        }
+
+        let mut used = self.partitions.iter().enumerate().filter(|(i, p)| *i != BSD_RAW_PARTITION && p.is_used());
+        used.all(|(_, p)| p.offset as u64 >= slice_start_lba)
+    }
+
+    /// The used partitions, other than the raw partition, with absolute addresses.
+    ///
+    /// Partitions are numbered by their letter, starting from 1 for `a`.
+    pub fn get_partitions(&self, slice_start_lba: u64, sector_size: u32) -> Vec<Partition> {
+        let base_lba = if self.has_absolute_offsets(slice_start_lba) { 0 } else { slice_start_lba };
+
+        self.partitions
+            .iter()
+            .enumerate()
+            .filter(|(i, p)| *i != BSD_RAW_PARTITION && p.is_used() && p.size > 0)
+            .map(|(i, p)| Partition {
+                index: i + 1,
+                scheme: PartitionScheme::Bsd,
+                partition_type: PartitionType::Bsd(p.fstype),
+                name: None,
+                unique_guid: None,
+                flags: 0,
+                sector_size,
+                start_lba: base_lba + p.offset as u64,
+                end_lba: base_lba + p.offset as u64 + p.size as u64 - 1,
+            })
+            .collect()
+    }
+}
+
+impl Display for BsdDisklabel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Type: {} ({}/{})\nPack: {}\nBytes per sector: {}\nSectors per track: {}\nTracks per cylinder: {}\n\
+             Cylinders: {}\nSectors per unit: {}\nFlags: 0x{:08x}\nChecksum: 0x{:04x} ({})\nPartitions: {}",
+            self.type_name,
+            self.drive_type,
+            self.drive_subtype,
+            self.pack_name,
+            self.sector_size,
+            self.sectors_per_track,
+            self.tracks_per_cylinder,
+            self.cylinder_count,
+            self.sectors_per_unit,
+            self.flags,
+            self.checksum,
+            if self.is_checksum_valid() {
+                "valid".to_string()
+            } else {
+                format!("mismatch, computed 0x{:04x}", self.computed_checksum)
+            },
+            self.partitions.len(),
+        )?;
+
+        for partition in self.partitions.iter().filter(|p| p.is_used()) {
+            write!(f, "\n    {}", partition)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Detect a BSD disklabel, assuming 512-byte sectors.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    Ok(BsdDisklabel::detect(reader, 512)?.map(|label| {
+        let mut result = ProbeResult::new("bsdlabel", ProbeUsage::PartitionTable, ProbeConfidence::High);
+        result.version = Some(format!("{} partitions", label.partitions.iter().filter(|p| p.is_used()).count()));
+        result.label = if label.pack_name.is_empty() { None } else { Some(label.pack_name) };
+        result
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_partition(label: &mut [u8], index: usize, size: u32, offset: u32, fstype: u8) {
+        let entry = &mut label[BSD_DISKLABEL_HEADER_SIZE + index * BSD_PARTITION_ENTRY_SIZE..];
+        entry[0..4].copy_from_slice(&size.to_le_bytes());
+        entry[4..8].copy_from_slice(&offset.to_le_bytes());
+        entry[12] = fstype;
+    }
+
+    fn make_slice(slice_start: u32, offsets_absolute: bool) -> Vec<u8> {
+        let mut slice = vec![0u8; 4 * 512];
+        let label = &mut slice[512..512 + BSD_DISKLABEL_SIZE];
+        label[0..4].copy_from_slice(&BSD_DISKLABEL_MAGIC.to_le_bytes());
+        label[132..136].copy_from_slice(&BSD_DISKLABEL_MAGIC.to_le_bytes());
+        label[40..44].copy_from_slice(&512u32.to_le_bytes());
+        label[138..140].copy_from_slice(&8u16.to_le_bytes());
+
+        let base = if offsets_absolute { slice_start } else { 0 };
+        write_partition(label, 0, 1024, base + 16, 7);
+        write_partition(label, 1, 512, base + 1040, 1);
+        write_partition(label, 2, 2048, base, BSD_FS_UNUSED);
+
+        let label_size = BSD_DISKLABEL_HEADER_SIZE + 8 * BSD_PARTITION_ENTRY_SIZE;
+        let checksum =
+            label[..label_size].chunks_exact(2).fold(0u16, |acc, word| acc ^ u16::from_le_bytes([word[0], word[1]]));
+        label[136..138].copy_from_slice(&checksum.to_le_bytes());
+        slice
+    }
+
+    #[test]
+    fn read_disklabel() {
+        for &absolute in [false, true].iter() {
+            let label = BsdDisklabel::detect(&mut Cursor::new(make_slice(63, absolute)), 512).unwrap().unwrap();
+            assert!(label.is_checksum_valid());
+            assert_eq!(label.partitions.len(), 8);
+            assert_eq!(label.partitions[0].letter, 'a');
+            assert_eq!(get_bsd_fstype_name(label.partitions[0].fstype), "4.2BSD");
+            assert_eq!(label.has_absolute_offsets(63), absolute);
+
+            let partitions = label.get_partitions(63, 512);
+            assert_eq!(partitions.len(), 2);
+            assert_eq!(partitions[0].start_lba, 63 + 16);
+            assert_eq!(partitions[0].end_lba, 63 + 16 + 1023);
+            assert_eq!(partitions[1].index, 2);
+            assert_eq!(partitions[1].partition_type, PartitionType::Bsd(1));
+        }
+
+        assert!(BsdDisklabel::detect(&mut Cursor::new(vec![0u8; 2048]), 512).unwrap().is_none());
+
+        let mut inconsistent = make_slice(63, false);
+        inconsistent[512 + 138..512 + 140].copy_from_slice(&17u16.to_le_bytes());
+        assert!(BsdDisklabel::detect(&mut Cursor::new(inconsistent), 512).unwrap().is_none());
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Error as IoError, ErrorKind, Read, Seek, SeekFrom},
+};
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io {
+        offset: u64,
+        source: IoError,
+    },
+    Truncated {
+        offset: u64,
+        expected: usize,
+        actual: usize,
+    },
+    ChecksumMismatch {
+        structure: String,
+        stored: u32,
+        computed: u32,
+    },
+    ClusterOutOfRange {
+        cluster: u32,
+        cluster_count: u32,
+    },
+    IncorrectPassphrase,
+    InvalidGeometry(String),
+    InvalidGptHeaderRevision(u32),
+    InvalidGptHeaderSignature(Vec<u8>),
+    InvalidGptHeaderSize(u32),
+    InvalidMetadata {
+        structure: String,
+        reason: String,
+    },
+    InvalidPartitionEntry(String),
+    InvalidPartitionType {
+        expected: String,
+        actual: String,
+    },
+    InvalidSignature([u8; 2]),
+    Unsupported(String),
+}
+
+impl ImageError {
+    /// An `InvalidMetadata` error for the named on-disk structure, such as "LVM" or "LUKS".
+    pub fn invalid_metadata(structure: &str, reason: String) -> Self {
+        Self::InvalidMetadata {
+            structure: structure.to_string(),
+            reason,
+        }
+    }
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Io { offset, source } => write!(f, "I/O error at offset 0x{:x}: {}", offset, source),
+            Self::Truncated {
+                offset,
+                expected,
+                actual,
+            } => {
+                write!(f, "Truncated read at offset 0x{:x}: expected {} bytes, got {} bytes", offset, expected, actual)
+            }
+            Self::ChecksumMismatch {
+                structure,
+                stored,
+                computed,
+            } => write!(f, "{} checksum mismatch: stored 0x{:08x}, computed 0x{:08x}", structure, stored, computed),
+            Self::ClusterOutOfRange { cluster, cluster_count } => {
+                write!(f, "Cluster number {} is out of range; the FAT has {} entries", cluster, cluster_count)
+            }
+            Self::IncorrectPassphrase => write!(f, "No key slot could be unlocked with the supplied passphrase"),
+            Self::InvalidGeometry(msg) => write!(f, "Invalid geometry: {}", msg),
+            Self::InvalidGptHeaderRevision(rev) => write!(f, "Invalid GPT header revision: 0x{:04x}", rev),
+            Self::InvalidGptHeaderSignature(sig) => {
+                f.write_str("Invalid GPT header signature: ")?;
+                for b in sig {
+                    write!(f, "{:02x}", b)?
+                }
+                Ok(())
+            }
+            Self::InvalidGptHeaderSize(size) => write!(f, "Invalid GPT header size: {}", size),
+            Self::InvalidMetadata { structure, reason } => write!(f, "Invalid {} metadata: {}", structure, reason),
+            Self::InvalidPartitionEntry(msg) => write!(f, "Invalid partition entry: {}", msg),
+            Self::InvalidPartitionType { expected, actual } => {
+                write!(f, "Invalid partition type; expected {}, actual {}", expected, actual)
+            }
+            Self::InvalidSignature(sig) => {
+                write!(f, "Invalid signature: expected [0x55, 0xaa], actual {}", hex::encode(sig))
+            }
+            Self::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Seek to `offset` and fill `buf`, reporting a short read as [ImageError::Truncated].
+pub(crate) fn read_exact_at<R: Read + Seek + ?Sized>(
+    reader: &mut R,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<(), ImageError> {
+    reader.seek(SeekFrom::Start(offset)).map_err(|source| ImageError::Io { offset, source })?;
+
+    let mut pos = 0;
+    while pos < buf.len() {
+        match reader.read(&mut buf[pos..]) {
+            Ok(0) => {
+                return Err(ImageError::Truncated {
+                    offset,
+                    expected: buf.len(),
+                    actual: pos,
+                })
+            }
+            Ok(n) => pos += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(source) => {
+                return Err(ImageError::Io {
+                    offset: offset + pos as u64,
+                    source,
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -4,12 +4,16 @@ use log::{debug, warn};
 use phf::{phf_map, Map};
 use std::{
     convert::TryInto,
-    error::Error,
     fmt::{Display, Formatter, Result as FmtResult},
-    io::{Read, Seek, SeekFrom},
+    io::{Read, Seek},
 };
 
-use crate::errors::ImageError;
+use crate::{
+    bootcode::{identify_boot_code, BootLoader},
+    errors::{read_exact_at, ImageError},
+    probe::{decode_label, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+    reader::PartitionReader,
+};
 
 pub const BOOT_SECTOR_SIZE: usize = 512;
 
@@ -55,40 +59,31 @@ impl Display for FatType {
 
 #[derive(Debug)]
 pub struct FatPartition<R: Read + Seek> {
-    pub reader: R,
-    pub offset: u64,
+    pub reader: PartitionReader<R>,
     pub fat_type: FatType,
     pub boot_sector: FatBootSector,
     pub fat_tables: Vec<Vec<u32>>,
 }
 
 impl<R: Read + Seek> FatPartition<R> {
-    pub fn from_partition_image(mut reader: R, offset: u64) -> Result<Self, Box<dyn Error + 'static>> {
+    pub fn from_partition_image(mut reader: R, offset: u64) -> Result<Self, ImageError> {
         let boot_sector = FatBootSector::from_partition_image(&mut reader, offset)?;
-        let fat_type = match boot_sector.extra {
-            FatBootSectorExtra::Fat12(_) => FatType::Fat12,
-            FatBootSectorExtra::Fat16(_) => FatType::Fat16,
-            FatBootSectorExtra::Fat32(_) => FatType::Fat32,
-        };
-
-        reader.seek(SeekFrom::Start(
-            offset + boot_sector.reserved_sectors as u64 * boot_sector.bytes_per_sector as u64,
-        ))?;
+        let filesystem_size = boot_sector.sectors_in_filesystem as u64 * boot_sector.bytes_per_sector as u64;
+        let mut reader = PartitionReader::new(reader, offset, filesystem_size);
+        let fat_type = boot_sector.get_fat_type();
 
         let mut fat_tables = Vec::with_capacity(boot_sector.number_of_fats as usize);
         let fat_table_size = boot_sector.sectors_per_fat as usize * boot_sector.bytes_per_sector as usize;
         for i in 0..boot_sector.number_of_fats as usize {
-            reader.seek(SeekFrom::Start(offset + boot_sector.get_fat_table_offset(i)))?;
             let mut fat_table_bytes = vec![0; fat_table_size];
-
-            reader.read_exact(&mut fat_table_bytes)?;
+            read_exact_at(&mut reader, boot_sector.get_fat_table_offset(i), &mut fat_table_bytes)?;
 
             match fat_type {
                 FatType::Fat12 => {
                     // FAT12 has to be handled differently. Each entry spans 1.5 bytes, so we read 3 bytes at a time
                     // and write 2 FAT entries.
-                    let mut fat_table = Vec::with_capacity(fat_table_size / 3);
-                    for i in (0..fat_table_size).step_by(3) {
+                    let mut fat_table = Vec::with_capacity(2 * fat_table_size / 3);
+                    for i in (0..fat_table_size - fat_table_size % 3).step_by(3) {
                         let first = fat_table_bytes[i] as u32 | (fat_table_bytes[i + 1] as u32 & 0x0f) << 8;
                         let second =
                             ((fat_table_bytes[i + 1] as u32 & 0xf0) >> 4) | ((fat_table_bytes[i + 2] as u32) << 4);
@@ -110,9 +105,9 @@ impl<R: Read + Seek> FatPartition<R> {
                 }
                 FatType::Fat32 => {
                     // 32-bit LE entries.
-                    let mut fat_table = Vec::with_capacity(fat_table_size / 2);
+                    let mut fat_table = Vec::with_capacity(fat_table_size / 4);
                     for i in (0..fat_table_size).step_by(4) {
-                        fat_table.push(u32::from_le_bytes(fat_table_bytes[i..i + 2].try_into().unwrap()));
+                        fat_table.push(u32::from_le_bytes(fat_table_bytes[i..i + 4].try_into().unwrap()));
                     }
 
                     fat_tables.push(fat_table);
@@ -122,41 +117,34 @@ impl<R: Read + Seek> FatPartition<R> {
 
         Ok(Self {
             reader,
-            offset,
             fat_type,
             fat_tables,
             boot_sector,
         })
     }
 
-    pub fn get_root_directory_entries(&mut self) -> Result<Vec<FatDirectoryEntry>, Box<dyn Error + 'static>> {
-        let mut directory_entries = Vec::with_capacity(self.boot_sector.root_directory_entries as usize);
-        self.reader.seek(SeekFrom::Start(self.offset + self.boot_sector.get_root_directory_offset()))?;
-
-        for _ in 0..self.boot_sector.root_directory_entries {
-            let mut directory_entry_bytes: [u8; FAT_DIRECTORY_ENTRY_SIZE] = [0; FAT_DIRECTORY_ENTRY_SIZE];
-            self.reader.read_exact(&mut directory_entry_bytes)?;
-            let directory_entry = FatDirectoryEntry::from_data(&directory_entry_bytes, self.fat_type);
-            directory_entries.push(directory_entry);
-        }
+    pub fn get_root_directory_entries(&mut self) -> Result<Vec<FatDirectoryEntry>, ImageError> {
+        let root_directory_entries = self.boot_sector.root_directory_entries as usize;
+        let mut directory_bytes = vec![0; root_directory_entries * FAT_DIRECTORY_ENTRY_SIZE];
+        read_exact_at(&mut self.reader, self.boot_sector.get_root_directory_offset(), &mut directory_bytes)?;
 
-        Ok(directory_entries)
+        Ok(directory_bytes
+            .chunks_exact(FAT_DIRECTORY_ENTRY_SIZE)
+            .map(|data| FatDirectoryEntry::from_data(data, self.fat_type))
+            .collect())
     }
 
-    pub fn get_directory_at_cluster(
-        &mut self,
-        mut cluster: u32,
-    ) -> Result<Vec<FatDirectoryEntry>, Box<dyn Error + 'static>> {
+    pub fn get_directory_at_cluster(&mut self, mut cluster: u32) -> Result<Vec<FatDirectoryEntry>, ImageError> {
         debug!("Retrieving directory at cluster {}", cluster);
         let bytes_per_cluster = self.boot_sector.get_bytes_per_cluster();
         let mut directory_entries = Vec::with_capacity(512);
 
         loop {
-            let cluster_offset = self.boot_sector.get_cluster_offset(cluster) + self.offset;
+            self.check_cluster(cluster)?;
+            let cluster_offset = self.boot_sector.get_cluster_offset(cluster);
             debug!("Current cluster is {} at offset {:x}", cluster, cluster_offset);
-            self.reader.seek(SeekFrom::Start(cluster_offset))?;
             let mut directory_entry_bytes = vec![0; bytes_per_cluster];
-            self.reader.read_exact(&mut directory_entry_bytes)?;
+            read_exact_at(&mut self.reader, cluster_offset, &mut directory_entry_bytes)?;
 
             for i in (0..bytes_per_cluster).step_by(FAT_DIRECTORY_ENTRY_SIZE) {
                 let directory_entry = FatDirectoryEntry::from_data(
@@ -179,6 +167,52 @@ impl<R: Read + Seek> FatPartition<R> {
 
         Ok(directory_entries)
     }
+
+    fn check_cluster(&self, cluster: u32) -> Result<(), ImageError> {
+        let cluster_count = self.fat_tables.first().map(|fat_table| fat_table.len()).unwrap_or(0) as u32;
+        if cluster < 2 || cluster >= cluster_count {
+            Err(ImageError::ClusterOutOfRange { cluster, cluster_count })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Detect a FAT filesystem by its boot sector signature and BIOS Parameter Block.
+pub fn probe(mut reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let boot_sector = match FatBootSector::from_partition_image(&mut reader, 0) {
+        Ok(boot_sector) => boot_sector,
+        Err(ImageError::InvalidSignature(_))
+        | Err(ImageError::InvalidGeometry(_))
+        | Err(ImageError::Truncated { .. }) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    if !boot_sector.bytes_per_sector.is_power_of_two()
+        || boot_sector.bytes_per_sector < 512
+        || !boot_sector.sectors_per_cluster.is_power_of_two()
+        || boot_sector.number_of_fats == 0
+        || boot_sector.sectors_per_fat == 0
+    {
+        return Ok(None);
+    }
+
+    let (serial_number, volume_label, file_system_type) = match &boot_sector.extra {
+        FatBootSectorExtra::Fat12(_) => (None, None, None),
+        FatBootSectorExtra::Fat16(extra) => (extra.serial_number, extra.volume_label, extra.file_system_type),
+        FatBootSectorExtra::Fat32(extra) => (extra.serial_number, extra.volume_label, extra.file_system_type),
+    };
+
+    let confidence = match file_system_type {
+        Some(fs_type) if fs_type.starts_with(b"FAT") => ProbeConfidence::High,
+        _ => ProbeConfidence::Medium,
+    };
+
+    let mut result = ProbeResult::new("vfat", ProbeUsage::Filesystem, confidence);
+    result.version = Some(boot_sector.get_fat_type().to_string());
+    result.label = volume_label.and_then(|label| decode_label(&label)).filter(|label| label != "NO NAME");
+    result.uuid = serial_number.map(|serial| format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff));
+    Ok(Some(result))
 }
 
 #[derive(Debug)]
@@ -198,22 +232,23 @@ pub struct FatBootSector {
     pub hidden_sectors: u32,
     pub signature: [u8; 2],
     pub extra: FatBootSectorExtra,
+    // Everything between the end of the BPB and the signature.
+    pub boot_code: Vec<u8>,
 }
 
 impl FatBootSector {
-    pub fn from_partition_image<R>(reader: &mut R, start_pos: u64) -> Result<Self, Box<dyn Error + 'static>>
+    pub fn from_partition_image<R>(reader: &mut R, start_pos: u64) -> Result<Self, ImageError>
     where
         R: Read + Seek,
     {
         let mut data: [u8; BOOT_SECTOR_SIZE] = [0; BOOT_SECTOR_SIZE];
-        reader.seek(SeekFrom::Start(start_pos))?;
-        reader.read_exact(&mut data)?;
+        read_exact_at(reader, start_pos, &mut data)?;
 
         // Read the BIOS Parameter Block. The format, alas, varies grossly by FAT type, which we have to deduce via
         // heuristics.
         let signature: [u8; 2] = data[510..512].try_into().unwrap();
         if signature != [0x55, 0xAA] {
-            return Err(ImageError::InvalidSignature(signature).into());
+            return Err(ImageError::InvalidSignature(signature));
         }
 
         let jump_instruction: [u8; 3] = [data[0], data[1], data[2]];
@@ -245,17 +280,30 @@ impl FatBootSector {
         let number_of_heads: u16 = u16::from_le_bytes(data[0x1a..0x1c].try_into().unwrap());
         let hidden_sectors: u32 = u32::from_le_bytes(data[0x1c..0x20].try_into().unwrap());
 
-        let data_sectors = sectors_in_filesystem
-            - reserved_sectors as u32
-            - (number_of_fats as u32 * sectors_per_fat)
-            - (root_directory_entries as u32 * FAT_DIRECTORY_ENTRY_SIZE as u32 / bytes_per_sector as u32);
+        if bytes_per_sector == 0 || sectors_per_cluster == 0 {
+            return Err(ImageError::InvalidGeometry(format!(
+                "bytes per sector ({}) and sectors per cluster ({}) must be non-zero",
+                bytes_per_sector, sectors_per_cluster
+            )));
+        }
+
+        let metadata_sectors = reserved_sectors as u64
+            + number_of_fats as u64 * sectors_per_fat as u64
+            + root_directory_entries as u64 * FAT_DIRECTORY_ENTRY_SIZE as u64 / bytes_per_sector as u64;
+        if metadata_sectors > sectors_in_filesystem as u64 {
+            return Err(ImageError::InvalidGeometry(format!(
+                "reserved, FAT, and root directory sectors ({}) exceed filesystem sectors ({})",
+                metadata_sectors, sectors_in_filesystem
+            )));
+        }
 
+        let data_sectors = sectors_in_filesystem - metadata_sectors as u32;
         let data_clusters = data_sectors / sectors_per_cluster as u32;
 
         // This logic is from https://www.win.tue.nl/~aeb/linux/fs/fat/fat-1.html
         let extra = match data_clusters {
             _ if data_clusters < 4085 => FatBootSectorExtra::Fat12(Fat12BootExtra {}),
-            _ if data_clusters >= 4085 && data_clusters < 65525 => {
+            _ if (4085..65525).contains(&data_clusters) => {
                 // FAT 16
                 let logical_drive_number: u8 = data[0x24];
                 let flags: u8 = data[0x25];
@@ -323,6 +371,8 @@ impl FatBootSector {
             }
         };
 
+        let boot_code = data[get_boot_code_offset(&extra)..510].to_vec();
+
         Ok(Self {
             jump_instruction,
             oem_name,
@@ -339,9 +389,26 @@ impl FatBootSector {
             number_of_heads,
             signature,
             extra,
+            boot_code,
         })
     }
 
+    pub fn get_boot_loader(&self) -> BootLoader {
+        match identify_boot_code(&self.boot_code, get_boot_code_offset(&self.extra)) {
+            BootLoader::Unknown if self.oem_name.starts_with(b"FRDOS") => BootLoader::FreeDos,
+            BootLoader::Unknown if &self.oem_name == b"SYSLINUX" => BootLoader::Syslinux,
+            boot_loader => boot_loader,
+        }
+    }
+
+    pub fn get_fat_type(&self) -> FatType {
+        match self.extra {
+            FatBootSectorExtra::Fat12(_) => FatType::Fat12,
+            FatBootSectorExtra::Fat16(_) => FatType::Fat16,
+            FatBootSectorExtra::Fat32(_) => FatType::Fat32,
+        }
+    }
+
     pub fn get_root_directory_offset(&self) -> u64 {
         self.get_fat_table_offset(self.number_of_fats as usize)
     }
@@ -379,7 +446,7 @@ impl Display for FatBootSector {
             f,
             "OEM name: {}\nBytes per sector: {}\nSectors per cluster: {}\nReserved sectors: {}\nNumber of FATs: {}\n\
              Max root entries: {}\nFilesystem sectors: {}\nMedia descriptor: 0x{:02x} ({})\nSectors per FAT: {}\n\
-             Sectors per track: {}\nNumber of heads: {}\nHidden sectors: {}\n",
+             Sectors per track: {}\nNumber of heads: {}\nHidden sectors: {}\nBoot code: {}\n",
             String::from_utf8_lossy(&self.oem_name),
             self.bytes_per_sector,
             self.sectors_per_cluster,
@@ -392,7 +459,8 @@ impl Display for FatBootSector {
             self.sectors_per_fat,
             self.sectors_per_track,
             self.number_of_heads,
-            self.hidden_sectors
+            self.hidden_sectors,
+            self.get_boot_loader(),
         )?;
 
         match &self.extra {
@@ -403,6 +471,13 @@ impl Display for FatBootSector {
     }
 }
 
+fn get_boot_code_offset(extra: &FatBootSectorExtra) -> usize {
+    match extra {
+        FatBootSectorExtra::Fat32(_) => 0x5a,
+        _ => 0x3e,
+    }
+}
+
 #[derive(Debug)]
 pub struct Fat12BootExtra {}
 
@@ -621,7 +696,7 @@ impl FatDirectoryEntry {
     pub fn get_directory_entries<R: Read + Seek>(
         &self,
         fp: &mut FatPartition<R>,
-    ) -> Result<Vec<FatDirectoryEntry>, Box<dyn Error + 'static>> {
+    ) -> Result<Vec<FatDirectoryEntry>, ImageError> {
         fp.get_directory_at_cluster(self.first_cluster)
     }
 
@@ -638,7 +713,7 @@ impl FatDirectoryEntry {
                 basename.extend_from_slice(&self.filename[1..8]);
             }
             0xe5 => {
-                basename.push('?' as u8);
+                basename.push(b'?');
                 basename.extend_from_slice(&self.filename[1..8]);
             }
             _ => basename.extend_from_slice(&self.filename[0..8]),
@@ -677,8 +752,8 @@ fn fat_date_to_chrono_naive_date(data: [u8; 2]) -> Option<NaiveDate> {
 fn fat_fine_time_to_chrono_naive_time(data: [u8; 3]) -> Option<NaiveTime> {
     let centi_millis = data[0] as u32;
     let hms = u16::from_le_bytes(data[1..3].try_into().unwrap()) as u32;
-    let hour = (hms & 0xf800) >> 11 as u32;
-    let minute = (hms & 0x07e0) >> 5 as u32;
+    let hour = (hms & 0xf800) >> 11;
+    let minute = (hms & 0x07e0) >> 5;
 
     let seconds = centi_millis / 100;
     let milliseconds = 10 * (centi_millis % 100);
@@ -688,9 +763,9 @@ fn fat_fine_time_to_chrono_naive_time(data: [u8; 3]) -> Option<NaiveTime> {
 
 fn fat_time_to_chrono_naive_time(data: [u8; 2]) -> Option<NaiveTime> {
     let hms = u16::from_le_bytes(data) as u32;
-    let hour = (hms & 0xf800) >> 11 as u32;
-    let minute = (hms & 0x07e0) >> 5 as u32;
-    let seconds = (hms & 0x001f) as u32 * 2;
+    let hour = (hms & 0xf800) >> 11;
+    let minute = (hms & 0x07e0) >> 5;
+    let seconds = (hms & 0x001f) * 2;
 
     NaiveTime::from_hms_opt(hour, minute, seconds)
 }
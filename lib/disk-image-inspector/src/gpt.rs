@@ -0,0 +1,1508 @@
+use log::{info, warn};
+use phf::{phf_map, Map};
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+use uuid::Uuid;
+
+use crate::{
+    blockdev::{BlockDevice, PROBED_SECTOR_SIZES},
+    bootsector::BootSector,
+    errors::{read_exact_at, ImageError},
+    probe::{read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+    reader::PartitionReader,
+};
+
+pub const GPT_HEADER_SIGNATURE: [u8; 8] = [0x45, 0x46, 0x49, 0x20, 0x50, 0x41, 0x52, 0x54];
+pub const GPT_REVISION_1_0: u32 = 0x00010000;
+pub const GPT_HEADER_1_0_SIZE: u32 = 92;
+pub const GPT_PRIMARY_HEADER_LBA: u64 = 1;
+pub const MBR_GPT_PARTITION_TYPE: u8 = 0xee;
+pub const GPT_MIN_PARTITION_ENTRY_SIZE: u32 = 128;
+
+pub const GPT_ATTRIBUTE_PLATFORM_REQUIRED: u64 = 1 << 0;
+pub const GPT_ATTRIBUTE_EFI_IGNORE: u64 = 1 << 1;
+pub const GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+// Bits 48-63 are defined by the partition type.
+pub const GPT_ATTRIBUTE_TYPE_SPECIFIC_MASK: u64 = 0xffff << 48;
+
+// Far larger than any real partition entry array; guards against allocating for a corrupt header.
+pub const GPT_MAX_PARTITION_ENTRY_ARRAY_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct GptHeader {
+    pub signature: [u8; 8],
+    pub revision: u32,
+    pub header_size: u32,
+    pub crc32: u32,
+    pub reserved1: [u8; 4],
+    pub current_lba: u64,
+    pub backup_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Uuid,
+    pub partition_table_lba: u64,
+    pub partition_count: u32,
+    pub partition_entry_size: u32,
+    pub partition_entry_array_crc32: u32,
+    /// CRC32 of the header as read, computed with the `crc32` field zeroed.
+    pub computed_crc32: u32,
+}
+
+impl GptHeader {
+    pub fn new<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Self, ImageError> {
+        let mut header_bytes: [u8; GPT_HEADER_1_0_SIZE as usize] = [0; GPT_HEADER_1_0_SIZE as usize];
+        read_exact_at(reader, offset, &mut header_bytes)?;
+
+        let signature: [u8; 8] = header_bytes[0..8].try_into().unwrap();
+        if signature != GPT_HEADER_SIGNATURE {
+            return Err(ImageError::InvalidGptHeaderSignature(signature.to_vec()));
+        }
+
+        let revision = u32::from_le_bytes(header_bytes[8..12].try_into().unwrap());
+        if revision < GPT_REVISION_1_0 {
+            return Err(ImageError::InvalidGptHeaderRevision(revision));
+        }
+
+        let header_size = u32::from_le_bytes(header_bytes[12..16].try_into().unwrap());
+        if header_size != GPT_HEADER_1_0_SIZE {
+            return Err(ImageError::InvalidGptHeaderSize(header_size));
+        }
+
+        let crc32 = u32::from_le_bytes(header_bytes[16..20].try_into().unwrap());
+        let reserved1 = header_bytes[20..24].try_into().unwrap();
+
+        let current_lba = u64::from_le_bytes(header_bytes[24..32].try_into().unwrap());
+        let backup_lba = u64::from_le_bytes(header_bytes[32..40].try_into().unwrap());
+        let first_usable_lba = u64::from_le_bytes(header_bytes[40..48].try_into().unwrap());
+        let last_usable_lba = u64::from_le_bytes(header_bytes[48..56].try_into().unwrap());
+        let disk_guid = read_mixed_endian_uuid(header_bytes[56..72].try_into().unwrap());
+        let partition_table_lba = u64::from_le_bytes(header_bytes[72..80].try_into().unwrap());
+        let partition_count = u32::from_le_bytes(header_bytes[80..84].try_into().unwrap());
+        let partition_entry_size = u32::from_le_bytes(header_bytes[84..88].try_into().unwrap());
+        let partition_entry_array_crc32 = u32::from_le_bytes(header_bytes[88..92].try_into().unwrap());
+
+        header_bytes[16..20].copy_from_slice(&[0; 4]);
+        let computed_crc32 = crc32fast::hash(&header_bytes);
+
+        Ok(Self {
+            signature,
+            revision,
+            header_size,
+            crc32,
+            reserved1,
+            current_lba,
+            backup_lba,
+            first_usable_lba,
+            last_usable_lba,
+            disk_guid,
+            partition_table_lba,
+            partition_count,
+            partition_entry_size,
+            partition_entry_array_crc32,
+            computed_crc32,
+        })
+    }
+
+    pub fn from_block_device<R: Read + Seek>(device: &mut BlockDevice<R>, lba: u64) -> Result<Self, ImageError> {
+        let offset = device.lba_to_offset(lba)?;
+        Self::new(device, offset)
+    }
+
+    pub fn is_crc32_valid(&self) -> bool {
+        self.crc32 == self.computed_crc32
+    }
+
+    /// Read the raw partition entry array described by this header.
+    pub fn read_partition_entry_array<R: Read + Seek>(
+        &self,
+        device: &mut BlockDevice<R>,
+    ) -> Result<Vec<u8>, ImageError> {
+        if self.partition_entry_size < GPT_MIN_PARTITION_ENTRY_SIZE || !self.partition_entry_size.is_multiple_of(8) {
+            return Err(ImageError::InvalidGeometry(format!(
+                "GPT partition entry size {} is not a multiple of 8 of at least {}",
+                self.partition_entry_size, GPT_MIN_PARTITION_ENTRY_SIZE
+            )));
+        }
+
+        let array_size = self.partition_count as u64 * self.partition_entry_size as u64;
+        if array_size > GPT_MAX_PARTITION_ENTRY_ARRAY_SIZE {
+            return Err(ImageError::InvalidGeometry(format!(
+                "GPT partition entry array of {} bytes is larger than {} bytes",
+                array_size, GPT_MAX_PARTITION_ENTRY_ARRAY_SIZE
+            )));
+        }
+
+        let mut array_bytes = vec![0; array_size as usize];
+        let offset = device.lba_to_offset(self.partition_table_lba)?;
+        read_exact_at(device, offset, &mut array_bytes)?;
+        Ok(array_bytes)
+    }
+
+    /// Read every entry in the partition entry array, including unused ones, so that indices match slot numbers.
+    pub fn read_partition_entries<R: Read + Seek>(
+        &self,
+        device: &mut BlockDevice<R>,
+    ) -> Result<Vec<GptPartitionEntry>, ImageError> {
+        let array_bytes = self.read_partition_entry_array(device)?;
+        Ok(array_bytes.chunks_exact(self.partition_entry_size as usize).map(GptPartitionEntry::from_bytes).collect())
+    }
+
+    pub fn compute_partition_entry_array_crc32<R: Read + Seek>(
+        &self,
+        device: &mut BlockDevice<R>,
+    ) -> Result<u32, ImageError> {
+        Ok(crc32fast::hash(&self.read_partition_entry_array(device)?))
+    }
+
+    /// Verify the header and partition entry array checksums, returning [ImageError::ChecksumMismatch] for the first
+    /// one that does not match.
+    pub fn validate<R: Read + Seek>(&self, device: &mut BlockDevice<R>) -> Result<(), ImageError> {
+        if !self.is_crc32_valid() {
+            return Err(ImageError::ChecksumMismatch {
+                structure: "GPT header".to_string(),
+                stored: self.crc32,
+                computed: self.computed_crc32,
+            });
+        }
+
+        let computed = self.compute_partition_entry_array_crc32(device)?;
+        if computed != self.partition_entry_array_crc32 {
+            return Err(ImageError::ChecksumMismatch {
+                structure: "GPT partition entry array".to_string(),
+                stored: self.partition_entry_array_crc32,
+                computed,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for GptHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Signature: {}\nRevision: 0x{:04x}\nHeader size: {}\nCRC32: 0x{:08x} ({})\nCurrent LBA: {}\nBackup LBA: {}\n\
+             First usable LBA: {}\nLast usable LBA: {}\nDisk GUID: {}\nPartition table LBA: {}\nPartition count: {}\n\
+             Partition entry size: {}\nPartition table CRC32: 0x{:08x}",
+            hex::encode(self.signature),
+            self.revision,
+            self.header_size,
+            self.crc32,
+            if self.is_crc32_valid() {
+                "valid".to_string()
+            } else {
+                format!("mismatch, computed 0x{:08x}", self.computed_crc32)
+            },
+            self.current_lba,
+            self.backup_lba,
+            self.first_usable_lba,
+            self.last_usable_lba,
+            self.disk_guid,
+            self.partition_table_lba,
+            self.partition_count,
+            self.partition_entry_size,
+            self.partition_entry_array_crc32,
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum GptBackupWarning {
+    Truncated {
+        backup_lba: u64,
+        sector_count: u64,
+    },
+    NotAtEndOfImage {
+        backup_lba: u64,
+        last_lba: u64,
+    },
+    ReadFailed {
+        lba: u64,
+        error: String,
+    },
+    HeaderChecksumMismatch {
+        stored: u32,
+        computed: u32,
+    },
+    EntryArrayChecksumMismatch {
+        stored: u32,
+        computed: u32,
+    },
+    FieldMismatch {
+        field: &'static str,
+        primary: String,
+        backup: String,
+    },
+    EntryMismatch {
+        index: u32,
+    },
+}
+
+impl Display for GptBackupWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Truncated {
+                backup_lba,
+                sector_count,
+            } => write!(
+                f,
+                "Backup GPT header at LBA {} is beyond the end of the image ({} sectors); the image is truncated",
+                backup_lba, sector_count
+            ),
+            Self::NotAtEndOfImage { backup_lba, last_lba } => {
+                write!(f, "Backup GPT header is at LBA {}, not at the last LBA of the image ({})", backup_lba, last_lba)
+            }
+            Self::ReadFailed { lba, error } => write!(f, "Unable to read backup GPT data at LBA {}: {}", lba, error),
+            Self::HeaderChecksumMismatch { stored, computed } => {
+                write!(f, "Backup GPT header CRC32 mismatch: stored 0x{:08x}, computed 0x{:08x}", stored, computed)
+            }
+            Self::EntryArrayChecksumMismatch { stored, computed } => write!(
+                f,
+                "Backup GPT partition table CRC32 mismatch: stored 0x{:08x}, computed 0x{:08x}",
+                stored, computed
+            ),
+            Self::FieldMismatch { field, primary, backup } => {
+                write!(f, "Backup GPT header {} is {}, but the primary has {}", field, backup, primary)
+            }
+            Self::EntryMismatch { index } => {
+                write!(f, "Backup GPT partition entry {} differs from the primary", index + 1)
+            }
+        }
+    }
+}
+
+/// The backup GPT header at the end of the disk, checked against the primary header.
+#[derive(Debug)]
+pub struct GptBackup {
+    pub header: Option<GptHeader>,
+    pub warnings: Vec<GptBackupWarning>,
+}
+
+impl GptBackup {
+    /// Read the backup header and partition entry array that `primary` points to and compare them with the primary.
+    ///
+    /// A missing, corrupt or inconsistent backup is not an error; the problems are recorded in `warnings`.
+    pub fn read<R: Read + Seek>(device: &mut BlockDevice<R>, primary: &GptHeader) -> Result<Self, ImageError> {
+        let mut backup = Self {
+            header: None,
+            warnings: Vec::new(),
+        };
+
+        let sector_count = device.sector_count()?;
+        if primary.backup_lba >= sector_count {
+            backup.warn(GptBackupWarning::Truncated {
+                backup_lba: primary.backup_lba,
+                sector_count,
+            });
+            return Ok(backup);
+        }
+
+        if primary.backup_lba != sector_count - 1 {
+            backup.warn(GptBackupWarning::NotAtEndOfImage {
+                backup_lba: primary.backup_lba,
+                last_lba: sector_count - 1,
+            });
+        }
+
+        let header = match GptHeader::from_block_device(device, primary.backup_lba) {
+            Ok(header) => header,
+            Err(e @ ImageError::Io { .. }) => return Err(e),
+            Err(e) => {
+                backup.warn(GptBackupWarning::ReadFailed {
+                    lba: primary.backup_lba,
+                    error: e.to_string(),
+                });
+                return Ok(backup);
+            }
+        };
+
+        if !header.is_crc32_valid() {
+            backup.warn(GptBackupWarning::HeaderChecksumMismatch {
+                stored: header.crc32,
+                computed: header.computed_crc32,
+            });
+        }
+
+        // The backup header describes itself, so its current and backup LBAs are swapped relative to the primary.
+        backup.compare_field("signature", hex::encode(primary.signature), hex::encode(header.signature));
+        backup.compare_field("revision", primary.revision, header.revision);
+        backup.compare_field("header size", primary.header_size, header.header_size);
+        backup.compare_field("backup LBA", primary.current_lba, header.backup_lba);
+        backup.compare_field("current LBA", primary.backup_lba, header.current_lba);
+        backup.compare_field("first usable LBA", primary.first_usable_lba, header.first_usable_lba);
+        backup.compare_field("last usable LBA", primary.last_usable_lba, header.last_usable_lba);
+        backup.compare_field("disk GUID", primary.disk_guid, header.disk_guid);
+        backup.compare_field("partition count", primary.partition_count, header.partition_count);
+        backup.compare_field("partition entry size", primary.partition_entry_size, header.partition_entry_size);
+        backup.compare_field(
+            "partition table CRC32",
+            format!("0x{:08x}", primary.partition_entry_array_crc32),
+            format!("0x{:08x}", header.partition_entry_array_crc32),
+        );
+
+        let backup_array = match header.read_partition_entry_array(device) {
+            Ok(array) => Some(array),
+            Err(e @ ImageError::Io { .. }) => return Err(e),
+            Err(e) => {
+                backup.warn(GptBackupWarning::ReadFailed {
+                    lba: header.partition_table_lba,
+                    error: e.to_string(),
+                });
+                None
+            }
+        };
+
+        if let Some(backup_array) = backup_array {
+            let computed = crc32fast::hash(&backup_array);
+            if computed != header.partition_entry_array_crc32 {
+                backup.warn(GptBackupWarning::EntryArrayChecksumMismatch {
+                    stored: header.partition_entry_array_crc32,
+                    computed,
+                });
+            }
+
+            if header.partition_entry_size == primary.partition_entry_size {
+                if let Ok(primary_array) = primary.read_partition_entry_array(device) {
+                    let entry_size = primary.partition_entry_size as usize;
+                    for (index, (primary_entry, backup_entry)) in
+                        primary_array.chunks(entry_size).zip(backup_array.chunks(entry_size)).enumerate()
+                    {
+                        if primary_entry != backup_entry {
+                            backup.warn(GptBackupWarning::EntryMismatch { index: index as u32 });
+                        }
+                    }
+                }
+            }
+        }
+
+        backup.header = Some(header);
+        Ok(backup)
+    }
+
+    fn compare_field<T: Display + PartialEq>(&mut self, field: &'static str, primary: T, backup: T) {
+        if primary != backup {
+            self.warn(GptBackupWarning::FieldMismatch {
+                field,
+                primary: primary.to_string(),
+                backup: backup.to_string(),
+            });
+        }
+    }
+
+    fn warn(&mut self, warning: GptBackupWarning) {
+        warn!("{}", warning);
+        self.warnings.push(warning);
+    }
+}
+
+/// How the MBR of a GPT disk protects the GPT partitions from legacy tools.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MbrKind {
+    /// A single 0xEE entry covering the disk.
+    Protective,
+    /// A 0xEE entry alongside MBR entries that mirror GPT partitions.
+    Hybrid,
+    /// No usable 0xEE entry.
+    Invalid,
+}
+
+impl Display for MbrKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Protective => f.write_str("protective"),
+            Self::Hybrid => f.write_str("hybrid"),
+            Self::Invalid => f.write_str("invalid"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ProtectiveMbrWarning {
+    NoProtectiveEntry,
+    MultipleProtectiveEntries {
+        slots: Vec<usize>,
+    },
+    ProtectiveEntryStart {
+        slot: usize,
+        lba_start: u32,
+    },
+    ProtectiveEntrySize {
+        slot: usize,
+        sector_count: u32,
+        expected: u32,
+    },
+    ProtectiveEntryBootable {
+        slot: usize,
+    },
+    HybridEntryMismatch {
+        slot: usize,
+        start_lba: u64,
+        end_lba: u64,
+    },
+    HybridEntryOverlapsProtective {
+        slot: usize,
+    },
+}
+
+impl Display for ProtectiveMbrWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::NoProtectiveEntry => f.write_str("The MBR has no 0xEE protective entry"),
+            Self::MultipleProtectiveEntries { slots } => {
+                let slots: Vec<String> = slots.iter().map(|slot| (slot + 1).to_string()).collect();
+                write!(f, "The MBR has more than one 0xEE protective entry (slots {})", slots.join(", "))
+            }
+            Self::ProtectiveEntryStart { slot, lba_start } => {
+                write!(f, "The 0xEE entry in MBR slot {} starts at LBA {} instead of LBA 1", slot + 1, lba_start)
+            }
+            Self::ProtectiveEntrySize {
+                slot,
+                sector_count,
+                expected,
+            } => write!(
+                f,
+                "The 0xEE entry in MBR slot {} covers {} sectors; it should cover {} sectors or be 0xffffffff",
+                slot + 1,
+                sector_count,
+                expected
+            ),
+            Self::ProtectiveEntryBootable { slot } => {
+                write!(f, "The 0xEE entry in MBR slot {} is marked bootable", slot + 1)
+            }
+            Self::HybridEntryMismatch {
+                slot,
+                start_lba,
+                end_lba,
+            } => write!(
+                f,
+                "Hybrid MBR entry in slot {} (LBA {}-{}) does not match any GPT partition",
+                slot + 1,
+                start_lba,
+                end_lba
+            ),
+            Self::HybridEntryOverlapsProtective { slot } => {
+                write!(f, "Hybrid MBR entry in slot {} overlaps the 0xEE protective entry", slot + 1)
+            }
+        }
+    }
+}
+
+/// The classification of the MBR on a GPT disk, and any problems found with it.
+#[derive(Clone, Debug)]
+pub struct ProtectiveMbr {
+    pub kind: MbrKind,
+    pub protective_slot: Option<usize>,
+    pub warnings: Vec<ProtectiveMbrWarning>,
+}
+
+impl ProtectiveMbr {
+    /// Check the MBR of a GPT disk with `sector_count` sectors against its GPT partition entries.
+    pub fn check(boot_sector: &BootSector, gpt_entries: &[GptPartitionEntry], sector_count: u64) -> Self {
+        let mut mbr = Self {
+            kind: MbrKind::Invalid,
+            protective_slot: None,
+            warnings: Vec::new(),
+        };
+
+        let protective_slots: Vec<usize> = (0..boot_sector.partitions.len())
+            .filter(|&slot| boot_sector.partitions[slot].partition_type.code == MBR_GPT_PARTITION_TYPE)
+            .collect();
+
+        let slot = match protective_slots[..] {
+            [] => {
+                mbr.warn(ProtectiveMbrWarning::NoProtectiveEntry);
+                return mbr;
+            }
+            [slot] => slot,
+            _ => {
+                mbr.warn(ProtectiveMbrWarning::MultipleProtectiveEntries {
+                    slots: protective_slots,
+                });
+                return mbr;
+            }
+        };
+        mbr.protective_slot = Some(slot);
+
+        let protective = &boot_sector.partitions[slot];
+        if protective.lba_start as u64 != GPT_PRIMARY_HEADER_LBA {
+            mbr.warn(ProtectiveMbrWarning::ProtectiveEntryStart {
+                slot,
+                lba_start: protective.lba_start,
+            });
+            return mbr;
+        }
+
+        if protective.is_bootable() {
+            mbr.warn(ProtectiveMbrWarning::ProtectiveEntryBootable { slot });
+        }
+
+        let protective_end_lba = protective.lba_start as u64 + protective.sector_count as u64;
+        mbr.kind = MbrKind::Protective;
+        for (other_slot, entry) in boot_sector.partitions.iter().enumerate() {
+            if other_slot == slot || entry.is_empty() {
+                continue;
+            }
+
+            mbr.kind = MbrKind::Hybrid;
+            let start_lba = entry.lba_start as u64;
+            let end_lba = start_lba + (entry.sector_count as u64).max(1) - 1;
+            if start_lba < protective_end_lba && end_lba >= protective.lba_start as u64 {
+                mbr.warn(ProtectiveMbrWarning::HybridEntryOverlapsProtective { slot: other_slot });
+            }
+
+            let matches_gpt = gpt_entries
+                .iter()
+                .any(|gpt| gpt.is_used() && gpt.starting_lba == start_lba && gpt.ending_lba == end_lba);
+            if !matches_gpt {
+                mbr.warn(ProtectiveMbrWarning::HybridEntryMismatch {
+                    slot: other_slot,
+                    start_lba,
+                    end_lba,
+                });
+            }
+        }
+
+        // In a hybrid MBR the 0xEE entry only needs to cover the GPT structures, not the whole disk.
+        let expected = sector_count.saturating_sub(1).min(u32::MAX as u64) as u32;
+        if mbr.kind == MbrKind::Protective && protective.sector_count != expected && protective.sector_count != u32::MAX
+        {
+            mbr.warn(ProtectiveMbrWarning::ProtectiveEntrySize {
+                slot,
+                sector_count: protective.sector_count,
+                expected,
+            });
+        }
+
+        mbr
+    }
+
+    fn warn(&mut self, warning: ProtectiveMbrWarning) {
+        warn!("{}", warning);
+        self.warnings.push(warning);
+    }
+}
+
+pub const GPT_PREFERRED_ALIGNMENT: u64 = 1024 * 1024;
+pub const GPT_MINIMUM_ALIGNMENT: u64 = 4096;
+
+#[derive(Clone, Debug)]
+pub enum GptLayoutWarning {
+    InvertedRange {
+        index: usize,
+        starting_lba: u64,
+        ending_lba: u64,
+    },
+    OutsideUsableRange {
+        index: usize,
+        starting_lba: u64,
+        ending_lba: u64,
+    },
+    Overlap {
+        index: usize,
+        other_index: usize,
+    },
+    Misaligned {
+        index: usize,
+        starting_lba: u64,
+        alignment: u64,
+    },
+    OverlapsGptStructure {
+        index: usize,
+        structure: &'static str,
+    },
+    TableInUsableRange {
+        table_lba: u64,
+        table_end_lba: u64,
+    },
+    TableBeyondAddressableRange {
+        table_lba: u64,
+        partition_count: u32,
+        partition_entry_size: u32,
+    },
+    BeyondAddressableRange {
+        index: usize,
+        starting_lba: u64,
+    },
+    DuplicateGuid {
+        index: usize,
+        other_index: usize,
+        guid: Uuid,
+    },
+}
+
+impl GptLayoutWarning {
+    /// Whether this is advice rather than a problem. Partitions aligned to 4 KiB but not 1 MiB work on every disk.
+    pub fn is_informational(&self) -> bool {
+        matches!(
+            self,
+            Self::Misaligned {
+                alignment: GPT_PREFERRED_ALIGNMENT,
+                ..
+            }
+        )
+    }
+}
+
+impl Display for GptLayoutWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::InvertedRange {
+                index,
+                starting_lba,
+                ending_lba,
+            } => write!(
+                f,
+                "GPT partition {} ends at LBA {}, before its starting LBA {}",
+                index + 1,
+                ending_lba,
+                starting_lba
+            ),
+            Self::OutsideUsableRange {
+                index,
+                starting_lba,
+                ending_lba,
+            } => write!(
+                f,
+                "GPT partition {} (LBA {}-{}) is outside the usable LBA range",
+                index + 1,
+                starting_lba,
+                ending_lba
+            ),
+            Self::Overlap { index, other_index } => {
+                write!(f, "GPT partitions {} and {} overlap", other_index + 1, index + 1)
+            }
+            Self::Misaligned {
+                index,
+                starting_lba,
+                alignment,
+            } => write!(
+                f,
+                "GPT partition {} starting at LBA {} is not aligned to {} bytes",
+                index + 1,
+                starting_lba,
+                alignment
+            ),
+            Self::OverlapsGptStructure { index, structure } => {
+                write!(f, "GPT partition {} overlaps the {}", index + 1, structure)
+            }
+            Self::TableInUsableRange {
+                table_lba,
+                table_end_lba,
+            } => write!(f, "GPT partition table (LBA {}-{}) overlaps the usable LBA range", table_lba, table_end_lba),
+            Self::TableBeyondAddressableRange {
+                table_lba,
+                partition_count,
+                partition_entry_size,
+            } => write!(
+                f,
+                "GPT partition table at LBA {} with {} entries of {} bytes lies beyond the addressable range",
+                table_lba, partition_count, partition_entry_size
+            ),
+            Self::BeyondAddressableRange { index, starting_lba } => write!(
+                f,
+                "GPT partition {} starting at LBA {} lies beyond the addressable range",
+                index + 1,
+                starting_lba
+            ),
+            Self::DuplicateGuid {
+                index,
+                other_index,
+                guid,
+            } => write!(f, "GPT partitions {} and {} share the unique GUID {}", other_index + 1, index + 1, guid),
+        }
+    }
+}
+
+/// Check the layout of the partitions described by a GPT header and its entries.
+///
+/// `entries` must be the full entry array, as returned by [GptHeader::read_partition_entries], so that partition
+/// numbers in the warnings match the slot numbers.
+pub fn check_gpt_layout(header: &GptHeader, entries: &[GptPartitionEntry], sector_size: u32) -> Vec<GptLayoutWarning> {
+    let mut warnings = Vec::new();
+    let mut warn = |warning: GptLayoutWarning| {
+        if warning.is_informational() {
+            info!("{}", warning);
+        } else {
+            warn!("{}", warning);
+        }
+        warnings.push(warning);
+    };
+
+    let mut structures = vec![
+        ("protective MBR", 0, 0),
+        ("GPT header", header.current_lba, header.current_lba),
+        ("backup GPT header", header.backup_lba, header.backup_lba),
+    ];
+
+    let table_end_lba = (header.partition_count as u64)
+        .checked_mul(header.partition_entry_size as u64)
+        .map(|table_size| table_size.div_ceil(sector_size as u64).max(1))
+        .and_then(|table_sectors| header.partition_table_lba.checked_add(table_sectors - 1));
+    match table_end_lba {
+        Some(table_end_lba) => {
+            if header.partition_table_lba <= header.last_usable_lba && table_end_lba >= header.first_usable_lba {
+                warn(GptLayoutWarning::TableInUsableRange {
+                    table_lba: header.partition_table_lba,
+                    table_end_lba,
+                });
+            }
+            structures.insert(2, ("GPT partition table", header.partition_table_lba, table_end_lba));
+        }
+        None => warn(GptLayoutWarning::TableBeyondAddressableRange {
+            table_lba: header.partition_table_lba,
+            partition_count: header.partition_count,
+            partition_entry_size: header.partition_entry_size,
+        }),
+    }
+
+    let used: Vec<(usize, &GptPartitionEntry)> = entries.iter().enumerate().filter(|(_, e)| e.is_used()).collect();
+    for (position, &(index, entry)) in used.iter().enumerate() {
+        // An inverted entry has no range to check, but its unique GUID still has to be unique.
+        let inverted = entry.ending_lba < entry.starting_lba;
+        if inverted {
+            warn(GptLayoutWarning::InvertedRange {
+                index,
+                starting_lba: entry.starting_lba,
+                ending_lba: entry.ending_lba,
+            });
+        } else {
+            if entry.starting_lba < header.first_usable_lba || entry.ending_lba > header.last_usable_lba {
+                warn(GptLayoutWarning::OutsideUsableRange {
+                    index,
+                    starting_lba: entry.starting_lba,
+                    ending_lba: entry.ending_lba,
+                });
+            }
+
+            for &(structure, start_lba, end_lba) in structures.iter() {
+                if entry.starting_lba <= end_lba && entry.ending_lba >= start_lba {
+                    warn(GptLayoutWarning::OverlapsGptStructure { index, structure });
+                }
+            }
+
+            match entry.starting_lba.checked_mul(sector_size as u64) {
+                Some(start_offset) if !start_offset.is_multiple_of(GPT_MINIMUM_ALIGNMENT) => {
+                    warn(GptLayoutWarning::Misaligned {
+                        index,
+                        starting_lba: entry.starting_lba,
+                        alignment: GPT_MINIMUM_ALIGNMENT,
+                    })
+                }
+                Some(start_offset) if !start_offset.is_multiple_of(GPT_PREFERRED_ALIGNMENT) => {
+                    warn(GptLayoutWarning::Misaligned {
+                        index,
+                        starting_lba: entry.starting_lba,
+                        alignment: GPT_PREFERRED_ALIGNMENT,
+                    })
+                }
+                Some(_) => (),
+                None => warn(GptLayoutWarning::BeyondAddressableRange {
+                    index,
+                    starting_lba: entry.starting_lba,
+                }),
+            }
+        }
+
+        for &(other_index, other) in used[..position].iter() {
+            if !inverted
+                && other.ending_lba >= other.starting_lba
+                && entry.starting_lba <= other.ending_lba
+                && entry.ending_lba >= other.starting_lba
+            {
+                warn(GptLayoutWarning::Overlap { index, other_index });
+            }
+
+            if entry.unique_partition_guid == other.unique_partition_guid {
+                warn(GptLayoutWarning::DuplicateGuid {
+                    index,
+                    other_index,
+                    guid: entry.unique_partition_guid,
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Detect a nested GPT by looking for a header signature at LBA 1.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    for &sector_size in PROBED_SECTOR_SIZES.iter() {
+        if let Some(header_bytes) = read_probe_bytes(reader, sector_size as u64, GPT_HEADER_1_0_SIZE as usize)? {
+            if header_bytes[0..8] == GPT_HEADER_SIGNATURE {
+                let mut result = ProbeResult::new("gpt", ProbeUsage::PartitionTable, ProbeConfidence::Medium);
+                result.uuid = Some(read_mixed_endian_uuid(&header_bytes[56..72]).to_string());
+                return Ok(Some(result));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// See https://en.wikipedia.org/wiki/GUID_Partition_Table for partition types.
+pub const GPT_PARTITION_TYPES: Map<u128, &'static str> = phf_map! {
+    0x00000000000000000000000000000000u128 => "Empty",
+    0x024dee4133e711d39d690008c781f39fu128 => "MBR partition scheme",
+    0xc12a7328f81f11d2ba4b00a0c93ec93bu128 => "EFI system",
+    0x2168614864496e6f744e656564454649u128 => "BIOS boot",
+    0xd3bfe2de3daf11dfba40e3a556d89593u128 => "Intel Fast Flash",
+    0xf4019732066e4e128273346c5641494fu128 => "Sony boot",
+    0xbfbfafe7a34f448a9a5b6213eb736c22u128 => "Lenovo boot",
+    0xe3c9e3160b5c4db8817df92df00215aeu128 => "Microsoft reserved",
+    0xebd0a0a2b9e5443387c068b6b72699c7u128 => "Windows basic data",
+    0x5808c8aa7e8f42e085d2e1e90434cfb3u128 => "Windows Logical Disk Manager metadata",
+    0xaf9b60a014314f62bc683311714a69adu128 => "Windows Logical Disk Manager data",
+    0xde94bba406d14d40a16abfd50179d6acu128 => "Windows recovery environment",
+    0x37affc90ef7d4e9691c32d7ae055b174u128 => "IBM General Parallel File System",
+    0xe75caf8ff6804ceeafa3b001e56efc2du128 => "Windows Storage Spaces",
+    0x558d43c5a1ac43c0aac8d1472b2923d1u128 => "Windows Storage replica",
+    0x75894c1e3aeb11d3b7c17b03a0000000u128 => "HP-UX data",
+    0xe2a1e72832e311d6a6827b03a0000000u128 => "HP-UX service",
+    0x0fc63daf848347728e793d69d8477de4u128 => "Linux data",
+    0x8484680c952148c69c11b0720656f69eu128 => "Linux /usr (x86-64)",
+    0x4d21b016b53445c2a9fb5c16e091fd2du128 => "Linux /var",
+    0x7ec6f5573bc54acab29316ef5df639d1u128 => "Linux /var/tmp",
+    0xa19d880f05fc4d3ba006743f0f84911eu128 => "Linux RAID",
+    0x44479540f29741b29af7d131d5f0458au128 => "Linux root (x86)",
+    0x4f68bce3e8cd4db196e7fbcaf984b709u128 => "Linux root (x86-64)",
+    0x69dad7102ce44e3cb16c21a1d49abed3u128 => "Linux root (ARM 32)",
+    0xb921b0451df041c3af444c6f280d3faeu128 => "Linux root (ARM 64)",
+    0xbc13c2ff59e64262a352b275fd6f7172u128 => "Linux /boot",
+    0x0657fd6da4ab43c484e50933c84b4f4fu128 => "Linux swap",
+    0xe6d6d379f50744c2a23c238f2a3df928u128 => "Linux LVM",
+    0x933ac7e12eb44f13b8440e14e2aef915u128 => "Linux /home",
+    0x3b8f842520e04f3b907f1a25a76f98e8u128 => "Linux /srv",
+    0x7ffec5c92d0049b789413ea10a5586b7u128 => "Linux dm-crypt",
+    0xca7d7ccb63ed4c53861c1742536059ccu128 => "Linux LUKS",
+    0x8da63339000760c0c436083ac8230908u128 => "Linux reserved",
+    0x83bd6b9d7f4111dcbe0b001560b84f0fu128 => "FreeBSD boot",
+    0x516e7cb46ecf11d68ff800022d09712bu128 => "FreeBSD disklabel",
+    0x516e7cb56ecf11d68ff800022d09712bu128 => "FreeBSD swap",
+    0x516e7cb66ecf11d68ff800022d09712bu128 => "FreeBSD UFS",
+    0x516e7cb86ecf11d68ff800022d09712bu128 => "FreeBSD Vinum volume manager",
+    0x516e7cba6ecf11d68ff800022d09712bu128 => "FreeBSD ZFS",
+    0x74ba7dd9a68911e1bd0400e081286acfu128 => "FreeBSD nandfs",
+    0x48465300000011aaaa1100306543ecacu128 => "Apple HFS+",
+    0x7c3457ef000011aaaa1100306543ecacu128 => "Apple APFS container/APFS FileVault container",
+    0x55465300000011aaaa1100306543ecacu128 => "Apple UFS container",
+    0x52414944000011aaaa1100306543ecacu128 => "Apple RAID",
+    0x524149445f4f11aaaa1100306543ecacu128 => "Apple RAID (offline)",
+    0x426f6f74000011aaaa1100306543ecacu128 => "Apple recovery",
+    0x4c6162656c0011aaaa1100306543ecacu128 => "Apple label",
+    0x5265636f766511aaaa1100306543ecacu128 => "AppleTV recovery",
+    0x53746f72616711aaaa1100306543ecacu128 => "Apple Core Storage container/HFS+ FileVault container",
+    0x6a82cb451dd211b299a6080020736631u128 => "Solaris boot",
+    0x6a85cf4d1dd211b299a6080020736631u128 => "Solaris root",
+    0x6a87c46f1dd211b299a6080020736631u128 => "Solaris swap",
+    0x6a8b642b1dd211b299a6080020736631u128 => "Solaris backup",
+    0x6a898cc31dd211b299a6080020736631u128 => "Solaris /usr",
+    0x6a8ef2e91dd211b299a6080020736631u128 => "Solaris /var",
+    0x6a90ba391dd211b299a6080020736631u128 => "Solaris /home",
+    0x6a9283a51dd211b299a6080020736631u128 => "Solaris alternate sector",
+    0x6a945a3b1dd211b299a6080020736631u128 => "Solaris reserved",
+    0x6a9630d11dd211b299a6080020736631u128 => "Solaris reserved",
+    0x6a9807671dd211b299a6080020736631u128 => "Solaris reserved",
+    0x6a96237f1dd211b299a6080020736631u128 => "Solaris reserved",
+    0x6a8d2ac71dd211b299a6080020736631u128 => "Solaris reserved",
+    0x49f48d32b10e11dcb99b0019d1879648u128 => "NetBSD swap",
+    0x49f48d5ab10e11dcb99b0019d1879648u128 => "NetBSD FFS",
+    0x49f48d82b10e11dcb99b0019d1879648u128 => "NetBSD LFS",
+    0x49f48daab10e11dcb99b0019d1879648u128 => "NetBSD RAID",
+    0x2db519c4b10f11dcb99b0019d1879648u128 => "NetBSD concatenated",
+    0x2db519ecb10f11dcb99b0019d1879648u128 => "NetBSD encrypted",
+    0xfe3a2a5d4f3241a7b725accc3285a309u128 => "ChromeOS kernel",
+    0x3cb8e2023b7e47dd8a3c7ff2a13cfcecu128 => "ChromeOS root",
+    0x2e0a753d9e4843b08337b15192cb1b5eu128 => "ChromeOS reserved",
+    0x5dfbf5f428484bacaa5e0d9a20b745a6u128 => "CoreOS /usr",
+    0x3884dd4185824404b9a8e9b84f2df50eu128 => "CoreOS resizeable root",
+    0xc95dc21adf0e43408d7b26cbfa9a03e0u128 => "CoreOS reserved",
+    0xbe9067b9ea494f15b4f6f36f8c9e1818u128 => "CoreOS root RAID",
+    0x424653313ba310f1802a4861696b7521u128 => "Haiku BFS",
+    0x85d5e45e237c11e1b4b3e89a8f7fc3a7u128 => "MidnightBSD boot",
+    0x85d5e45a237c11e1b4b3e89a8f7fc3a7u128 => "MidnightBSD data",
+    0x85d5e45b237c11e1b4b3e89a8f7fc3a7u128 => "MidnightBSD swap",
+    0x0394ef8b237e11e1b4b3e89a8f7fc3a7u128 => "MidnightBSD UFS",
+    0x85d5e45c237c11e1b4b3e89a8f7fc3a7u128 => "MidnightBSD Vinum volume manager",
+    0x85d5e45d237c11e1b4b3e89a8f7fc3a7u128 => "MidnightBSD ZFS",
+    0x45b0969e9b034f30b4c6b4b80ceff106u128 => "Ceph journal",
+    0x45b0969e9b034f30b4c65ec00ceff106u128 => "Ceph dm-crypt journal",
+    0x4fbd7e299d2541b8afd0062c0ceff05du128 => "Ceph OSD",
+    0x4fbd7e299d2541b8afd05ec00ceff05du128 => "Ceph dm-crypt OSD",
+    0x89c57f982fe54dc089c1f3ad0ceff2beu128 => "Ceph disk in creation",
+    0x89c57f982fe54dc089c15ec00ceff2beu128 => "Ceph dm-crypt disk in creation",
+    0xcafecafe9b034f30b4c6b4b80ceff106u128 => "Ceph block",
+    0x30cd0809c2b2499c88792d6b78529876u128 => "Ceph block DB",
+    0x5ce17fce40874169b7ff056cc58473f9u128 => "Ceph block write-ahead log",
+    0xfb3aabf9d25f47ccbf5e721d1816496bu128 => "Ceph dm-crypt lockbox",
+    0x4fbd7e298ae04982bf9d5a8d867af560u128 => "Ceph multipath OSD",
+    0x45b0969e8ae04982bf9d5a8d867af560u128 => "Ceph multipath journal",
+    0xcafecafe8ae04982bf9d5a8d867af560u128 => "Ceph multipath block",
+    0x7f4a666a16f347a28445152ef4d03f6cu128 => "Ceph multipath block",
+    0xec6d6385e34645dcbe91da2a7c8b3261u128 => "Ceph multipath block DB",
+    0x01b41e1b002a453c9f1788793989ff8fu128 => "Ceph multipath block write-ahead log",
+    0xcafecafe9b034f30b4c65ec00ceff106u128 => "Ceph dm-crypt block",
+    0x93b0052d02d94d8aa43b33a3ee4dfbc3u128 => "Ceph dm-crypt block DB",
+    0x306e86834fe24330b7c000a917c16966u128 => "Ceph dm-crypt block write-ahead log",
+    0x45b0969e9b034f30b4c635865ceff106u128 => "Ceph dm-crypt LUKS journal",
+    0xcafecafe9b034f30b4c635865ceff106u128 => "Ceph dm-crypt LUKS block",
+    0x166418dac4694022adf4b30afd37f176u128 => "Ceph dm-crypt LUKS block DB",
+    0x86a32090364740b9bbbd38d8c573aa86u128 => "Ceph dm-crypt LUKS block write-ahead log",
+    0x4fbd7e299d2541b8afd035865ceff05du128 => "Ceph dm-crypt LUKS OSD",
+    0x824cc7a036a811e3890a952519ad3f61u128 => "OpenBSD data",
+    0xcef5a9ad73bc460189f3cdeeeee321a1u128 => "QNX Power-Safe",
+    0xc91818f9802547af89d2f030d7000c2cu128 => "Plan9",
+    0x9d27538040ad11dbbf97000c2911d1b8u128 => "VMware ESX vmkcore",
+    0xaa31e02a400f11db9590000c2911d1b8u128 => "VMware ESX VMFS",
+    0x9198effc31c011db8f78000c2911d1b8u128 => "VMware ESX reserved",
+    0x2568845d23324675bc398fa5a4748d15u128 => "Android x86 bootloader",
+    0x114eaffe15524022b26e9b053604cf84u128 => "Android x86 bootloader2",
+    0x49a4d17f93a345c1a0def50b2ebe2599u128 => "Android x86 boot",
+    0x4177c7229e924aab864443502bfd5506u128 => "Android x86 recovery",
+    0xef32a33ba409486c91419ffb711f6266u128 => "Android x86 misc",
+    0x20ac26be20b711e384c56cfdb94711e9u128 => "Android x86 metadata",
+    0x38f428e6d326425d91406e0ea133647cu128 => "Android x86 system",
+    0xa893ef21e428470a9e550668fd91a2d9u128 => "Android x86 cache",
+    0xdc76dda95ac1491caf42a82591580c0du128 => "Android x86 data",
+    0xebc597d020534b158b64e0aac75f4db1u128 => "Android x86 persistent",
+    0xc5a0aeec13ea11e5a1b1001e67ca0c3cu128 => "Android x86 vendor",
+    0xbd59408b4514490dbf129878d963f378u128 => "Android x86 config",
+    0x8f68cc74c5e548dabe91a0c8c15e9c80u128 => "Android x86 factory",
+    0x9fdaa6ef4b3f40d2ba8dbff16bfb887bu128 => "Android x86 factory alt",
+    0x767941d0208511e3ad3b6cfdb94711e9u128 => "Android x86 fastboot/tertiary",
+    0xac6d7924eb714df8b48de267b27148ffu128 => "Android x86 OEM",
+    0x19a710a2b3ca11e4b02610604b889dcfu128 => "Android ARM metadata",
+    0x193d1ea4b3ca11e4b07510604b889dcfu128 => "Android ARM EXT",
+    0x7412f7d5a1564b1381dc867174929325u128 => "Open Network Install Environment boot",
+    0xd4e6e2cd446946f3b5cb1bff57afc149u128 => "Open Network Install Environment config",
+    0x9e1a2d38c6124316aa268b49521e5a8bu128 => "PowerPC PReP boot",
+    0x734e5afef61a11e6bc6492361f002671u128 => "Atari TOS data",
+    0x8c8f8effac954770814a21994f2dbc8fu128 => "VeraCrypt",
+    0x90b6ff38b98f4358a21f48f35b4a8ad3u128 => "ArcaOS type 1",
+    0x7c5222bd8f5d40879c00bf9843c7b58cu128 => "Storage Performance Development Kit block",
+    0x4778ed65bf4245fa9c5b287a1dc4aab1u128 => "Barebox state",
+    0xb6fa30da92d24a9a96f1871ec6486200u128 => "SoftRAID status",
+    0x2e31346519b9463f81268a7993773801u128 => "SoftRAID scratch",
+    0xfa709c7e65b14593bfd5e71d61de9b02u128 => "SoftRAID volume",
+    0xbbba6df5f46f4a898f598765b2727503u128 => "SoftRAID cache",
+};
+
+const MICROSOFT_BASIC_DATA_TYPE: u128 = 0xebd0a0a2b9e5443387c068b6b72699c7;
+const CHROMEOS_KERNEL_TYPE: u128 = 0xfe3a2a5d4f3241a7b725accc3285a309;
+
+// Partition types from the Discoverable Partitions Specification, which defines the systemd attribute bits.
+const SYSTEMD_PARTITION_TYPES: &[u128] = &[
+    // Root partitions: Alpha, ARC, ARM, ARM64, IA-64, LoongArch64, MIPS LE, MIPS64 LE, PA-RISC, PPC, PPC64 BE,
+    // PPC64 LE, RISC-V 32, RISC-V 64, s390, s390x, TILE-Gx, x86, x86-64.
+    0x6523f8ae3eb14e2aa05a18b695ae656f,
+    0xd27f46ed29194cb8bd259531f3c16534,
+    0x69dad7102ce44e3cb16c21a1d49abed3,
+    0xb921b0451df041c3af444c6f280d3fae,
+    0x993d8d3df80e4225855a9daf8ed7ea97,
+    0x77055800792c4f94b39a98c91b762bb6,
+    0x37c58c8ad9134156a25f48b1b64e07f0,
+    0x700bda437a344507b179eeb93d7a7ca3,
+    0x1aacdb3b54444138bd9ee5c2239b2346,
+    0x1de3f1effa9847b58dcd4a860a654d78,
+    0x912ade1da83949138964a10eee08fbd2,
+    0xc31c45e63f39412e80fb4809c4980599,
+    0x60d5a7fe8e7d435cb7143dd8162144e1,
+    0x72ec70a6cf7440e6bd494bda08e8f224,
+    0x08a7acea624c4a2091e86e0fa67d23f9,
+    0x5eead9a9fe094a1ea1d7520d00531306,
+    0xc50cdd7038624cc390e1809a8c93ee2c,
+    0x44479540f29741b29af7d131d5f0458a,
+    0x4f68bce3e8cd4db196e7fbcaf984b709,
+    // /usr partitions, for the same architectures in the same order.
+    0xe18cf08c33ec4c0d8246c6c6fb3da024,
+    0x7978a68363164922bbee38bff5a2fecc,
+    0x7d0359a302b34f0a865c654403e70625,
+    0xb0e01050ee5f4390949a9101b17104e9,
+    0x4301d2a64e3b4b2abb949e0b2c4225ea,
+    0xe611c702575c4cbe9a46434fa0bf7e3f,
+    0x0f4868e999524706979f3ed3a473e947,
+    0xc97c1f32ba0640b49f22236061b08aa8,
+    0xdc4a448069174262a4ecdb9384949f25,
+    0x7d14fec5cc71415d9d6c06bf0b3c3eaf,
+    0x2c9739e2f06846b39fd001c5a9afbcca,
+    0x15bb03af77e74d4ab12bc0d084f7491c,
+    0xb933fb225c3f4f91af90e2bb0fa50702,
+    0xbeaec34b8442439ba40b984381ed097d,
+    0xcd0f869bd0fb4ca0b1419ea87cc78d66,
+    0x8a4f577050aa4ed3874a99b710db6fea,
+    0x55497029c7c144ccaa39815ed1558630,
+    0x75250d768cc6458ebd66bd47cc81a812,
+    0x8484680c952148c69c11b0720656f69e,
+    // /home, /srv, /var, /var/tmp, per-user home and generic Linux data partitions.
+    0x933ac7e12eb44f13b8440e14e2aef915,
+    0x3b8f842520e04f3b907f1a25a76f98e8,
+    0x4d21b016b53445c2a9fb5c16e091fd2d,
+    0x7ec6f5573bc54acab29316ef5df639d1,
+    0x773f91ef66d449b5bd83d683bf40ad16,
+    0x0fc63daf848347728e793d69d8477de4,
+];
+
+// EFI system, XBOOTLDR and swap partitions, for which the Discoverable Partitions Specification only defines bit 63.
+const SYSTEMD_NO_AUTO_PARTITION_TYPES: &[u128] =
+    &[0xc12a7328f81f11d2ba4b00a0c93ec93b, 0xbc13c2ff59e64262a352b275fd6f7172, 0x0657fd6da4ab43c484e50933c84b4f4f];
+
+/// Describe the set attribute bits of a GPT partition entry.
+///
+/// Bits 48-63 are interpreted according to the partition type; unknown bits are reported by number.
+pub fn describe_gpt_attributes(partition_type: &Uuid, attributes: u64) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut known = 0u64;
+    let mut flag = |mask: u64, name: &str, names: &mut Vec<String>| {
+        known |= mask;
+        if attributes & mask != 0 {
+            names.push(name.to_string());
+        }
+    };
+
+    flag(GPT_ATTRIBUTE_PLATFORM_REQUIRED, "platform required", &mut names);
+    flag(GPT_ATTRIBUTE_EFI_IGNORE, "EFI ignore", &mut names);
+    flag(GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE, "legacy BIOS bootable", &mut names);
+
+    match partition_type.as_u128() {
+        MICROSOFT_BASIC_DATA_TYPE => {
+            flag(1 << 60, "read-only", &mut names);
+            flag(1 << 61, "shadow copy", &mut names);
+            flag(1 << 62, "hidden", &mut names);
+            flag(1 << 63, "no automount", &mut names);
+        }
+        CHROMEOS_KERNEL_TYPE => {
+            // Priority and tries are always reported, since zero is meaningful for both.
+            known |= 0x1ff << 48;
+            names.push(format!("priority {}", (attributes >> 48) & 0xf));
+            names.push(format!("tries {}", (attributes >> 52) & 0xf));
+            if attributes & (1 << 56) != 0 {
+                names.push("successful".to_string());
+            }
+        }
+        guid if SYSTEMD_PARTITION_TYPES.contains(&guid) => {
+            flag(1 << 59, "growfs", &mut names);
+            flag(1 << 60, "read-only", &mut names);
+            flag(1 << 63, "no automount", &mut names);
+        }
+        guid if SYSTEMD_NO_AUTO_PARTITION_TYPES.contains(&guid) => {
+            flag(1 << 63, "no automount", &mut names);
+        }
+        _ => (),
+    }
+
+    for bit in 0..64 {
+        let mask = 1u64 << bit;
+        if attributes & mask != 0 && known & mask == 0 {
+            if mask & GPT_ATTRIBUTE_TYPE_SPECIFIC_MASK != 0 {
+                names.push(format!("type-specific bit {}", bit));
+            } else {
+                names.push(format!("reserved bit {}", bit));
+            }
+        }
+    }
+
+    names
+}
+
+pub struct GptPartitionEntry {
+    pub partition_type: Uuid,
+    pub unique_partition_guid: Uuid,
+    pub starting_lba: u64,
+    pub ending_lba: u64,
+    pub attributes: u64,
+    pub name: [u8; 72],
+}
+
+fn read_mixed_endian_uuid(data: &[u8]) -> Uuid {
+    let part1 = u32::from_le_bytes(data[0..4].try_into().unwrap());
+    let part2 = u16::from_le_bytes(data[4..6].try_into().unwrap());
+    let part3 = u16::from_le_bytes(data[6..8].try_into().unwrap());
+    let part4 = &data[8..16];
+
+    Uuid::from_fields(part1, part2, part3, part4).unwrap()
+}
+
+impl GptPartitionEntry {
+    /// Decode the UTF-16LE partition name, stopping at the first NUL.
+    pub fn get_name(&self) -> String {
+        let units: Vec<u16> = self
+            .name
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|&unit| unit != 0)
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+
+    pub fn is_used(&self) -> bool {
+        !self.partition_type.is_nil()
+    }
+
+    pub fn get_attribute_names(&self) -> Vec<String> {
+        describe_gpt_attributes(&self.partition_type, self.attributes)
+    }
+
+    pub fn get_start_offset<R: Read + Seek>(&self, device: &BlockDevice<R>) -> Result<u64, ImageError> {
+        device.lba_to_offset(self.starting_lba)
+    }
+
+    pub fn get_size<R: Read + Seek>(&self, device: &BlockDevice<R>) -> Result<u64, ImageError> {
+        device.lba_to_offset(self.ending_lba.saturating_add(1).saturating_sub(self.starting_lba))
+    }
+
+    pub fn get_partition_reader<'a, R: Read + Seek>(
+        &self,
+        device: &'a mut BlockDevice<R>,
+    ) -> Result<PartitionReader<&'a mut BlockDevice<R>>, ImageError> {
+        let start = self.get_start_offset(device)?;
+        let length = self.get_size(device)?;
+        Ok(PartitionReader::new(device, start, length))
+    }
+
+    pub fn new<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Self, ImageError> {
+        let mut partition_entry_bytes: [u8; 128] = [0; 128];
+        read_exact_at(reader, offset, &mut partition_entry_bytes)?;
+        Ok(Self::from_bytes(&partition_entry_bytes))
+    }
+
+    /// Parse an entry from the first 128 bytes of `data`.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let partition_type = read_mixed_endian_uuid(&data[0..16]);
+        let unique_partition_guid = read_mixed_endian_uuid(&data[16..32]);
+        let starting_lba = u64::from_le_bytes(data[32..40].try_into().unwrap());
+        let ending_lba = u64::from_le_bytes(data[40..48].try_into().unwrap());
+        let attributes = u64::from_le_bytes(data[48..56].try_into().unwrap());
+        let name = data[56..128].try_into().unwrap();
+
+        Self {
+            partition_type,
+            unique_partition_guid,
+            starting_lba,
+            ending_lba,
+            attributes,
+            name,
+        }
+    }
+}
+
+impl Display for GptPartitionEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let partition_type_name = GPT_PARTITION_TYPES.get(&self.partition_type.as_u128()).unwrap_or(&"Unknown");
+        write!(
+            f,
+            "Partition Type: {} ({})\nPartition GUID: {}\nStarting LBA: {}\nEnding LBA: {}\nAttributes: 0x{:016x}",
+            self.partition_type,
+            partition_type_name,
+            self.unique_partition_guid,
+            self.starting_lba,
+            self.ending_lba,
+            self.attributes,
+        )?;
+
+        let attribute_names = self.get_attribute_names();
+        if !attribute_names.is_empty() {
+            write!(f, " ({})", attribute_names.join(", "))?;
+        }
+
+        write!(f, "\nName: {}", self.get_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const IMAGE_SECTORS: u64 = 128;
+    const BACKUP_TABLE_LBA: u64 = IMAGE_SECTORS - 33;
+
+    fn write_header(image: &mut [u8], current_lba: u64, backup_lba: u64, table_lba: u64, array_crc32: u32) {
+        let pos = current_lba as usize * 512;
+        let header = &mut image[pos..pos + GPT_HEADER_1_0_SIZE as usize];
+        header[0..8].copy_from_slice(&GPT_HEADER_SIGNATURE);
+        header[8..12].copy_from_slice(&GPT_REVISION_1_0.to_le_bytes());
+        header[12..16].copy_from_slice(&GPT_HEADER_1_0_SIZE.to_le_bytes());
+        header[24..32].copy_from_slice(&current_lba.to_le_bytes());
+        header[32..40].copy_from_slice(&backup_lba.to_le_bytes());
+        header[40..48].copy_from_slice(&34u64.to_le_bytes());
+        header[48..56].copy_from_slice(&(BACKUP_TABLE_LBA - 1).to_le_bytes());
+        header[72..80].copy_from_slice(&table_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&array_crc32.to_le_bytes());
+        let header_crc32 = crc32fast::hash(header);
+        header[16..20].copy_from_slice(&header_crc32.to_le_bytes());
+    }
+
+    fn build_gpt_image() -> Vec<u8> {
+        let mut image = vec![0u8; IMAGE_SECTORS as usize * 512];
+
+        let entry = &mut image[2 * 512..2 * 512 + 128];
+        entry[0..16].copy_from_slice(&[
+            0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b,
+        ]);
+        entry[32..40].copy_from_slice(&34u64.to_le_bytes());
+        entry[40..48].copy_from_slice(&61u64.to_le_bytes());
+        let array = image[2 * 512..2 * 512 + 128 * 128].to_vec();
+        let array_crc32 = crc32fast::hash(&array);
+        let backup_pos = BACKUP_TABLE_LBA as usize * 512;
+        image[backup_pos..backup_pos + array.len()].copy_from_slice(&array);
+
+        write_header(&mut image, 1, IMAGE_SECTORS - 1, 2, array_crc32);
+        write_header(&mut image, IMAGE_SECTORS - 1, 1, BACKUP_TABLE_LBA, array_crc32);
+
+        image
+    }
+
+    fn read_backup(image: Vec<u8>) -> GptBackup {
+        let mut device = BlockDevice::new(Cursor::new(image));
+        let primary = GptHeader::from_block_device(&mut device, GPT_PRIMARY_HEADER_LBA).unwrap();
+        GptBackup::read(&mut device, &primary).unwrap()
+    }
+
+    #[test]
+    fn validate_crc32s() {
+        let mut device = BlockDevice::new(Cursor::new(build_gpt_image()));
+        let header = GptHeader::from_block_device(&mut device, GPT_PRIMARY_HEADER_LBA).unwrap();
+        assert!(header.is_crc32_valid());
+        header.validate(&mut device).unwrap();
+
+        let mut image = build_gpt_image();
+        image[2 * 512 + 56] = b'X';
+        let mut device = BlockDevice::new(Cursor::new(image));
+        let header = GptHeader::from_block_device(&mut device, GPT_PRIMARY_HEADER_LBA).unwrap();
+        assert!(header.is_crc32_valid());
+        assert!(matches!(
+            header.validate(&mut device),
+            Err(ImageError::ChecksumMismatch { ref structure, .. }) if structure == "GPT partition entry array"
+        ));
+
+        let mut image = build_gpt_image();
+        image[512 + 40] = 35;
+        let mut device = BlockDevice::new(Cursor::new(image));
+        let header = GptHeader::from_block_device(&mut device, GPT_PRIMARY_HEADER_LBA).unwrap();
+        assert!(!header.is_crc32_valid());
+        assert!(header.validate(&mut device).is_err());
+    }
+
+    #[test]
+    fn backup_matches_primary() {
+        let backup = read_backup(build_gpt_image());
+        assert!(backup.warnings.is_empty(), "{:?}", backup.warnings);
+        assert_eq!(backup.header.unwrap().partition_table_lba, BACKUP_TABLE_LBA);
+    }
+
+    #[test]
+    fn backup_differences_are_reported() {
+        let mut image = build_gpt_image();
+        let entry_pos = BACKUP_TABLE_LBA as usize * 512;
+        image[entry_pos + 40] = 60;
+        write_header(&mut image, IMAGE_SECTORS - 1, 2, BACKUP_TABLE_LBA, 0);
+
+        let backup = read_backup(image);
+        assert!(backup
+            .warnings
+            .iter()
+            .any(|w| matches!(w, GptBackupWarning::FieldMismatch { field, .. } if *field == "backup LBA")));
+        assert!(backup.warnings.iter().any(|w| matches!(w, GptBackupWarning::EntryArrayChecksumMismatch { .. })));
+        assert!(backup.warnings.iter().any(|w| matches!(w, GptBackupWarning::EntryMismatch { index: 0 })));
+    }
+
+    #[test]
+    fn truncated_image_is_reported() {
+        let mut image = build_gpt_image();
+        image.truncate(100 * 512);
+
+        let backup = read_backup(image);
+        assert!(backup.header.is_none());
+        assert!(matches!(
+            backup.warnings[..],
+            [GptBackupWarning::Truncated {
+                backup_lba: 127,
+                sector_count: 100
+            }]
+        ));
+    }
+
+    #[test]
+    fn decode_attributes_and_name() {
+        let chromeos_kernel = Uuid::from_u128(CHROMEOS_KERNEL_TYPE);
+        let attributes = GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE | (2 << 48) | (1 << 52) | (1 << 56);
+        assert_eq!(
+            describe_gpt_attributes(&chromeos_kernel, attributes),
+            vec!["legacy BIOS bootable", "priority 2", "tries 1", "successful"]
+        );
+
+        let basic_data = Uuid::from_u128(MICROSOFT_BASIC_DATA_TYPE);
+        assert_eq!(describe_gpt_attributes(&basic_data, (1 << 62) | (1 << 63)), vec!["hidden", "no automount"]);
+
+        let linux_data = Uuid::from_u128(0x0fc63daf848347728e793d69d8477de4);
+        assert_eq!(
+            describe_gpt_attributes(&linux_data, (1 << 3) | (1 << 59) | (1 << 58)),
+            vec!["growfs", "reserved bit 3", "type-specific bit 58"]
+        );
+
+        let usr_arm64 = Uuid::from_u128(0xb0e01050ee5f4390949a9101b17104e9);
+        assert_eq!(describe_gpt_attributes(&usr_arm64, 1 << 60), vec!["read-only"]);
+
+        let swap = Uuid::from_u128(0x0657fd6da4ab43c484e50933c84b4f4f);
+        assert_eq!(describe_gpt_attributes(&swap, (1 << 60) | (1 << 63)), vec!["no automount", "type-specific bit 60"]);
+
+        let mut name = [0u8; 72];
+        for (i, unit) in "Bôot".encode_utf16().enumerate() {
+            name[2 * i..2 * i + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        let entry = GptPartitionEntry {
+            partition_type: linux_data,
+            unique_partition_guid: Uuid::nil(),
+            starting_lba: 34,
+            ending_lba: 61,
+            attributes: 0,
+            name,
+        };
+        assert_eq!(entry.get_name(), "Bôot");
+        assert!(entry.to_string().ends_with("\nName: Bôot"));
+    }
+
+    fn check_mbr(mbr_entries: &[(u8, u32, u32)]) -> ProtectiveMbr {
+        let mut image = build_gpt_image();
+        for (slot, &(partition_type, lba_start, sector_count)) in mbr_entries.iter().enumerate() {
+            let entry = &mut image[446 + 16 * slot..446 + 16 * (slot + 1)];
+            entry[4] = partition_type;
+            entry[8..12].copy_from_slice(&lba_start.to_le_bytes());
+            entry[12..16].copy_from_slice(&sector_count.to_le_bytes());
+        }
+
+        let mut device = BlockDevice::new(Cursor::new(image));
+        let boot_sector = BootSector::from_disk_image(&mut device, 0).unwrap();
+        let header = GptHeader::from_block_device(&mut device, GPT_PRIMARY_HEADER_LBA).unwrap();
+        let entries = header.read_partition_entries(&mut device).unwrap();
+        ProtectiveMbr::check(&boot_sector, &entries, IMAGE_SECTORS)
+    }
+
+    #[test]
+    fn classify_protective_mbr() {
+        let mbr = check_mbr(&[(0, 0, 0), (0xee, 1, IMAGE_SECTORS as u32 - 1)]);
+        assert_eq!(mbr.kind, MbrKind::Protective);
+        assert_eq!(mbr.protective_slot, Some(1));
+        assert!(mbr.warnings.is_empty());
+
+        let mbr = check_mbr(&[(0xee, 1, 0xffffffff)]);
+        assert_eq!(mbr.kind, MbrKind::Protective);
+        assert!(mbr.warnings.is_empty());
+
+        let mbr = check_mbr(&[(0xee, 1, 10)]);
+        assert!(matches!(mbr.warnings[..], [ProtectiveMbrWarning::ProtectiveEntrySize { expected: 127, .. }]));
+
+        let mbr = check_mbr(&[(0xee, 1, 33), (0x0c, 34, 28), (0x83, 70, 10)]);
+        assert_eq!(mbr.kind, MbrKind::Hybrid);
+        assert!(matches!(mbr.warnings[..], [ProtectiveMbrWarning::HybridEntryMismatch { slot: 2, .. }]));
+
+        let mbr = check_mbr(&[(0x83, 1, 100)]);
+        assert_eq!(mbr.kind, MbrKind::Invalid);
+    }
+
+    #[test]
+    fn check_layout() {
+        let mut device = BlockDevice::new(Cursor::new(build_gpt_image()));
+        let header = GptHeader::from_block_device(&mut device, GPT_PRIMARY_HEADER_LBA).unwrap();
+        let entry = |starting_lba: u64, ending_lba: u64, guid: u128| GptPartitionEntry {
+            partition_type: Uuid::from_u128(MICROSOFT_BASIC_DATA_TYPE),
+            unique_partition_guid: Uuid::from_u128(guid),
+            starting_lba,
+            ending_lba,
+            attributes: 0,
+            name: [0; 72],
+        };
+
+        // 4 KiB-aligned but not 1 MiB-aligned, and fully inside the usable range.
+        let warnings = check_gpt_layout(&header, &[entry(40, 47, 1)], 512);
+        assert!(matches!(
+            warnings[..],
+            [GptLayoutWarning::Misaligned {
+                alignment: GPT_PREFERRED_ALIGNMENT,
+                ..
+            }]
+        ));
+        assert!(warnings[0].is_informational());
+
+        let warnings = check_gpt_layout(&header, &[entry(40, 47, 3), entry(47, 40, 3)], 512);
+        assert!(matches!(
+            warnings[1..],
+            [
+                GptLayoutWarning::InvertedRange { index: 1, .. },
+                GptLayoutWarning::DuplicateGuid {
+                    index: 1,
+                    other_index: 0,
+                    ..
+                }
+            ]
+        ));
+
+        let warnings = check_gpt_layout(&header, &[entry(2048, 4095, 1)], 4096);
+        assert!(matches!(warnings[..], [GptLayoutWarning::OutsideUsableRange { .. }]));
+
+        let entries = [entry(8, 47, 1), entry(47, 40, 2), entry(40, 55, 1)];
+        let warnings: Vec<GptLayoutWarning> = check_gpt_layout(&header, &entries, 512)
+            .into_iter()
+            .filter(|warning| !matches!(warning, GptLayoutWarning::Misaligned { .. }))
+            .collect();
+        assert_eq!(warnings.len(), 5, "{:?}", warnings);
+        assert!(matches!(warnings[0], GptLayoutWarning::OutsideUsableRange { index: 0, .. }));
+        assert!(matches!(
+            warnings[1],
+            GptLayoutWarning::OverlapsGptStructure {
+                index: 0,
+                structure: "GPT partition table"
+            }
+        ));
+        assert!(matches!(warnings[2], GptLayoutWarning::InvertedRange { index: 1, .. }));
+        assert!(matches!(
+            warnings[3],
+            GptLayoutWarning::Overlap {
+                index: 2,
+                other_index: 0
+            }
+        ));
+        assert!(matches!(
+            warnings[4],
+            GptLayoutWarning::DuplicateGuid {
+                index: 2,
+                other_index: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn check_layout_beyond_addressable_range() {
+        let mut device = BlockDevice::new(Cursor::new(build_gpt_image()));
+        let mut header = GptHeader::from_block_device(&mut device, GPT_PRIMARY_HEADER_LBA).unwrap();
+        header.partition_table_lba = u64::MAX;
+        header.last_usable_lba = u64::MAX;
+        let entry = GptPartitionEntry {
+            partition_type: Uuid::from_u128(MICROSOFT_BASIC_DATA_TYPE),
+            unique_partition_guid: Uuid::from_u128(1),
+            starting_lba: u64::MAX / 2,
+            ending_lba: u64::MAX / 2,
+            attributes: 0,
+            name: [0; 72],
+        };
+
+        let warnings = check_gpt_layout(&header, &[entry], 512);
+        assert!(matches!(
+            warnings[..],
+            [
+                GptLayoutWarning::TableBeyondAddressableRange {
+                    table_lba: u64::MAX,
+                    ..
+                },
+                GptLayoutWarning::BeyondAddressableRange { index: 0, .. }
+            ]
+        ));
+    }
+}
@@ -0,0 +1,976 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+use std::{
+    collections::{HashSet, VecDeque},
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    fat::FatBootSector,
+    probe::{decode_label, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const ISO_SECTOR_SIZE: u64 = 2048;
+pub const ISO_VOLUME_DESCRIPTOR_LBA: u64 = 16;
+pub const ISO_STANDARD_ID: &[u8; 5] = b"CD001";
+
+pub const ISO_DESCRIPTOR_BOOT_RECORD: u8 = 0;
+pub const ISO_DESCRIPTOR_PRIMARY: u8 = 1;
+pub const ISO_DESCRIPTOR_SUPPLEMENTARY: u8 = 2;
+pub const ISO_DESCRIPTOR_PARTITION: u8 = 3;
+pub const ISO_DESCRIPTOR_TERMINATOR: u8 = 255;
+
+pub const ISO_FLAG_HIDDEN: u8 = 0x01;
+pub const ISO_FLAG_DIRECTORY: u8 = 0x02;
+
+pub const EL_TORITO_SYSTEM_ID: &str = "EL TORITO SPECIFICATION";
+pub const EL_TORITO_PLATFORM_X86: u8 = 0x00;
+pub const EL_TORITO_PLATFORM_POWERPC: u8 = 0x01;
+pub const EL_TORITO_PLATFORM_MAC: u8 = 0x02;
+pub const EL_TORITO_PLATFORM_EFI: u8 = 0xef;
+pub const EL_TORITO_BOOTABLE: u8 = 0x88;
+pub const EL_TORITO_VIRTUAL_SECTOR_SIZE: u64 = 512;
+
+// Guards against malformed images with descriptor sets, directories or continuation chains that never end.
+const ISO_MAX_VOLUME_DESCRIPTORS: u64 = 64;
+const ISO_MAX_DIRECTORY_SIZE: u32 = 16 * 1024 * 1024;
+pub const ISO_MAX_DIRECTORIES_SEARCHED: usize = 4096;
+const SUSP_MAX_CONTINUATIONS: usize = 16;
+const EL_TORITO_ENTRY_SIZE: usize = 32;
+
+pub fn get_el_torito_platform_name(platform_id: u8) -> &'static str {
+    match platform_id {
+        EL_TORITO_PLATFORM_X86 => "x86 BIOS",
+        EL_TORITO_PLATFORM_POWERPC => "PowerPC",
+        EL_TORITO_PLATFORM_MAC => "Mac",
+        EL_TORITO_PLATFORM_EFI => "EFI",
+        _ => "Unknown",
+    }
+}
+
+pub fn get_el_torito_emulation_name(media_type: u8) -> &'static str {
+    match media_type & 0x0f {
+        0 => "no emulation",
+        1 => "1.2M floppy",
+        2 => "1.44M floppy",
+        3 => "2.88M floppy",
+        4 => "hard disk",
+        _ => "unknown emulation",
+    }
+}
+
+/// Decode a big-endian UCS-2 Joliet string, dropping the padding.
+fn decode_ucs2(data: &[u8]) -> String {
+    let units: Vec<u16> = data.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+    String::from_utf16_lossy(&units).trim_end_matches([' ', '\0']).to_string()
+}
+
+fn decode_text(data: &[u8], joliet: bool) -> Option<String> {
+    if joliet {
+        Some(decode_ucs2(data)).filter(|text| !text.is_empty())
+    } else {
+        decode_label(data)
+    }
+}
+
+fn both_endian_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes(data[0..4].try_into().unwrap())
+}
+
+fn both_endian_u16(data: &[u8]) -> u16 {
+    u16::from_le_bytes(data[0..2].try_into().unwrap())
+}
+
+fn timezone(offset: u8) -> Option<FixedOffset> {
+    // In 15-minute intervals from GMT.
+    FixedOffset::east_opt(offset as i8 as i32 * 15 * 60)
+}
+
+/// Decode a 17-byte volume descriptor date ("YYYYMMDDHHMMSScc" plus a time zone), or `None` if it is unset.
+fn decode_descriptor_date(data: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let digits = std::str::from_utf8(&data[0..16]).ok()?;
+    let field = |start: usize, end: usize| digits.get(start..end)?.parse::<u32>().ok();
+    let year = field(0, 4)?;
+    if year == 0 {
+        return None;
+    }
+
+    let naive = NaiveDate::from_ymd_opt(year as i32, field(4, 6)?, field(6, 8)?)?.and_hms_milli_opt(
+        field(8, 10)?,
+        field(10, 12)?,
+        field(12, 14)?,
+        field(14, 16)? * 10,
+    )?;
+    timezone(data[16])?.from_local_datetime(&naive).single()
+}
+
+/// Decode a 7-byte directory record date (years since 1900, month, day, hour, minute, second, time zone).
+fn decode_record_date(data: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let naive = NaiveDate::from_ymd_opt(1900 + data[0] as i32, data[1] as u32, data[2] as u32)?.and_hms_opt(
+        data[3] as u32,
+        data[4] as u32,
+        data[5] as u32,
+    )?;
+    timezone(data[6])?.from_local_datetime(&naive).single()
+}
+
+/// Rock Ridge attributes decoded from a directory record's System Use Sharing Protocol (SUSP) entries.
+#[derive(Clone, Debug, Default)]
+pub struct RockRidgeEntry {
+    // Every SUSP entry signature seen, such as "PX" or "NM", in order.
+    pub signatures: Vec<String>,
+    pub name: Option<String>,
+    pub mode: Option<u32>,
+    pub links: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub symlink_target: Option<String>,
+    // Extension identifiers from "ER" entries, such as "RRIP_1991A"; usually only on the root directory.
+    pub extensions: Vec<String>,
+    // Bytes to skip at the start of every system use area, from the root directory's "SP" entry.
+    pub susp_skip: Option<u8>,
+}
+
+impl RockRidgeEntry {
+    /// Parse the SUSP entries in a system use area, following any "CE" continuation areas.
+    pub fn from_system_use<R: Read + Seek + ?Sized>(reader: &mut R, data: &[u8]) -> Result<Option<Self>, ImageError> {
+        let mut entry = Self::default();
+        let mut area = data.to_vec();
+        let mut continuations = 0;
+        let mut symlink_continued = false;
+
+        loop {
+            let mut continuation = None;
+            let mut pos = 0;
+            while pos + 4 <= area.len() {
+                let signature = &area[pos..pos + 2];
+                let len = area[pos + 2] as usize;
+                if len < 4 || pos + len > area.len() {
+                    break;
+                }
+
+                let body = &area[pos + 4..pos + len];
+                entry.signatures.push(String::from_utf8_lossy(signature).to_string());
+                match signature {
+                    b"SP" if body.len() >= 3 && body[0..2] == [0xbe, 0xef] => entry.susp_skip = Some(body[2]),
+                    b"CE" if body.len() >= 24 => {
+                        continuation = Some((
+                            both_endian_u32(&body[0..]),
+                            both_endian_u32(&body[8..]),
+                            both_endian_u32(&body[16..]),
+                        ))
+                    }
+                    b"ER" if body.len() >= 4 => {
+                        let id_len = body[0] as usize;
+                        if let Some(id) = body.get(4..4 + id_len) {
+                            entry.extensions.push(String::from_utf8_lossy(id).to_string());
+                        }
+                    }
+                    b"NM" if !body.is_empty() => {
+                        let flags = body[0];
+                        let part = String::from_utf8_lossy(&body[1..]);
+                        // The current and parent directory flags name "." and "..", which already have names.
+                        if flags & 0x06 == 0 {
+                            entry.name.get_or_insert_with(String::new).push_str(&part);
+                        }
+                    }
+                    b"PX" if body.len() >= 32 => {
+                        entry.mode = Some(both_endian_u32(&body[0..]));
+                        entry.links = Some(both_endian_u32(&body[8..]));
+                        entry.uid = Some(both_endian_u32(&body[16..]));
+                        entry.gid = Some(both_endian_u32(&body[24..]));
+                    }
+                    b"SL" if !body.is_empty() => {
+                        let target = entry.symlink_target.get_or_insert_with(String::new);
+                        append_symlink_components(target, &body[1..], &mut symlink_continued);
+                    }
+                    b"ST" => break,
+                    _ => (),
+                }
+                pos += len;
+            }
+
+            let (block, offset, length) = match continuation {
+                Some(continuation) if continuations < SUSP_MAX_CONTINUATIONS => continuation,
+                _ => break,
+            };
+            continuations += 1;
+            // A continuation area never extends past the logical block that holds it.
+            area = vec![0u8; (length as u64).min(ISO_SECTOR_SIZE.saturating_sub(offset as u64)) as usize];
+            read_exact_at(reader, block as u64 * ISO_SECTOR_SIZE + offset as u64, &mut area)?;
+        }
+
+        if entry.signatures.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(entry))
+        }
+    }
+}
+
+/// Append the components of an "SL" entry to a symbolic link target. `continued` carries whether the last component
+/// continues in the next component record, which may be in the next entry.
+fn append_symlink_components(target: &mut String, mut data: &[u8], continued: &mut bool) {
+    while data.len() >= 2 {
+        let flags = data[0];
+        let len = data[1] as usize;
+        let content = match data.get(2..2 + len) {
+            Some(content) => content,
+            None => break,
+        };
+
+        if !*continued && !target.is_empty() && !target.ends_with('/') {
+            target.push('/');
+        }
+        match flags & 0x0e {
+            0x02 => target.push('.'),
+            0x04 => target.push_str(".."),
+            0x08 => target.push('/'),
+            _ => target.push_str(&String::from_utf8_lossy(content)),
+        }
+        *continued = flags & 0x01 != 0;
+        data = &data[2 + len..];
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IsoDirectoryRecord {
+    pub extent_lba: u32,
+    pub data_length: u32,
+    pub recording_date: Option<DateTime<FixedOffset>>,
+    pub flags: u8,
+    // The identifier with any ";1" version suffix removed; "." and ".." for the special entries.
+    pub identifier: String,
+    pub rock_ridge: Option<RockRidgeEntry>,
+}
+
+impl IsoDirectoryRecord {
+    /// Parse a directory record; `joliet` selects UCS-2 identifiers, and `susp_skip` enables SUSP parsing.
+    pub fn from_data<R: Read + Seek + ?Sized>(
+        reader: &mut R,
+        data: &[u8],
+        joliet: bool,
+        susp_skip: Option<u8>,
+    ) -> Result<Self, ImageError> {
+        let len = data.first().copied().unwrap_or(0) as usize;
+        if len < 34 || len > data.len() {
+            return Err(ImageError::invalid_metadata(
+                "ISO 9660",
+                format!("directory record length {} is invalid", len),
+            ));
+        }
+
+        let name_len = data[32] as usize;
+        if 33 + name_len > len {
+            return Err(ImageError::invalid_metadata(
+                "ISO 9660",
+                format!("directory record name length {} is invalid", name_len),
+            ));
+        }
+
+        let name = &data[33..33 + name_len];
+        let identifier = match name {
+            [0] => ".".to_string(),
+            [1] => "..".to_string(),
+            _ => {
+                let identifier = if joliet { decode_ucs2(name) } else { String::from_utf8_lossy(name).to_string() };
+                let identifier = match identifier.rfind(';') {
+                    Some(pos) => identifier[..pos].to_string(),
+                    None => identifier,
+                };
+                // Files without an extension are recorded as "NAME.".
+                identifier.strip_suffix('.').map(str::to_string).unwrap_or(identifier)
+            }
+        };
+
+        // The name is padded to an even length, then the system use area runs to the end of the record.
+        let system_use_start = 33 + name_len + (1 - name_len % 2);
+        let rock_ridge = match susp_skip {
+            Some(skip) if system_use_start + (skip as usize) < len => {
+                RockRidgeEntry::from_system_use(reader, &data[system_use_start + skip as usize..len])?
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            extent_lba: both_endian_u32(&data[2..]),
+            data_length: both_endian_u32(&data[10..]),
+            recording_date: decode_record_date(&data[18..25]),
+            flags: data[25],
+            identifier,
+            rock_ridge,
+        })
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.flags & ISO_FLAG_DIRECTORY != 0
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.flags & ISO_FLAG_HIDDEN != 0
+    }
+
+    pub fn is_self_or_parent(&self) -> bool {
+        self.identifier == "." || self.identifier == ".."
+    }
+
+    /// The Rock Ridge name if there is one, otherwise the ISO 9660 or Joliet identifier.
+    pub fn get_name(&self) -> &str {
+        match self.rock_ridge.as_ref().and_then(|rr| rr.name.as_deref()) {
+            Some(name) if !self.is_self_or_parent() => name,
+            _ => &self.identifier,
+        }
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.extent_lba as u64 * ISO_SECTOR_SIZE
+    }
+}
+
+impl Display for IsoDirectoryRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let size = if self.is_directory() { "<DIR>".to_string() } else { self.data_length.to_string() };
+        let date = self.recording_date.map(|date| date.to_string()).unwrap_or_default();
+        write!(f, "{:-24} {:>10} LBA {:<8} {}", self.get_name(), size, self.extent_lba, date)?;
+        if self.is_hidden() {
+            f.write_str(" hidden")?;
+        }
+
+        if let Some(rr) = &self.rock_ridge {
+            if let Some(mode) = rr.mode {
+                write!(f, " mode {:o}", mode)?;
+            }
+            if let (Some(uid), Some(gid)) = (rr.uid, rr.gid) {
+                write!(f, " uid {} gid {}", uid, gid)?;
+            }
+            if let Some(target) = &rr.symlink_target {
+                write!(f, " -> {}", target)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A primary or supplementary volume descriptor.
+#[derive(Clone, Debug)]
+pub struct IsoVolumeDescriptor {
+    pub descriptor_type: u8,
+    pub version: u8,
+    // Joliet UCS-2 level (1-3) from a supplementary descriptor's escape sequences.
+    pub joliet_level: Option<u8>,
+    pub system_id: Option<String>,
+    pub volume_id: Option<String>,
+    pub volume_space_size: u32,
+    pub volume_set_size: u16,
+    pub volume_sequence_number: u16,
+    pub logical_block_size: u16,
+    pub volume_set_id: Option<String>,
+    pub publisher_id: Option<String>,
+    pub preparer_id: Option<String>,
+    pub application_id: Option<String>,
+    pub creation_date: Option<DateTime<FixedOffset>>,
+    pub modification_date: Option<DateTime<FixedOffset>>,
+    pub root_directory: IsoDirectoryRecord,
+}
+
+impl IsoVolumeDescriptor {
+    pub fn new<R: Read + Seek + ?Sized>(reader: &mut R, data: &[u8]) -> Result<Self, ImageError> {
+        let descriptor_type = data[0];
+        let joliet_level = match (descriptor_type, &data[88..91]) {
+            (ISO_DESCRIPTOR_SUPPLEMENTARY, b"%/@") => Some(1),
+            (ISO_DESCRIPTOR_SUPPLEMENTARY, b"%/C") => Some(2),
+            (ISO_DESCRIPTOR_SUPPLEMENTARY, b"%/E") => Some(3),
+            _ => None,
+        };
+        let joliet = joliet_level.is_some();
+
+        Ok(Self {
+            descriptor_type,
+            version: data[6],
+            joliet_level,
+            system_id: decode_text(&data[8..40], joliet),
+            volume_id: decode_text(&data[40..72], joliet),
+            volume_space_size: both_endian_u32(&data[80..]),
+            volume_set_size: both_endian_u16(&data[120..]),
+            volume_sequence_number: both_endian_u16(&data[124..]),
+            logical_block_size: both_endian_u16(&data[128..]),
+            volume_set_id: decode_text(&data[190..318], joliet),
+            publisher_id: decode_text(&data[318..446], joliet),
+            preparer_id: decode_text(&data[446..574], joliet),
+            application_id: decode_text(&data[574..702], joliet),
+            creation_date: decode_descriptor_date(&data[813..830]),
+            modification_date: decode_descriptor_date(&data[830..847]),
+            root_directory: IsoDirectoryRecord::from_data(reader, &data[156..190], joliet, None)?,
+        })
+    }
+
+    pub fn is_joliet(&self) -> bool {
+        self.joliet_level.is_some()
+    }
+}
+
+impl Display for IsoVolumeDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match (self.descriptor_type, self.joliet_level) {
+            (ISO_DESCRIPTOR_PRIMARY, _) => write!(f, "Type: primary (version {})", self.version)?,
+            (_, Some(level)) => write!(f, "Type: supplementary, Joliet level {} (version {})", level, self.version)?,
+            _ => write!(f, "Type: supplementary (version {})", self.version)?,
+        }
+
+        let fields = [
+            ("System ID", &self.system_id),
+            ("Volume ID", &self.volume_id),
+            ("Volume set ID", &self.volume_set_id),
+            ("Publisher", &self.publisher_id),
+            ("Preparer", &self.preparer_id),
+            ("Application", &self.application_id),
+        ];
+        for (name, value) in fields.iter() {
+            if let Some(value) = value {
+                write!(f, "\n{}: {}", name, value)?;
+            }
+        }
+
+        write!(
+            f,
+            "\nVolume size: {} blocks of {} bytes\nVolume set: {} of {}",
+            self.volume_space_size, self.logical_block_size, self.volume_sequence_number, self.volume_set_size
+        )?;
+        if let Some(date) = self.creation_date {
+            write!(f, "\nCreated: {}", date)?;
+        }
+        if let Some(date) = self.modification_date {
+            write!(f, "\nModified: {}", date)?;
+        }
+        write!(f, "\nRoot directory: LBA {}, {} bytes", self.root_directory.extent_lba, self.root_directory.data_length)
+    }
+}
+
+/// One boot image in an El Torito boot catalog: the default entry or an entry in a section.
+#[derive(Clone, Debug)]
+pub struct ElToritoEntry {
+    // 0 for the default entry; sections are numbered from 1.
+    pub section: usize,
+    pub platform_id: u8,
+    pub section_id: Option<String>,
+    pub bootable: bool,
+    pub media_type: u8,
+    pub load_segment: u16,
+    pub system_type: u8,
+    // In 512-byte virtual sectors.
+    pub sector_count: u16,
+    pub load_rba: u32,
+}
+
+impl ElToritoEntry {
+    fn new(data: &[u8], section: usize, platform_id: u8, section_id: Option<String>) -> Self {
+        Self {
+            section,
+            platform_id,
+            section_id,
+            bootable: data[0] == EL_TORITO_BOOTABLE,
+            media_type: data[1],
+            load_segment: u16::from_le_bytes([data[2], data[3]]),
+            system_type: data[4],
+            sector_count: u16::from_le_bytes([data[6], data[7]]),
+            load_rba: u32::from_le_bytes(data[8..12].try_into().unwrap()),
+        }
+    }
+
+    pub fn get_image_offset(&self) -> u64 {
+        self.load_rba as u64 * ISO_SECTOR_SIZE
+    }
+
+    pub fn is_efi(&self) -> bool {
+        self.platform_id == EL_TORITO_PLATFORM_EFI
+    }
+}
+
+impl Display for ElToritoEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if self.section == 0 {
+            f.write_str("Default entry: ")?;
+        } else {
+            write!(f, "Section {} entry: ", self.section)?;
+        }
+        write!(
+            f,
+            "{}, platform 0x{:02x} ({}), {}, load RBA {} (offset 0x{:x}), {} virtual sectors, system type 0x{:02x}",
+            if self.bootable { "bootable" } else { "not bootable" },
+            self.platform_id,
+            get_el_torito_platform_name(self.platform_id),
+            get_el_torito_emulation_name(self.media_type),
+            self.load_rba,
+            self.get_image_offset(),
+            self.sector_count,
+            self.system_type
+        )?;
+        if self.load_segment != 0 {
+            write!(f, ", load segment 0x{:04x}", self.load_segment)?;
+        }
+        if let Some(id) = &self.section_id {
+            write!(f, " \"{}\"", id)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ElToritoCatalog {
+    pub lba: u32,
+    pub platform_id: u8,
+    pub id_string: Option<String>,
+    pub checksum: u16,
+    // The validation entry's 16-bit words must sum to zero.
+    pub checksum_valid: bool,
+    pub entries: Vec<ElToritoEntry>,
+}
+
+impl ElToritoCatalog {
+    pub fn new(data: &[u8], lba: u32) -> Result<Self, ImageError> {
+        if data.len() < 2 * EL_TORITO_ENTRY_SIZE || data[0] != 0x01 || data[30..32] != [0x55, 0xaa] {
+            return Err(ImageError::invalid_metadata(
+                "ISO 9660",
+                format!("no El Torito validation entry at LBA {}", lba),
+            ));
+        }
+
+        let sum = data[0..EL_TORITO_ENTRY_SIZE]
+            .chunks_exact(2)
+            .fold(0u16, |sum, word| sum.wrapping_add(u16::from_le_bytes([word[0], word[1]])));
+        let platform_id = data[1];
+
+        let mut entries = vec![ElToritoEntry::new(&data[32..64], 0, platform_id, None)];
+        let mut records = data[64..].chunks_exact(EL_TORITO_ENTRY_SIZE);
+        let mut section = 0;
+        while let Some(header) = records.next() {
+            // 0x90 is a section header with more sections to follow, 0x91 the final one.
+            if header[0] != 0x90 && header[0] != 0x91 {
+                break;
+            }
+
+            section += 1;
+            let section_id = decode_label(&header[4..32]);
+            for _ in 0..u16::from_le_bytes([header[2], header[3]]) {
+                let entry = match records.next() {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                entries.push(ElToritoEntry::new(entry, section, header[1], section_id.clone()));
+
+                // Skip any selection criteria extension entries.
+                let mut more = entry[12] & 0x20 != 0;
+                while more {
+                    more =
+                        matches!(records.next(), Some(extension) if extension[0] == 0x44 && extension[1] & 0x20 != 0);
+                }
+            }
+
+            if header[0] == 0x91 {
+                break;
+            }
+        }
+
+        Ok(Self {
+            lba,
+            platform_id,
+            id_string: decode_label(&data[4..28]),
+            checksum: u16::from_le_bytes([data[28], data[29]]),
+            checksum_valid: sum == 0,
+            entries,
+        })
+    }
+}
+
+impl Display for ElToritoCatalog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Catalog LBA: {}\nPlatform: 0x{:02x} ({})\nID: {}\nChecksum: 0x{:04x} ({})",
+            self.lba,
+            self.platform_id,
+            get_el_torito_platform_name(self.platform_id),
+            self.id_string.as_deref().unwrap_or(""),
+            self.checksum,
+            if self.checksum_valid { "valid" } else { "invalid" }
+        )
+    }
+}
+
+/// Where a boot entry's image lives, and the file that holds it if it is visible in the directory tree.
+#[derive(Clone, Debug)]
+pub struct ElToritoImage {
+    pub offset: u64,
+    pub length: u64,
+    pub path: Option<String>,
+}
+
+/// An ISO 9660 volume: its volume descriptors, SUSP/Rock Ridge details and any El Torito boot catalog location.
+#[derive(Clone, Debug)]
+pub struct IsoImage {
+    pub primary: IsoVolumeDescriptor,
+    pub supplementary: Vec<IsoVolumeDescriptor>,
+    pub boot_catalog_lba: Option<u32>,
+    // From the root directory's "." entry; `None` if the volume does not use SUSP.
+    pub susp_skip: Option<u8>,
+    pub extensions: Vec<String>,
+}
+
+impl IsoImage {
+    /// Read the volume descriptor set at sector 16, or return `None` if there is no ISO 9660 volume.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        let mut primary = None;
+        let mut supplementary = Vec::new();
+        let mut boot_catalog_lba = None;
+
+        for i in 0..ISO_MAX_VOLUME_DESCRIPTORS {
+            let data = match read_probe_bytes(reader, (ISO_VOLUME_DESCRIPTOR_LBA + i) * ISO_SECTOR_SIZE, 2048)? {
+                Some(data) if &data[1..6] == ISO_STANDARD_ID => data,
+                _ if i == 0 => return Ok(None),
+                _ => break,
+            };
+
+            match data[0] {
+                ISO_DESCRIPTOR_BOOT_RECORD if decode_label(&data[7..39]).as_deref() == Some(EL_TORITO_SYSTEM_ID) => {
+                    boot_catalog_lba = Some(u32::from_le_bytes(data[71..75].try_into().unwrap()));
+                }
+                ISO_DESCRIPTOR_PRIMARY if primary.is_none() => primary = Some(IsoVolumeDescriptor::new(reader, &data)?),
+                ISO_DESCRIPTOR_SUPPLEMENTARY => supplementary.push(IsoVolumeDescriptor::new(reader, &data)?),
+                ISO_DESCRIPTOR_TERMINATOR => break,
+                _ => (),
+            }
+        }
+
+        let primary = match primary {
+            Some(primary) => primary,
+            None => return Err(ImageError::invalid_metadata("ISO 9660", "no primary volume descriptor".to_string())),
+        };
+
+        // SUSP use is announced by an "SP" entry at the start of the root directory's "." record.
+        let mut root = vec![0u8; 255];
+        read_exact_at(reader, primary.root_directory.get_offset(), &mut root)?;
+        let rock_ridge = IsoDirectoryRecord::from_data(reader, &root, false, Some(0))?.rock_ridge;
+        let (susp_skip, extensions) = match rock_ridge {
+            Some(rr) if rr.susp_skip.is_some() => (rr.susp_skip, rr.extensions),
+            _ => (None, Vec::new()),
+        };
+
+        Ok(Some(Self {
+            primary,
+            supplementary,
+            boot_catalog_lba,
+            susp_skip,
+            extensions,
+        }))
+    }
+
+    pub fn get_joliet_descriptor(&self) -> Option<&IsoVolumeDescriptor> {
+        self.supplementary.iter().filter(|descriptor| descriptor.is_joliet()).max_by_key(|d| d.joliet_level)
+    }
+
+    /// Whether the primary volume has Rock Ridge entries: SUSP with an RRIP extension, or with no "ER" at all as in
+    /// Rock Ridge 1.09.
+    pub fn has_rock_ridge(&self) -> bool {
+        self.susp_skip.is_some()
+            && (self.extensions.is_empty()
+                || self.extensions.iter().any(|id| id.starts_with("RRIP") || id.contains("1282")))
+    }
+
+    /// The descriptor whose tree has the most faithful names: Rock Ridge, then Joliet, then plain ISO 9660.
+    pub fn get_preferred_descriptor(&self) -> &IsoVolumeDescriptor {
+        match self.get_joliet_descriptor() {
+            Some(joliet) if !self.has_rock_ridge() => joliet,
+            _ => &self.primary,
+        }
+    }
+
+    pub fn read_directory<R: Read + Seek + ?Sized>(
+        &self,
+        reader: &mut R,
+        descriptor: &IsoVolumeDescriptor,
+        directory: &IsoDirectoryRecord,
+    ) -> Result<Vec<IsoDirectoryRecord>, ImageError> {
+        if directory.data_length > ISO_MAX_DIRECTORY_SIZE {
+            return Err(ImageError::invalid_metadata(
+                "ISO 9660",
+                format!("directory size {} is too large", directory.data_length),
+            ));
+        }
+
+        let mut data = vec![0u8; directory.data_length as usize];
+        read_exact_at(reader, directory.get_offset(), &mut data)?;
+
+        // Joliet trees carry no SUSP entries.
+        let susp_skip = if descriptor.is_joliet() { None } else { self.susp_skip };
+        let mut records = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let len = data[pos] as usize;
+            if len == 0 {
+                // Records do not cross sector boundaries; the rest of the sector is padding.
+                pos = (pos / ISO_SECTOR_SIZE as usize + 1) * ISO_SECTOR_SIZE as usize;
+                continue;
+            }
+
+            let end = (pos + len).min(data.len());
+            records.push(IsoDirectoryRecord::from_data(reader, &data[pos..end], descriptor.is_joliet(), susp_skip)?);
+            pos += len;
+        }
+
+        Ok(records)
+    }
+
+    /// Find the path of the file whose data starts at `lba`, searching the preferred directory tree breadth first.
+    pub fn find_path_by_extent<R: Read + Seek + ?Sized>(
+        &self,
+        reader: &mut R,
+        lba: u32,
+    ) -> Result<Option<(String, IsoDirectoryRecord)>, ImageError> {
+        let descriptor = self.get_preferred_descriptor();
+        let mut queue = VecDeque::from(vec![("/".to_string(), descriptor.root_directory.clone())]);
+        let mut visited = HashSet::new();
+
+        while let Some((path, directory)) = queue.pop_front() {
+            if !visited.insert(directory.extent_lba) || visited.len() > ISO_MAX_DIRECTORIES_SEARCHED {
+                continue;
+            }
+
+            for record in self.read_directory(reader, descriptor, &directory)? {
+                if record.is_self_or_parent() {
+                    continue;
+                }
+
+                let record_path = format!("{}{}", path, record.get_name());
+                if record.is_directory() {
+                    queue.push_back((format!("{}/", record_path), record));
+                } else if record.extent_lba == lba {
+                    return Ok(Some((record_path, record)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn read_boot_catalog<R: Read + Seek + ?Sized>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Option<ElToritoCatalog>, ImageError> {
+        let lba = match self.boot_catalog_lba {
+            Some(lba) => lba,
+            None => return Ok(None),
+        };
+
+        let mut data = vec![0u8; ISO_SECTOR_SIZE as usize];
+        read_exact_at(reader, lba as u64 * ISO_SECTOR_SIZE, &mut data)?;
+        Ok(Some(ElToritoCatalog::new(&data, lba)?))
+    }
+
+    /// Locate a boot entry's image. The size comes from the file holding it, else from the FAT boot sector of an
+    /// embedded EFI system image, else from the emulated floppy size or the entry's sector count.
+    pub fn locate_boot_image<R: Read + Seek + ?Sized>(
+        &self,
+        reader: &mut R,
+        entry: &ElToritoEntry,
+    ) -> Result<ElToritoImage, ImageError> {
+        let offset = entry.get_image_offset();
+        if let Some((path, record)) = self.find_path_by_extent(reader, entry.load_rba)? {
+            return Ok(ElToritoImage {
+                offset,
+                length: record.data_length as u64,
+                path: Some(path),
+            });
+        }
+
+        let length = match (entry.media_type & 0x0f, FatBootSector::from_partition_image(&mut &mut *reader, offset)) {
+            (1, _) => 1_228_800,
+            (2, _) => 1_474_560,
+            (3, _) => 2_949_120,
+            (_, Ok(boot_sector)) => boot_sector.sectors_in_filesystem as u64 * boot_sector.bytes_per_sector as u64,
+            _ => entry.sector_count as u64 * EL_TORITO_VIRTUAL_SECTOR_SIZE,
+        };
+
+        Ok(ElToritoImage {
+            offset,
+            length,
+            path: None,
+        })
+    }
+}
+
+impl Display for IsoImage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.primary)?;
+        if self.has_rock_ridge() && self.extensions.is_empty() {
+            f.write_str("\nRock Ridge: yes")?;
+        } else if self.has_rock_ridge() {
+            write!(f, "\nRock Ridge: {}", self.extensions.join(", "))?;
+        } else if self.susp_skip.is_some() {
+            write!(f, "\nSUSP extensions: {}", self.extensions.join(", "))?;
+        }
+        for descriptor in self.supplementary.iter() {
+            write!(f, "\n    {}", descriptor.to_string().replace("\n", "\n    "))?;
+        }
+        if let Some(lba) = self.boot_catalog_lba {
+            write!(f, "\nEl Torito boot catalog: LBA {}", lba)?;
+        }
+        Ok(())
+    }
+}
+
+/// Detect an ISO 9660 volume.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let data = match read_probe_bytes(reader, ISO_VOLUME_DESCRIPTOR_LBA * ISO_SECTOR_SIZE, 2048)? {
+        Some(data) if &data[1..6] == ISO_STANDARD_ID => data,
+        _ => return Ok(None),
+    };
+
+    let mut result = ProbeResult::new("iso9660", ProbeUsage::Filesystem, ProbeConfidence::High);
+    if data[0] == ISO_DESCRIPTOR_PRIMARY {
+        result.label = decode_label(&data[40..72]);
+        // Like blkid, use the modification (or else creation) date as the UUID.
+        let date = if data[830] != 0 && data[830] != b'0' { &data[830..846] } else { &data[813..829] };
+        if date.iter().all(u8::is_ascii_digit) && date.iter().any(|&b| b != b'0') {
+            let d = String::from_utf8_lossy(date);
+            result.uuid = Some(format!(
+                "{}-{}-{}-{}-{}-{}-{}",
+                &d[0..4],
+                &d[4..6],
+                &d[6..8],
+                &d[8..10],
+                &d[10..12],
+                &d[12..14],
+                &d[14..16]
+            ));
+        }
+    }
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_record(data: &mut Vec<u8>, name: &[u8], lba: u32, length: u32, flags: u8, system_use: &[u8]) {
+        let name_len = name.len();
+        let len = 33 + name_len + (1 - name_len % 2) + system_use.len();
+        let mut record = vec![0u8; len];
+        record[0] = len as u8;
+        record[2..6].copy_from_slice(&lba.to_le_bytes());
+        record[6..10].copy_from_slice(&lba.to_be_bytes());
+        record[10..14].copy_from_slice(&length.to_le_bytes());
+        record[14..18].copy_from_slice(&length.to_be_bytes());
+        record[18..25].copy_from_slice(&[124, 1, 2, 3, 4, 5, 0]);
+        record[25] = flags;
+        record[32] = name_len as u8;
+        record[33..33 + name_len].copy_from_slice(name);
+        record[len - system_use.len()..].copy_from_slice(system_use);
+        data.extend_from_slice(&record);
+    }
+
+    fn write_descriptor(image: &mut [u8], lba: usize, descriptor_type: u8, volume_id: &[u8], root_lba: u32) {
+        let data = &mut image[lba * 2048..(lba + 1) * 2048];
+        data[0] = descriptor_type;
+        data[1..6].copy_from_slice(ISO_STANDARD_ID);
+        data[6] = 1;
+        data[40..40 + volume_id.len()].copy_from_slice(volume_id);
+        data[80..84].copy_from_slice(&32u32.to_le_bytes());
+        data[128..130].copy_from_slice(&2048u16.to_le_bytes());
+        data[813..830].copy_from_slice(b"2024010203040500\0");
+        let mut root = Vec::new();
+        write_record(&mut root, &[0], root_lba, 2048, ISO_FLAG_DIRECTORY, &[]);
+        data[156..190].copy_from_slice(&root);
+    }
+
+    fn build_image() -> Vec<u8> {
+        let mut image = vec![0u8; 32 * 2048];
+        write_descriptor(&mut image, 16, ISO_DESCRIPTOR_PRIMARY, b"INSTALLER", 20);
+        let boot = &mut image[17 * 2048..18 * 2048];
+        boot[1..6].copy_from_slice(ISO_STANDARD_ID);
+        boot[6] = 1;
+        boot[7..7 + EL_TORITO_SYSTEM_ID.len()].copy_from_slice(EL_TORITO_SYSTEM_ID.as_bytes());
+        boot[71..75].copy_from_slice(&23u32.to_le_bytes());
+        write_descriptor(&mut image, 18, ISO_DESCRIPTOR_SUPPLEMENTARY, &[0, b'I', 0, b'n'], 21);
+        image[18 * 2048 + 88..18 * 2048 + 91].copy_from_slice(b"%/E");
+        image[19 * 2048] = ISO_DESCRIPTOR_TERMINATOR;
+        image[19 * 2048 + 1..19 * 2048 + 6].copy_from_slice(ISO_STANDARD_ID);
+
+        let mut sp = b"SP\x07\x01\xbe\xef\x00".to_vec();
+        sp.extend_from_slice(b"ER\x12\x01\x0a\x00\x00\x01RRIP_1991A");
+        let mut nm = b"NM\x0c\x01\x00efi.img".to_vec();
+        nm.extend_from_slice(b"PX\x24\x01");
+        for value in [0o100644u32, 1, 0, 0] {
+            nm.extend_from_slice(&value.to_le_bytes());
+            nm.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut root = Vec::new();
+        write_record(&mut root, &[0], 20, 2048, ISO_FLAG_DIRECTORY, &sp);
+        write_record(&mut root, &[1], 20, 2048, ISO_FLAG_DIRECTORY, &[]);
+        write_record(&mut root, b"EFI.IMG;1", 24, 4096, 0, &nm);
+        image[20 * 2048..20 * 2048 + root.len()].copy_from_slice(&root);
+
+        let mut joliet = Vec::new();
+        write_record(&mut joliet, &[0], 21, 2048, ISO_FLAG_DIRECTORY, &[]);
+        write_record(&mut joliet, &[0, b'e', 0, b'f', 0, b'i', 0, b'.', 0, b'i', 0, b'm', 0, b'g'], 24, 4096, 0, &[]);
+        image[21 * 2048..21 * 2048 + joliet.len()].copy_from_slice(&joliet);
+
+        let catalog = &mut image[23 * 2048..24 * 2048];
+        catalog[0] = 0x01;
+        catalog[30..32].copy_from_slice(&[0x55, 0xaa]);
+        let sum = catalog[0..32]
+            .chunks_exact(2)
+            .fold(0u16, |sum, word| sum.wrapping_add(u16::from_le_bytes([word[0], word[1]])));
+        catalog[28..30].copy_from_slice(&0u16.wrapping_sub(sum).to_le_bytes());
+        catalog[32] = EL_TORITO_BOOTABLE;
+        catalog[38..40].copy_from_slice(&4u16.to_le_bytes());
+        catalog[40..44].copy_from_slice(&25u32.to_le_bytes());
+        catalog[64] = 0x91;
+        catalog[65] = EL_TORITO_PLATFORM_EFI;
+        catalog[66..68].copy_from_slice(&1u16.to_le_bytes());
+        catalog[96] = EL_TORITO_BOOTABLE;
+        catalog[104..108].copy_from_slice(&24u32.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn read_iso_directories() {
+        let mut cursor = Cursor::new(build_image());
+        let iso = IsoImage::detect(&mut cursor).unwrap().unwrap();
+        assert_eq!(iso.primary.volume_id.as_deref(), Some("INSTALLER"));
+        assert_eq!(iso.extensions, vec!["RRIP_1991A".to_string()]);
+        assert!(iso.has_rock_ridge());
+        assert_eq!(iso.get_joliet_descriptor().unwrap().volume_id.as_deref(), Some("In"));
+
+        let records = iso.read_directory(&mut cursor, &iso.primary, &iso.primary.root_directory).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].identifier, "EFI.IMG");
+        assert_eq!(records[2].get_name(), "efi.img");
+        assert_eq!(records[2].rock_ridge.as_ref().unwrap().mode, Some(0o100644));
+
+        let joliet = iso.get_joliet_descriptor().unwrap();
+        let records = iso.read_directory(&mut cursor, joliet, &joliet.root_directory).unwrap();
+        assert_eq!(records[1].get_name(), "efi.img");
+    }
+
+    #[test]
+    fn read_el_torito_catalog() {
+        let mut cursor = Cursor::new(build_image());
+        let iso = IsoImage::detect(&mut cursor).unwrap().unwrap();
+        let catalog = iso.read_boot_catalog(&mut cursor).unwrap().unwrap();
+        assert!(catalog.checksum_valid);
+        assert_eq!(catalog.entries.len(), 2);
+        assert_eq!(catalog.entries[0].platform_id, EL_TORITO_PLATFORM_X86);
+        assert_eq!(catalog.entries[0].load_rba, 25);
+
+        let efi = &catalog.entries[1];
+        assert!(efi.is_efi());
+        let image = iso.locate_boot_image(&mut cursor, efi).unwrap();
+        assert_eq!(image.offset, 24 * 2048);
+        assert_eq!(image.length, 4096);
+        assert_eq!(image.path.as_deref(), Some("/efi.img"));
+    }
+}
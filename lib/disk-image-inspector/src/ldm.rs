@@ -0,0 +1,782 @@
+use log::warn;
+use std::{
+    collections::BTreeMap,
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+use uuid::Uuid;
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    partition::{Partition, PartitionType},
+    probe::{decode_label, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+    reader::{Extent, ExtentReader},
+};
+
+// The LDM database always uses 512-byte sectors.
+pub const LDM_SECTOR_SIZE: u64 = 512;
+
+// On MBR disks the PRIVHEAD is in sector 6; on GPT disks it is the last sector of the LDM metadata partition.
+pub const LDM_MBR_PRIVHEAD_LBA: u64 = 6;
+pub const LDM_MBR_PARTITION_TYPE: u8 = 0x42;
+pub const LDM_GPT_METADATA_PARTITION_TYPE: u128 = 0x5808c8aa7e8f42e085d2e1e90434cfb3;
+
+pub const LDM_PRIVHEAD_MAGIC: &[u8; 8] = b"PRIVHEAD";
+pub const LDM_TOCBLOCK_MAGIC: &[u8; 8] = b"TOCBLOCK";
+pub const LDM_VMDB_MAGIC: &[u8; 4] = b"VMDB";
+pub const LDM_VBLK_MAGIC: &[u8; 4] = b"VBLK";
+
+// The TOCBLOCK and its copy follow the start of the database.
+pub const LDM_TOCBLOCK_SECTORS: [u64; 2] = [1, 2];
+pub const LDM_VBLK_HEADER_SIZE: usize = 16;
+pub const MAX_LDM_VBLKS: u64 = 65536;
+// The whole LDM database occupies the last 1 MiB of the disk, so no configuration is larger.
+pub const MAX_LDM_DATABASE_SIZE: u64 = 1024 * 1024;
+
+// VBLK record types, in the low nibble of the type byte; the high nibble is the record revision.
+pub const LDM_VBLK_VOLUME: u8 = 0x1;
+pub const LDM_VBLK_COMPONENT: u8 = 0x2;
+pub const LDM_VBLK_PARTITION: u8 = 0x3;
+pub const LDM_VBLK_DISK: u8 = 0x4;
+pub const LDM_VBLK_DISK_GROUP: u8 = 0x5;
+
+pub const LDM_COMPONENT_FLAG_STRIPE: u8 = 0x10;
+pub const LDM_PARTITION_FLAG_INDEX: u8 = 0x08;
+pub const LDM_VOLUME_FLAG_ID1: u8 = 0x08;
+pub const LDM_VOLUME_FLAG_ID2: u8 = 0x20;
+pub const LDM_VOLUME_FLAG_SIZE: u8 = 0x80;
+pub const LDM_VOLUME_FLAG_DRIVE: u8 = 0x02;
+
+pub const LDM_COMPONENT_STRIPED: u8 = 1;
+pub const LDM_COMPONENT_SPANNED: u8 = 2;
+pub const LDM_COMPONENT_RAID: u8 = 3;
+
+/// The name of an LDM component layout.
+pub fn get_ldm_component_type_name(component_type: u8) -> &'static str {
+    match component_type {
+        LDM_COMPONENT_STRIPED => "striped",
+        LDM_COMPONENT_SPANNED => "spanned",
+        LDM_COMPONENT_RAID => "RAID-5",
+        _ => "Unknown",
+    }
+}
+
+/// Where a dynamic disk keeps its PRIVHEAD, given the disk's partitions: sector 6 on an MBR disk with a type 0x42
+/// partition, or the last sector of the LDM metadata partition on a GPT disk.
+pub fn find_ldm_privhead_lba(partitions: &[Partition]) -> Option<u64> {
+    partitions.iter().find_map(|partition| match &partition.partition_type {
+        PartitionType::Mbr(mbr_type) if mbr_type.code == LDM_MBR_PARTITION_TYPE => Some(LDM_MBR_PRIVHEAD_LBA),
+        PartitionType::Gpt(guid) if guid.as_u128() == LDM_GPT_METADATA_PARTITION_TYPE => {
+            Some(partition.get_end_offset() / LDM_SECTOR_SIZE - 1)
+        }
+        _ => None,
+    })
+}
+
+/// The private header describing this disk and where its copy of the LDM database is.
+#[derive(Clone, Debug)]
+pub struct LdmPrivateHeader {
+    pub version_major: u16,
+    pub version_minor: u16,
+    pub disk_guid: String,
+    pub host_guid: String,
+    pub disk_group_guid: String,
+    pub disk_group_name: String,
+    // In sectors; partition starts in the database are relative to `logical_disk_start`.
+    pub logical_disk_start: u64,
+    pub logical_disk_size: u64,
+    pub config_start: u64,
+    pub config_size: u64,
+}
+
+impl LdmPrivateHeader {
+    pub fn new(data: &[u8]) -> Result<Self, ImageError> {
+        if data.len() < LDM_SECTOR_SIZE as usize {
+            return Err(ImageError::Truncated {
+                offset: 0,
+                expected: LDM_SECTOR_SIZE as usize,
+                actual: data.len(),
+            });
+        }
+
+        if &data[0..8] != LDM_PRIVHEAD_MAGIC {
+            return Err(ImageError::invalid_metadata("LDM", "no PRIVHEAD signature".to_string()));
+        }
+
+        let be16 = |offset: usize| u16::from_be_bytes(data[offset..offset + 2].try_into().unwrap());
+        let be64 = |offset: usize| u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        Ok(Self {
+            version_major: be16(0x0c),
+            version_minor: be16(0x0e),
+            disk_guid: decode_label(&data[0x30..0x70]).unwrap_or_default(),
+            host_guid: decode_label(&data[0x70..0xb0]).unwrap_or_default(),
+            disk_group_guid: decode_label(&data[0xb0..0xf0]).unwrap_or_default(),
+            disk_group_name: decode_label(&data[0xf0..0x110]).unwrap_or_default(),
+            logical_disk_start: be64(0x11b),
+            logical_disk_size: be64(0x123),
+            config_start: be64(0x12b),
+            config_size: be64(0x133),
+        })
+    }
+}
+
+/// A record from the VMDB, with its common header fields and the record-specific data that follows the name.
+#[derive(Clone, Debug)]
+struct VblkRecord {
+    flags: u8,
+    record_type: u8,
+    data: Vec<u8>,
+}
+
+/// A cursor over the variable-length numbers and strings that make up a VBLK record.
+struct VblkCursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> VblkCursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ImageError> {
+        if self.pos + len > self.data.len() {
+            return Err(ImageError::invalid_metadata(
+                "LDM",
+                format!("VBLK record ends within a field at offset {}", self.pos),
+            ));
+        }
+        let value = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(value)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), ImageError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn byte(&mut self) -> Result<u8, ImageError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn be64(&mut self) -> Result<u64, ImageError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // A length byte followed by up to eight big-endian bytes.
+    fn number(&mut self) -> Result<u64, ImageError> {
+        let len = self.byte()? as usize;
+        if len > 8 {
+            return Err(ImageError::invalid_metadata("LDM", format!("VBLK number is {} bytes long", len)));
+        }
+        Ok(self.take(len)?.iter().fold(0, |acc, &b| acc << 8 | b as u64))
+    }
+
+    // A length byte followed by that many characters.
+    fn string(&mut self) -> Result<String, ImageError> {
+        let len = self.byte()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LdmDisk {
+    pub id: u64,
+    pub name: String,
+    pub guid: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct LdmDiskGroup {
+    pub id: u64,
+    pub name: String,
+}
+
+/// A run of sectors on one disk, which the database calls a partition.
+#[derive(Clone, Debug)]
+pub struct LdmPartition {
+    pub id: u64,
+    pub name: String,
+    // In sectors, relative to the disk's logical disk start.
+    pub start: u64,
+    // In sectors, where this extent sits within its component.
+    pub volume_offset: u64,
+    pub size: u64,
+    pub component_id: u64,
+    pub disk_id: u64,
+    pub index: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LdmComponent {
+    pub id: u64,
+    pub name: String,
+    pub state: String,
+    pub component_type: u8,
+    pub child_count: u64,
+    pub volume_id: u64,
+    // In sectors; only set for striped and RAID-5 components.
+    pub stripe_size: u64,
+    pub columns: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct LdmVolume {
+    pub id: u64,
+    pub name: String,
+    pub volume_type: String,
+    pub child_count: u64,
+    pub size: u64,
+    pub partition_type: u8,
+    pub guid: Uuid,
+    pub drive_hint: Option<String>,
+}
+
+/// The LDM database of a dynamic disk: its private header and the disk group's volumes, components, partitions and
+/// disks.
+#[derive(Clone, Debug)]
+pub struct LdmDatabase {
+    pub privhead_lba: u64,
+    pub private_header: LdmPrivateHeader,
+    pub vmdb_version_major: u16,
+    pub vmdb_version_minor: u16,
+    pub committed_sequence: u64,
+    pub disk_groups: Vec<LdmDiskGroup>,
+    pub disks: Vec<LdmDisk>,
+    pub volumes: Vec<LdmVolume>,
+    pub components: Vec<LdmComponent>,
+    pub partitions: Vec<LdmPartition>,
+}
+
+impl LdmDatabase {
+    /// Read the database of an MBR dynamic disk, or return `None` if there is no PRIVHEAD in sector 6.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        match read_probe_bytes(reader, LDM_MBR_PRIVHEAD_LBA * LDM_SECTOR_SIZE, LDM_SECTOR_SIZE as usize)? {
+            Some(data) if &data[0..8] == LDM_PRIVHEAD_MAGIC => Ok(Some(Self::read(reader, LDM_MBR_PRIVHEAD_LBA)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Read the database whose PRIVHEAD is at sector `privhead_lba`, such as the last sector of a GPT LDM metadata
+    /// partition.
+    pub fn read<R: Read + Seek + ?Sized>(reader: &mut R, privhead_lba: u64) -> Result<Self, ImageError> {
+        let mut sector = [0u8; LDM_SECTOR_SIZE as usize];
+        read_exact_at(reader, privhead_lba * LDM_SECTOR_SIZE, &mut sector)?;
+        let private_header = LdmPrivateHeader::new(&sector)?;
+        let overflow =
+            || ImageError::invalid_metadata("LDM", "configuration lies beyond the addressable range".to_string());
+        let config_offset = private_header.config_start.checked_mul(LDM_SECTOR_SIZE).ok_or_else(overflow)?;
+
+        // The first bitmap in the TOCBLOCK locates the configuration (the VMDB and its VBLKs).
+        let mut config_bitmap = None;
+        for toc_sector in LDM_TOCBLOCK_SECTORS.iter() {
+            let toc_offset = config_offset.checked_add(toc_sector * LDM_SECTOR_SIZE).ok_or_else(overflow)?;
+            read_exact_at(reader, toc_offset, &mut sector)?;
+            if &sector[0..8] == LDM_TOCBLOCK_MAGIC && &sector[0x24..0x2a] == b"config" {
+                let start = u64::from_be_bytes(sector[0x2e..0x36].try_into().unwrap());
+                let size = u64::from_be_bytes(sector[0x36..0x3e].try_into().unwrap());
+                config_bitmap = Some((start, size));
+                break;
+            }
+            warn!("LDM TOCBLOCK at sector {} is invalid", private_header.config_start + toc_sector);
+        }
+
+        let (config_start, config_size) = match config_bitmap {
+            Some(bitmap) => bitmap,
+            None => return Err(ImageError::invalid_metadata("LDM", "no valid TOCBLOCK".to_string())),
+        };
+
+        let vmdb_offset = config_start
+            .checked_mul(LDM_SECTOR_SIZE)
+            .and_then(|offset| offset.checked_add(config_offset))
+            .ok_or_else(overflow)?;
+        read_exact_at(reader, vmdb_offset, &mut sector)?;
+        if &sector[0..4] != LDM_VMDB_MAGIC {
+            return Err(ImageError::invalid_metadata("LDM", format!("no VMDB at offset 0x{:x}", vmdb_offset)));
+        }
+
+        let be32 = |data: &[u8], offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let last_sequence = be32(&sector, 0x04) as u64;
+        let vblk_size = be32(&sector, 0x08) as u64;
+        let first_offset = be32(&sector, 0x0c) as u64;
+        let vmdb_version_major = u16::from_be_bytes(sector[0x12..0x14].try_into().unwrap());
+        let vmdb_version_minor = u16::from_be_bytes(sector[0x14..0x16].try_into().unwrap());
+        let committed_sequence = u64::from_be_bytes(sector[0x75..0x7d].try_into().unwrap());
+
+        if vblk_size <= LDM_VBLK_HEADER_SIZE as u64 || !first_offset.is_multiple_of(vblk_size) {
+            return Err(ImageError::invalid_metadata(
+                "LDM",
+                format!("VBLK size {} and offset {} are invalid", vblk_size, first_offset),
+            ));
+        }
+
+        // VBLKs are numbered from the start of the VMDB, so the first is `first_offset / vblk_size`.
+        // Only the configuration's VBLKs are read, and never more than the size of a whole database.
+        let config_bytes = config_size.checked_mul(LDM_SECTOR_SIZE).ok_or_else(overflow)?;
+        let available = config_bytes.min(MAX_LDM_DATABASE_SIZE).saturating_sub(first_offset) / vblk_size;
+        let count = (last_sequence + 1).saturating_sub(first_offset / vblk_size).min(available).min(MAX_LDM_VBLKS);
+        let mut blocks = vec![0u8; (count * vblk_size) as usize];
+        read_exact_at(reader, vmdb_offset.checked_add(first_offset).ok_or_else(overflow)?, &mut blocks)?;
+
+        // Records longer than one VBLK are split into fragments that share a group number.
+        let mut fragments: BTreeMap<u32, (u16, BTreeMap<u16, Vec<u8>>)> = BTreeMap::new();
+        for block in blocks.chunks_exact(vblk_size as usize) {
+            if &block[0..4] != LDM_VBLK_MAGIC {
+                continue;
+            }
+
+            let group = u32::from_be_bytes(block[8..12].try_into().unwrap());
+            let entry = u16::from_be_bytes(block[12..14].try_into().unwrap());
+            let entry_count = u16::from_be_bytes(block[14..16].try_into().unwrap());
+            if entry_count == 0 || block[LDM_VBLK_HEADER_SIZE..].iter().all(|&b| b == 0) {
+                continue;
+            }
+
+            let record = fragments.entry(group).or_insert_with(|| (entry_count, BTreeMap::new()));
+            record.1.insert(entry, block[LDM_VBLK_HEADER_SIZE..].to_vec());
+        }
+
+        let mut database = Self {
+            privhead_lba,
+            private_header,
+            vmdb_version_major,
+            vmdb_version_minor,
+            committed_sequence,
+            disk_groups: Vec::new(),
+            disks: Vec::new(),
+            volumes: Vec::new(),
+            components: Vec::new(),
+            partitions: Vec::new(),
+        };
+
+        for (group, (entry_count, parts)) in fragments {
+            if parts.len() != entry_count as usize {
+                warn!("LDM record group {} has {} of {} fragments", group, parts.len(), entry_count);
+                continue;
+            }
+
+            let data: Vec<u8> = parts.into_values().flatten().collect();
+            if data.len() < 8 {
+                continue;
+            }
+
+            let record = VblkRecord {
+                flags: data[2],
+                record_type: data[3],
+                data,
+            };
+            if let Err(e) = database.add_record(&record) {
+                warn!("Ignoring LDM record group {}: {}", group, e);
+            }
+        }
+
+        Ok(database)
+    }
+
+    fn add_record(&mut self, record: &VblkRecord) -> Result<(), ImageError> {
+        // The record-specific fields start after a 4-byte size, at offset 0x18 of the first VBLK.
+        let mut cursor = VblkCursor {
+            data: &record.data,
+            pos: 8,
+        };
+        let id = cursor.number()?;
+        let name = cursor.string()?;
+
+        match record.record_type & 0x0f {
+            LDM_VBLK_VOLUME => {
+                let volume_type = cursor.string()?;
+                cursor.string()?;
+                cursor.skip(21)?;
+                let child_count = cursor.number()?;
+                cursor.skip(16)?;
+                let size = cursor.number()?;
+                cursor.skip(4)?;
+                let partition_type = cursor.byte()?;
+                let guid = Uuid::from_slice(cursor.take(16)?).unwrap_or_else(|_| Uuid::nil());
+                if record.flags & LDM_VOLUME_FLAG_ID1 != 0 {
+                    cursor.string()?;
+                }
+                if record.flags & LDM_VOLUME_FLAG_ID2 != 0 {
+                    cursor.string()?;
+                }
+                if record.flags & LDM_VOLUME_FLAG_SIZE != 0 {
+                    cursor.number()?;
+                }
+                let drive_hint = if record.flags & LDM_VOLUME_FLAG_DRIVE != 0 { Some(cursor.string()?) } else { None };
+
+                self.volumes.push(LdmVolume {
+                    id,
+                    name,
+                    volume_type,
+                    child_count,
+                    size,
+                    partition_type,
+                    guid,
+                    drive_hint,
+                });
+            }
+            LDM_VBLK_COMPONENT => {
+                let state = cursor.string()?;
+                let component_type = cursor.byte()?;
+                cursor.skip(4)?;
+                let child_count = cursor.number()?;
+                cursor.skip(16)?;
+                let volume_id = cursor.number()?;
+                cursor.skip(1)?;
+                let (stripe_size, columns) = if record.flags & LDM_COMPONENT_FLAG_STRIPE != 0 {
+                    (cursor.number()?, cursor.number()?)
+                } else {
+                    (0, 0)
+                };
+
+                self.components.push(LdmComponent {
+                    id,
+                    name,
+                    state,
+                    component_type,
+                    child_count,
+                    volume_id,
+                    stripe_size,
+                    columns,
+                });
+            }
+            LDM_VBLK_PARTITION => {
+                cursor.skip(12)?;
+                let start = cursor.be64()?;
+                let volume_offset = cursor.be64()?;
+                let size = cursor.number()?;
+                let component_id = cursor.number()?;
+                let disk_id = cursor.number()?;
+                let index = if record.flags & LDM_PARTITION_FLAG_INDEX != 0 { Some(cursor.number()?) } else { None };
+
+                self.partitions.push(LdmPartition {
+                    id,
+                    name,
+                    start,
+                    volume_offset,
+                    size,
+                    component_id,
+                    disk_id,
+                    index,
+                });
+            }
+            LDM_VBLK_DISK => {
+                // Revision 3 stores the disk GUID as text; revision 4 stores it in binary.
+                let guid = if record.record_type >> 4 >= 4 {
+                    Uuid::from_slice(cursor.take(16)?).unwrap_or_else(|_| Uuid::nil()).to_string()
+                } else {
+                    cursor.string()?
+                };
+                self.disks.push(LdmDisk { id, name, guid });
+            }
+            LDM_VBLK_DISK_GROUP => self.disk_groups.push(LdmDiskGroup { id, name }),
+            record_type => warn!("Ignoring LDM record {} with type 0x{:02x}", id, record_type),
+        }
+
+        Ok(())
+    }
+
+    pub fn get_volume(&self, name: &str) -> Option<&LdmVolume> {
+        self.volumes.iter().find(|volume| volume.name == name)
+    }
+
+    /// The disk record for the disk this database was read from.
+    pub fn get_this_disk(&self) -> Option<&LdmDisk> {
+        self.disks.iter().find(|disk| disk.guid.eq_ignore_ascii_case(&self.private_header.disk_guid))
+    }
+
+    pub fn get_disk_name(&self, disk_id: u64) -> &str {
+        self.disks.iter().find(|disk| disk.id == disk_id).map(|disk| disk.name.as_str()).unwrap_or("unknown")
+    }
+
+    pub fn get_volume_components(&self, volume: &LdmVolume) -> Vec<&LdmComponent> {
+        self.components.iter().filter(|component| component.volume_id == volume.id).collect()
+    }
+
+    /// The partitions of a component, in the order they appear in the volume.
+    pub fn get_component_partitions(&self, component: &LdmComponent) -> Vec<&LdmPartition> {
+        let mut partitions: Vec<&LdmPartition> =
+            self.partitions.iter().filter(|partition| partition.component_id == component.id).collect();
+        partitions.sort_by_key(|partition| (partition.volume_offset, partition.index));
+        partitions
+    }
+
+    /// Map a simple or spanned volume onto byte extents of this disk.
+    pub fn get_volume_extents(&self, volume: &LdmVolume) -> Result<Vec<Extent>, ImageError> {
+        let components = self.get_volume_components(volume);
+        let component = match components.as_slice() {
+            [component] => component,
+            _ => {
+                return Err(ImageError::Unsupported(format!(
+                    "volume {} has {} components; only simple and spanned volumes can be read",
+                    volume.name,
+                    components.len()
+                )))
+            }
+        };
+
+        if component.component_type != LDM_COMPONENT_SPANNED {
+            return Err(ImageError::Unsupported(format!(
+                "volume {} is {}; only simple and spanned volumes can be read",
+                volume.name,
+                get_ldm_component_type_name(component.component_type)
+            )));
+        }
+
+        let this_disk = match self.get_this_disk() {
+            Some(disk) => disk,
+            None => {
+                return Err(ImageError::invalid_metadata(
+                    "LDM",
+                    format!("no disk record for {}", self.private_header.disk_guid),
+                ))
+            }
+        };
+
+        let mut extents = Vec::new();
+        for partition in self.get_component_partitions(component) {
+            if partition.disk_id != this_disk.id {
+                return Err(ImageError::Unsupported(format!(
+                    "volume {} continues on disk {}, which is not this image",
+                    volume.name,
+                    self.get_disk_name(partition.disk_id)
+                )));
+            }
+
+            extents.push(Extent {
+                logical_start: partition.volume_offset * LDM_SECTOR_SIZE,
+                physical_start: (self.private_header.logical_disk_start + partition.start) * LDM_SECTOR_SIZE,
+                length: partition.size * LDM_SECTOR_SIZE,
+            });
+        }
+
+        Ok(extents)
+    }
+
+    /// A reader over a simple or spanned volume; `reader` must be positioned over the whole disk.
+    pub fn get_volume_reader<R: Read + Seek>(&self, reader: R, name: &str) -> Result<ExtentReader<R>, ImageError> {
+        match self.get_volume(name) {
+            Some(volume) => Ok(ExtentReader::new(reader, self.get_volume_extents(volume)?)),
+            None => Err(ImageError::invalid_metadata("LDM", format!("no volume named {}", name))),
+        }
+    }
+}
+
+impl Display for LdmDatabase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let ph = &self.private_header;
+        write!(
+            f,
+            "PRIVHEAD: sector {}, version {}.{}\nDisk GUID: {}\nHost GUID: {}\nDisk group: {} ({})\n\
+             Logical disk: sector {}, {} sectors\nDatabase: sector {}, {} sectors, VMDB version {}.{}, \
+             committed sequence {}",
+            self.privhead_lba,
+            ph.version_major,
+            ph.version_minor,
+            ph.disk_guid,
+            ph.host_guid,
+            ph.disk_group_name,
+            ph.disk_group_guid,
+            ph.logical_disk_start,
+            ph.logical_disk_size,
+            ph.config_start,
+            ph.config_size,
+            self.vmdb_version_major,
+            self.vmdb_version_minor,
+            self.committed_sequence,
+        )?;
+
+        for disk in self.disks.iter() {
+            write!(f, "\n    Disk {}: GUID {}", disk.name, disk.guid)?;
+        }
+
+        for volume in self.volumes.iter() {
+            write!(
+                f,
+                "\n    Volume {}: type {}, {} sectors, partition type 0x{:02x}, GUID {}",
+                volume.name, volume.volume_type, volume.size, volume.partition_type, volume.guid
+            )?;
+            if let Some(drive_hint) = &volume.drive_hint {
+                write!(f, ", drive {}", drive_hint)?;
+            }
+
+            for component in self.get_volume_components(volume) {
+                write!(
+                    f,
+                    "\n        Component {}: {}, {}",
+                    component.name,
+                    get_ldm_component_type_name(component.component_type),
+                    component.state
+                )?;
+                if component.columns > 0 {
+                    write!(f, ", {} columns, stripe size {} sectors", component.columns, component.stripe_size)?;
+                }
+
+                for partition in self.get_component_partitions(component) {
+                    write!(
+                        f,
+                        "\n            Extent {}: disk {}, sector {}, {} sectors, volume offset {}",
+                        partition.name,
+                        self.get_disk_name(partition.disk_id),
+                        ph.logical_disk_start + partition.start,
+                        partition.size,
+                        partition.volume_offset
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Detect the PRIVHEAD of an MBR dynamic disk.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    match read_probe_bytes(reader, LDM_MBR_PRIVHEAD_LBA * LDM_SECTOR_SIZE, LDM_SECTOR_SIZE as usize)? {
+        Some(data) if &data[0..8] == LDM_PRIVHEAD_MAGIC => {
+            let header = LdmPrivateHeader::new(&data)?;
+            let mut result = ProbeResult::new("ldm", ProbeUsage::PartitionTable, ProbeConfidence::High);
+            result.version = Some(format!("{}.{}", header.version_major, header.version_minor));
+            result.label = Some(header.disk_group_name).filter(|name| !name.is_empty());
+            result.uuid = Some(header.disk_guid).filter(|guid| !guid.is_empty());
+            Ok(Some(result))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const DISK_GUID: &str = "11111111-2222-3333-4444-555555555555";
+
+    fn number(value: u64) -> Vec<u8> {
+        let bytes: Vec<u8> = value.to_be_bytes().iter().copied().skip_while(|&b| b == 0).collect();
+        let mut field = vec![bytes.len() as u8];
+        field.extend(bytes);
+        field
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut field = vec![value.len() as u8];
+        field.extend(value.as_bytes());
+        field
+    }
+
+    // Build the record bytes that follow the 16-byte VBLK header.
+    fn record(flags: u8, record_type: u8, id: u64, name: &str, body: Vec<u8>) -> Vec<u8> {
+        let mut data = vec![0, 0, flags, record_type, 0, 0, 0, 0];
+        data.extend(number(id));
+        data.extend(string(name));
+        data.extend(body);
+        data
+    }
+
+    fn write_vblk(image: &mut [u8], offset: usize, sequence: u32, group: u32, entry: u16, count: u16, data: &[u8]) {
+        image[offset..offset + 4].copy_from_slice(LDM_VBLK_MAGIC);
+        image[offset + 4..offset + 8].copy_from_slice(&sequence.to_be_bytes());
+        image[offset + 8..offset + 12].copy_from_slice(&group.to_be_bytes());
+        image[offset + 12..offset + 14].copy_from_slice(&entry.to_be_bytes());
+        image[offset + 14..offset + 16].copy_from_slice(&count.to_be_bytes());
+        image[offset + 16..offset + 16 + data.len()].copy_from_slice(data);
+    }
+
+    #[test]
+    fn read_spanned_volume() {
+        let mut image = vec![0u8; 4096 * 512];
+
+        let ph = &mut image[6 * 512..7 * 512];
+        ph[0..8].copy_from_slice(LDM_PRIVHEAD_MAGIC);
+        ph[0x0c..0x0e].copy_from_slice(&2u16.to_be_bytes());
+        ph[0x0e..0x10].copy_from_slice(&12u16.to_be_bytes());
+        ph[0x30..0x30 + DISK_GUID.len()].copy_from_slice(DISK_GUID.as_bytes());
+        ph[0xf0..0xf6].copy_from_slice(b"WinDg0");
+        ph[0x11b..0x123].copy_from_slice(&63u64.to_be_bytes());
+        ph[0x123..0x12b].copy_from_slice(&1000u64.to_be_bytes());
+        ph[0x12b..0x133].copy_from_slice(&2048u64.to_be_bytes());
+        ph[0x133..0x13b].copy_from_slice(&2048u64.to_be_bytes());
+
+        let toc = &mut image[2049 * 512..2050 * 512];
+        toc[0..8].copy_from_slice(LDM_TOCBLOCK_MAGIC);
+        toc[0x24..0x2a].copy_from_slice(b"config");
+        toc[0x2e..0x36].copy_from_slice(&17u64.to_be_bytes());
+        toc[0x36..0x3e].copy_from_slice(&100u64.to_be_bytes());
+
+        let vmdb_offset = (2048 + 17) * 512;
+        let vmdb = &mut image[vmdb_offset..vmdb_offset + 512];
+        vmdb[0..4].copy_from_slice(LDM_VMDB_MAGIC);
+        vmdb[4..8].copy_from_slice(&12u32.to_be_bytes());
+        vmdb[8..12].copy_from_slice(&128u32.to_be_bytes());
+        vmdb[12..16].copy_from_slice(&512u32.to_be_bytes());
+        vmdb[0x12..0x14].copy_from_slice(&4u16.to_be_bytes());
+        vmdb[0x14..0x16].copy_from_slice(&10u16.to_be_bytes());
+
+        let mut volume = string("gen");
+        volume.extend(string(""));
+        volume.extend(vec![0; 21]);
+        volume.extend(number(1));
+        volume.extend(vec![0; 16]);
+        volume.extend(number(16));
+        volume.extend(vec![0; 4]);
+        volume.push(0x07);
+        volume.extend(vec![0xab; 16]);
+        volume.extend(string("{bd0c0e51-3b1e-11ef-9a6e-000c29d8a0aa}"));
+        volume.extend(string("E:"));
+
+        let mut component = string("ACTIVE");
+        component.push(LDM_COMPONENT_SPANNED);
+        component.extend(vec![0; 4]);
+        component.extend(number(2));
+        component.extend(vec![0; 16]);
+        component.extend(number(1));
+        component.push(0);
+
+        let partition = |start: u64, volume_offset: u64| {
+            let mut body = vec![0; 12];
+            body.extend(&start.to_be_bytes());
+            body.extend(&volume_offset.to_be_bytes());
+            body.extend(number(8));
+            body.extend(number(2));
+            body.extend(number(5));
+            body
+        };
+
+        let records = [
+            record(LDM_VOLUME_FLAG_ID1 | LDM_VOLUME_FLAG_DRIVE, 0x51, 1, "Volume1", volume),
+            record(0, 0x32, 2, "Volume1-01", component),
+            record(0, 0x33, 3, "Disk1-01", partition(100, 8)),
+            record(0, 0x33, 4, "Disk1-02", partition(10, 0)),
+            record(0, 0x34, 5, "Disk1", string(DISK_GUID)),
+            record(0, 0x35, 6, "WinDg0", Vec::new()),
+        ];
+
+        // Split the first record across two VBLKs to check that fragments are joined.
+        let first = &records[0];
+        write_vblk(&mut image, vmdb_offset + 512, 4, 1, 0, 2, &first[..112]);
+        write_vblk(&mut image, vmdb_offset + 640, 5, 1, 1, 2, &first[112..]);
+        for (i, data) in records[1..].iter().enumerate() {
+            write_vblk(&mut image, vmdb_offset + 768 + 128 * i, 6 + i as u32, 2 + i as u32, 0, 1, data);
+        }
+
+        image[(63 + 10) * 512] = 0xaa;
+        image[(63 + 100) * 512] = 0xbb;
+
+        let mut reader = Cursor::new(image);
+        let db = LdmDatabase::detect(&mut reader).unwrap().unwrap();
+        assert_eq!(db.private_header.disk_group_name, "WinDg0");
+        assert_eq!(db.volumes.len(), 1);
+        assert_eq!(db.volumes[0].drive_hint.as_deref(), Some("E:"));
+        assert_eq!(db.volumes[0].size, 16);
+        assert_eq!(db.components[0].component_type, LDM_COMPONENT_SPANNED);
+        assert_eq!(db.partitions.len(), 2);
+        assert_eq!(db.get_this_disk().unwrap().name, "Disk1");
+        assert_eq!(db.disk_groups[0].name, "WinDg0");
+
+        let mut volume = db.get_volume_reader(&mut reader, "Volume1").unwrap();
+        assert_eq!(volume.len(), 16 * 512);
+        let mut data = vec![0u8; 16 * 512];
+        volume.read_exact(&mut data).unwrap();
+        assert_eq!(data[0], 0xaa);
+        assert_eq!(data[8 * 512], 0xbb);
+    }
+}
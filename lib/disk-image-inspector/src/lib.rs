@@ -0,0 +1,117 @@
+//! Parsers for inspecting raw disk images.
+//!
+//! The library reads the structures found at the start of a disk image -- the master boot record (MBR) and its
+//! extended boot records, BSD disklabels, the GUID partition table (GPT), the Apple Partition Map (APM), Sun disk
+//! labels, SGI volume headers, Amiga Rigid Disk Blocks, Atari AHDI root sectors, Windows LDM databases, LVM2 physical
+//! volumes, Linux md RAID superblocks, LUKS headers, ISO 9660 volumes with their El Torito boot catalogs, and FAT
+//! filesystems -- from any `Read + Seek` source.
+//!
+//! With the `luks-unlock` feature, LUKS volumes can also be unlocked with a passphrase or key file and their
+//! decrypted payload read through [LuksReader].
+//!
+//! ```no_run
+//! use disk_image_inspector::{BlockDevice, BootSector, FatPartition};
+//! use std::fs::File;
+//!
+//! let mut device = BlockDevice::detect_sector_size(File::open("disk.img").unwrap()).unwrap();
+//! let boot_sector = BootSector::from_disk_image(&mut device, 0).unwrap();
+//! let partition_reader = boot_sector.partitions[0].get_partition_reader(&mut device, 0).unwrap();
+//! let fat = FatPartition::from_partition_image(partition_reader, 0).unwrap();
+//! println!("{}", fat.boot_sector);
+//! ```
+
+/// Atari AHDI partition table parsing.
+pub mod ahdi;
+
+/// Apple Partition Map parsing.
+pub mod apm;
+
+/// Boot loader identification from MBR and VBR boot code.
+pub mod bootcode;
+
+/// BSD disklabel parsing inside MBR slices.
+pub mod bsdlabel;
+
+/// Block device abstraction carrying the logical sector size.
+pub mod blockdev;
+
+/// Master boot record and extended boot record parsing.
+pub mod bootsector;
+
+/// Error types returned by the parsers.
+pub mod errors;
+
+/// FAT12/FAT16/FAT32 filesystem parsing.
+pub mod fat;
+
+/// GUID partition table parsing.
+pub mod gpt;
+
+/// ISO 9660 volume, Joliet, Rock Ridge and El Torito boot catalog parsing.
+pub mod iso9660;
+
+/// Windows Logical Disk Manager (dynamic disk) database parsing.
+pub mod ldm;
+
+/// LUKS1 and LUKS2 encrypted volume header parsing.
+pub mod luks;
+
+/// LUKS key slot unlocking and decrypted payload access.
+#[cfg(feature = "luks-unlock")]
+pub mod luks_unlock;
+
+/// Linux LVM2 physical volume and logical volume parsing.
+pub mod lvm;
+
+/// Linux md RAID superblock parsing and RAID 0/1 assembly.
+pub mod md;
+
+/// Partition model shared by all partitioning schemes.
+pub mod partition;
+
+/// Filesystem and container detection.
+pub mod probe;
+
+/// Amiga Rigid Disk Block parsing.
+pub mod rdb;
+
+/// Bounded readers over a byte range of a disk image.
+pub mod reader;
+
+/// SGI disk volume header parsing.
+pub mod sgi;
+
+/// Sun SMI disk label and VTOC parsing.
+pub mod sun;
+
+pub use ahdi::{AhdiPartition, AhdiPartitionEntry, AhdiPartitionTable, AhdiRootSector, AhdiWarning};
+pub use apm::{describe_apm_status, ApmDriverDescriptor, ApmEntry, ApmPartitionMap};
+pub use blockdev::BlockDevice;
+pub use bootcode::{get_boot_code_sha256, identify_boot_code, locate_grub_core, BootLoader, GrubCoreLocation};
+pub use bootsector::{
+    BootSector, CHSPosition, ChsGeometry, EbrChain, EbrChainWarning, LogicalPartition, MBRPartitionType, MbrCheck,
+    MbrWarning, PartitionEntry, BOOT_SECTOR_SIGNATURE, BOOT_SECTOR_SIZE,
+};
+pub use bsdlabel::{BsdDisklabel, BsdPartition};
+pub use errors::ImageError;
+pub use fat::{FatBootSector, FatDirectoryEntry, FatPartition, FatType};
+pub use gpt::{
+    check_gpt_layout, describe_gpt_attributes, GptBackup, GptBackupWarning, GptHeader, GptLayoutWarning,
+    GptPartitionEntry, MbrKind, ProtectiveMbr, ProtectiveMbrWarning, GPT_PARTITION_TYPES, GPT_PRIMARY_HEADER_LBA,
+    MBR_GPT_PARTITION_TYPE,
+};
+pub use iso9660::{
+    ElToritoCatalog, ElToritoEntry, ElToritoImage, IsoDirectoryRecord, IsoImage, IsoVolumeDescriptor, RockRidgeEntry,
+};
+pub use ldm::{find_ldm_privhead_lba, LdmComponent, LdmDatabase, LdmDisk, LdmPartition, LdmPrivateHeader, LdmVolume};
+pub use luks::{LuksChecksum, LuksDigest, LuksHeader, LuksKdf, LuksKeySlot, LuksSegment, LuksToken};
+#[cfg(feature = "luks-unlock")]
+pub use luks_unlock::{LuksReader, LuksVolumeKey, XtsCipher};
+pub use lvm::{LvmLogicalVolume, LvmPhysicalVolume, LvmSegment, LvmVolumeGroup};
+pub use md::{assemble_md_array, MdRole, MdSuperblock, MdSuperblockVersion};
+pub use partition::{discover_device_partitions, discover_partitions, Partition, PartitionScheme, PartitionType};
+pub use probe::{ProbeConfidence, ProbeRegistry, ProbeResult};
+pub use rdb::{RdbFileSystemHeader, RdbPartition, RdbWarning, RigidDiskBlock};
+pub use reader::{Extent, ExtentReader, PartitionReader, StripedReader};
+pub use sgi::{SgiPartition, SgiVolumeHeader};
+pub use sun::{SunSlice, SunVtoc};
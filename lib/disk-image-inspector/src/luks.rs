@@ -0,0 +1,718 @@
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    probe::{decode_label, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const LUKS_MAGIC: &[u8; 6] = b"LUKS\xba\xbe";
+pub const LUKS2_SECONDARY_MAGIC: &[u8; 6] = b"SKUL\xba\xbe";
+pub const LUKS_SECTOR_SIZE: u64 = 512;
+
+pub const LUKS1_HEADER_SIZE: usize = 592;
+pub const LUKS1_KEY_SLOTS: usize = 8;
+pub const LUKS1_KEY_ENABLED: u32 = 0x00ac_71f3;
+pub const LUKS1_DIGEST_SIZE: usize = 20;
+
+// The binary part of a LUKS2 header; the JSON metadata fills the rest of `hdr_size`.
+pub const LUKS2_BINARY_HEADER_SIZE: usize = 4096;
+pub const LUKS2_MIN_HEADER_SIZE: u64 = 16 * 1024;
+pub const LUKS2_MAX_HEADER_SIZE: u64 = 4 * 1024 * 1024;
+pub const LUKS2_CHECKSUM_OFFSET: usize = 448;
+pub const LUKS2_CHECKSUM_SIZE: usize = 64;
+
+/// Decode standard base64 with optional padding, as used for salts and digests in LUKS2 metadata.
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits) as u8);
+        }
+    }
+    Some(data)
+}
+
+/// How a key slot turns a passphrase into the key that unlocks it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LuksKdf {
+    Pbkdf2 {
+        hash: String,
+        iterations: u32,
+    },
+    Argon2 {
+        // "argon2i" or "argon2id".
+        variant: String,
+        time: u32,
+        // In KiB.
+        memory: u32,
+        cpus: u32,
+    },
+    Other(String),
+}
+
+impl Display for LuksKdf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Pbkdf2 { hash, iterations } => write!(f, "pbkdf2 ({}, {} iterations)", hash, iterations),
+            Self::Argon2 {
+                variant,
+                time,
+                memory,
+                cpus,
+            } => write!(f, "{} (time {}, memory {} KiB, {} CPUs)", variant, time, memory, cpus),
+            Self::Other(name) => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LuksKeySlot {
+    pub index: u32,
+    pub slot_type: String,
+    pub enabled: bool,
+    // In bytes.
+    pub key_size: u32,
+    pub kdf: LuksKdf,
+    pub salt: Vec<u8>,
+    // The encrypted, anti-forensic split key material, in bytes from the start of the device.
+    pub area_offset: u64,
+    pub area_size: u64,
+    pub encryption: String,
+    // In bytes; the size of the key derived from the passphrase to decrypt the area.
+    pub area_key_size: u32,
+    pub af_stripes: u32,
+    pub af_hash: String,
+    pub priority: Option<i64>,
+}
+
+impl Display for LuksKeySlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if !self.enabled {
+            return write!(f, "Key slot {}: disabled", self.index);
+        }
+
+        write!(
+            f,
+            "Key slot {}: {}, {}-bit key, KDF {}, area offset {}, {} bytes, {}, AF {} stripes {}",
+            self.index,
+            self.slot_type,
+            self.key_size * 8,
+            self.kdf,
+            self.area_offset,
+            self.area_size,
+            self.encryption,
+            self.af_stripes,
+            self.af_hash
+        )?;
+        if let Some(priority) = self.priority {
+            write!(f, ", priority {}", priority)?;
+        }
+        Ok(())
+    }
+}
+
+/// An encrypted region of the device.
+#[derive(Clone, Debug)]
+pub struct LuksSegment {
+    pub index: u32,
+    pub segment_type: String,
+    // In bytes from the start of the device.
+    pub offset: u64,
+    // In bytes, or `None` if the segment runs to the end of the device.
+    pub size: Option<u64>,
+    pub iv_tweak: u64,
+    pub encryption: String,
+    pub sector_size: u32,
+}
+
+impl Display for LuksSegment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Segment {}: {}, offset {}, size {}, {}, IV tweak {}, sector size {}",
+            self.index,
+            self.segment_type,
+            self.offset,
+            self.size.map(|size| size.to_string()).unwrap_or_else(|| "dynamic".to_string()),
+            self.encryption,
+            self.iv_tweak,
+            self.sector_size
+        )
+    }
+}
+
+/// A digest of the volume key, used to check that a key slot was unlocked correctly.
+#[derive(Clone, Debug)]
+pub struct LuksDigest {
+    pub index: u32,
+    pub digest_type: String,
+    pub hash: String,
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub digest: Vec<u8>,
+    pub keyslots: Vec<u32>,
+    pub segments: Vec<u32>,
+}
+
+impl Display for LuksDigest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Digest {}: {} ({}, {} iterations), key slots {:?}, segments {:?}",
+            self.index, self.digest_type, self.hash, self.iterations, self.keyslots, self.segments
+        )
+    }
+}
+
+/// External unlock metadata, such as a TPM2 or FIDO2 binding.
+#[derive(Clone, Debug)]
+pub struct LuksToken {
+    pub index: u32,
+    pub token_type: String,
+    pub keyslots: Vec<u32>,
+}
+
+impl Display for LuksToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Token {}: {}, key slots {:?}", self.index, self.token_type, self.keyslots)
+    }
+}
+
+/// The checksum of one copy of a LUKS2 header.
+#[derive(Clone, Debug)]
+pub struct LuksChecksum {
+    pub offset: u64,
+    pub algorithm: String,
+    pub sequence_id: u64,
+    pub stored: Vec<u8>,
+    // `None` if the algorithm is not supported.
+    pub computed: Option<Vec<u8>>,
+}
+
+impl LuksChecksum {
+    pub fn is_valid(&self) -> bool {
+        self.computed.as_ref() == Some(&self.stored)
+    }
+}
+
+impl Display for LuksChecksum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "header at offset 0x{:x}, sequence ID {}, {} {}",
+            self.offset,
+            self.sequence_id,
+            self.algorithm,
+            match &self.computed {
+                Some(_) if self.is_valid() => "valid".to_string(),
+                Some(computed) => format!("mismatch, computed {}", hex::encode(computed)),
+                None => "not checked (unsupported algorithm)".to_string(),
+            }
+        )
+    }
+}
+
+/// A LUKS1 or LUKS2 header, with the key slot, segment, digest and token metadata normalized across both versions.
+#[derive(Clone, Debug)]
+pub struct LuksHeader {
+    pub version: u16,
+    pub uuid: String,
+    pub label: Option<String>,
+    pub subsystem: Option<String>,
+    // For LUKS2, the size of each header copy including its JSON area.
+    pub header_size: u64,
+    pub cipher: String,
+    // In bytes.
+    pub key_size: u32,
+    // LUKS2 header copies; LUKS1 headers have no checksum.
+    pub checksums: Vec<LuksChecksum>,
+    pub keyslots: Vec<LuksKeySlot>,
+    pub segments: Vec<LuksSegment>,
+    pub digests: Vec<LuksDigest>,
+    pub tokens: Vec<LuksToken>,
+}
+
+impl LuksHeader {
+    /// Read the LUKS header at the start of `reader`, or return `None` if there is no LUKS magic.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        let data = match read_probe_bytes(reader, 0, LUKS1_HEADER_SIZE)? {
+            Some(data) if &data[0..6] == LUKS_MAGIC => data,
+            _ => return Ok(None),
+        };
+
+        match u16::from_be_bytes([data[6], data[7]]) {
+            1 => Ok(Some(Self::new_luks1(&data)?)),
+            2 => Ok(Some(Self::read_luks2(reader)?)),
+            version => Err(ImageError::Unsupported(format!("LUKS version {}", version))),
+        }
+    }
+
+    pub fn new_luks1(data: &[u8]) -> Result<Self, ImageError> {
+        if data.len() < LUKS1_HEADER_SIZE {
+            return Err(ImageError::Truncated {
+                offset: 0,
+                expected: LUKS1_HEADER_SIZE,
+                actual: data.len(),
+            });
+        }
+
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let text = |start: usize, end: usize| decode_label(&data[start..end]).unwrap_or_default();
+
+        let cipher = format!("{}-{}", text(8, 40), text(40, 72));
+        let hash = text(72, 104);
+        let key_size = be32(108);
+
+        let keyslots = (0..LUKS1_KEY_SLOTS)
+            .map(|i| {
+                let slot = 208 + 48 * i;
+                let stripes = be32(slot + 44);
+                LuksKeySlot {
+                    index: i as u32,
+                    slot_type: "luks1".to_string(),
+                    enabled: be32(slot) == LUKS1_KEY_ENABLED,
+                    key_size,
+                    kdf: LuksKdf::Pbkdf2 {
+                        hash: hash.clone(),
+                        iterations: be32(slot + 4),
+                    },
+                    salt: data[slot + 8..slot + 40].to_vec(),
+                    area_offset: be32(slot + 40) as u64 * LUKS_SECTOR_SIZE,
+                    // The split key is padded to whole sectors.
+                    area_size: (key_size as u64 * stripes as u64).div_ceil(LUKS_SECTOR_SIZE) * LUKS_SECTOR_SIZE,
+                    encryption: cipher.clone(),
+                    area_key_size: key_size,
+                    af_stripes: stripes,
+                    af_hash: hash.clone(),
+                    priority: None,
+                }
+            })
+            .collect();
+
+        let segment = LuksSegment {
+            index: 0,
+            segment_type: "crypt".to_string(),
+            offset: be32(104) as u64 * LUKS_SECTOR_SIZE,
+            size: None,
+            iv_tweak: 0,
+            encryption: cipher.clone(),
+            sector_size: LUKS_SECTOR_SIZE as u32,
+        };
+
+        let digest = LuksDigest {
+            index: 0,
+            digest_type: "pbkdf2".to_string(),
+            hash,
+            iterations: be32(164),
+            salt: data[132..164].to_vec(),
+            digest: data[112..112 + LUKS1_DIGEST_SIZE].to_vec(),
+            keyslots: (0..LUKS1_KEY_SLOTS as u32).collect(),
+            segments: vec![0],
+        };
+
+        Ok(Self {
+            version: 1,
+            uuid: text(168, 208),
+            label: None,
+            subsystem: None,
+            header_size: LUKS1_HEADER_SIZE as u64,
+            cipher,
+            key_size,
+            checksums: Vec::new(),
+            keyslots,
+            segments: vec![segment],
+            digests: vec![digest],
+            tokens: Vec::new(),
+        })
+    }
+
+    /// Read a LUKS2 header, checking both copies and taking the metadata from the newest valid one.
+    pub fn read_luks2<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Self, ImageError> {
+        let (primary, primary_checksum) = read_luks2_copy(reader, 0, LUKS_MAGIC)?;
+        let header_size = u64::from_be_bytes(primary[8..16].try_into().unwrap());
+
+        let mut copies = vec![(primary, primary_checksum)];
+        match read_luks2_copy(reader, header_size, LUKS2_SECONDARY_MAGIC) {
+            Ok(secondary) => copies.push(secondary),
+            Err(e) => log::warn!("Unable to read secondary LUKS2 header: {}", e),
+        }
+
+        let checksums: Vec<LuksChecksum> = copies.iter().map(|(_, checksum)| checksum.clone()).collect();
+        let (header, _) = copies
+            .iter()
+            .filter(|(_, checksum)| checksum.is_valid())
+            .max_by_key(|(_, checksum)| checksum.sequence_id)
+            .unwrap_or(&copies[0]);
+
+        let text = |start: usize, end: usize| decode_label(&header[start..end]);
+        let json_end = header[LUKS2_BINARY_HEADER_SIZE..]
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(header.len() - LUKS2_BINARY_HEADER_SIZE);
+        let json: Value =
+            serde_json::from_slice(&header[LUKS2_BINARY_HEADER_SIZE..LUKS2_BINARY_HEADER_SIZE + json_end])
+                .map_err(|e| ImageError::invalid_metadata("LUKS", format!("JSON area is invalid: {}", e)))?;
+
+        let keyslots: Vec<LuksKeySlot> = json_entries(&json, "keyslots")
+            .into_iter()
+            .map(|(index, slot)| {
+                let area = &slot["area"];
+                let kdf = &slot["kdf"];
+                let kdf_type = json_str(kdf, "type");
+                LuksKeySlot {
+                    index,
+                    slot_type: json_str(slot, "type"),
+                    enabled: true,
+                    key_size: json_u64(&slot["key_size"]) as u32,
+                    kdf: match kdf_type.as_str() {
+                        "pbkdf2" => LuksKdf::Pbkdf2 {
+                            hash: json_str(kdf, "hash"),
+                            iterations: json_u64(&kdf["iterations"]) as u32,
+                        },
+                        "argon2i" | "argon2id" => LuksKdf::Argon2 {
+                            variant: kdf_type,
+                            time: json_u64(&kdf["time"]) as u32,
+                            memory: json_u64(&kdf["memory"]) as u32,
+                            cpus: json_u64(&kdf["cpus"]) as u32,
+                        },
+                        _ => LuksKdf::Other(kdf_type),
+                    },
+                    salt: decode_base64(&json_str(kdf, "salt")).unwrap_or_default(),
+                    area_offset: json_u64(&area["offset"]),
+                    area_size: json_u64(&area["size"]),
+                    encryption: json_str(area, "encryption"),
+                    area_key_size: json_u64(&area["key_size"]) as u32,
+                    af_stripes: json_u64(&slot["af"]["stripes"]) as u32,
+                    af_hash: json_str(&slot["af"], "hash"),
+                    priority: slot["priority"].as_i64(),
+                }
+            })
+            .collect();
+
+        let segments: Vec<LuksSegment> = json_entries(&json, "segments")
+            .into_iter()
+            .map(|(index, segment)| LuksSegment {
+                index,
+                segment_type: json_str(segment, "type"),
+                offset: json_u64(&segment["offset"]),
+                size: match segment["size"].as_str() {
+                    Some("dynamic") => None,
+                    _ => Some(json_u64(&segment["size"])),
+                },
+                iv_tweak: json_u64(&segment["iv_tweak"]),
+                encryption: json_str(segment, "encryption"),
+                sector_size: json_u64(&segment["sector_size"]) as u32,
+            })
+            .collect();
+
+        let digests = json_entries(&json, "digests")
+            .into_iter()
+            .map(|(index, digest)| LuksDigest {
+                index,
+                digest_type: json_str(digest, "type"),
+                hash: json_str(digest, "hash"),
+                iterations: json_u64(&digest["iterations"]) as u32,
+                salt: decode_base64(&json_str(digest, "salt")).unwrap_or_default(),
+                digest: decode_base64(&json_str(digest, "digest")).unwrap_or_default(),
+                keyslots: json_indexes(&digest["keyslots"]),
+                segments: json_indexes(&digest["segments"]),
+            })
+            .collect();
+
+        let tokens = json_entries(&json, "tokens")
+            .into_iter()
+            .map(|(index, token)| LuksToken {
+                index,
+                token_type: json_str(token, "type"),
+                keyslots: json_indexes(&token["keyslots"]),
+            })
+            .collect();
+
+        let (cipher, key_size) = match (segments.first(), keyslots.first()) {
+            (Some(segment), Some(slot)) => (segment.encryption.clone(), slot.key_size),
+            (Some(segment), None) => (segment.encryption.clone(), 0),
+            _ => (String::new(), 0),
+        };
+
+        Ok(Self {
+            version: 2,
+            uuid: text(168, 208).unwrap_or_default(),
+            label: text(24, 72),
+            subsystem: text(208, 256),
+            header_size,
+            cipher,
+            key_size,
+            checksums,
+            keyslots,
+            segments,
+            digests,
+            tokens,
+        })
+    }
+
+    /// Whether every header copy that carries a checksum matches it; LUKS1 headers have none.
+    pub fn is_checksum_valid(&self) -> bool {
+        self.checksums.iter().all(LuksChecksum::is_valid)
+    }
+}
+
+impl Display for LuksHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Version: {}\nUUID: {}\nCipher: {}, {}-bit key\nHeader size: {} bytes",
+            self.version,
+            self.uuid,
+            self.cipher,
+            self.key_size * 8,
+            self.header_size,
+        )?;
+        if let Some(label) = &self.label {
+            write!(f, "\nLabel: {}", label)?;
+        }
+        if let Some(subsystem) = &self.subsystem {
+            write!(f, "\nSubsystem: {}", subsystem)?;
+        }
+
+        if self.checksums.is_empty() {
+            f.write_str("\nChecksum: none (LUKS1 headers are not checksummed)")?;
+        }
+        for checksum in self.checksums.iter() {
+            write!(f, "\nChecksum: {}", checksum)?;
+        }
+
+        for slot in self.keyslots.iter() {
+            write!(f, "\n    {}", slot)?;
+        }
+        for segment in self.segments.iter() {
+            write!(f, "\n    {}", segment)?;
+        }
+        for digest in self.digests.iter() {
+            write!(f, "\n    {}", digest)?;
+        }
+        for token in self.tokens.iter() {
+            write!(f, "\n    {}", token)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read one copy of a LUKS2 header (binary header and JSON area) at `offset` and verify its checksum.
+fn read_luks2_copy<R: Read + Seek + ?Sized>(
+    reader: &mut R,
+    offset: u64,
+    magic: &[u8; 6],
+) -> Result<(Vec<u8>, LuksChecksum), ImageError> {
+    let mut binary = vec![0u8; LUKS2_BINARY_HEADER_SIZE];
+    read_exact_at(reader, offset, &mut binary)?;
+    if &binary[0..6] != magic || binary[6..8] != [0, 2] {
+        return Err(ImageError::invalid_metadata("LUKS", format!("no LUKS2 header at offset 0x{:x}", offset)));
+    }
+
+    let header_size = u64::from_be_bytes(binary[8..16].try_into().unwrap());
+    if !(LUKS2_MIN_HEADER_SIZE..=LUKS2_MAX_HEADER_SIZE).contains(&header_size) || !header_size.is_power_of_two() {
+        return Err(ImageError::invalid_metadata("LUKS", format!("header size {} is invalid", header_size)));
+    }
+
+    let mut header = vec![0u8; header_size as usize];
+    read_exact_at(reader, offset, &mut header)?;
+
+    // The checksum covers the whole header copy with the checksum field zeroed.
+    let algorithm = decode_label(&header[72..104]).unwrap_or_default();
+    let stored_field = header[LUKS2_CHECKSUM_OFFSET..LUKS2_CHECKSUM_OFFSET + LUKS2_CHECKSUM_SIZE].to_vec();
+    let mut zeroed = header.clone();
+    zeroed[LUKS2_CHECKSUM_OFFSET..LUKS2_CHECKSUM_OFFSET + LUKS2_CHECKSUM_SIZE].iter_mut().for_each(|b| *b = 0);
+    let computed: Option<Vec<u8>> = match algorithm.as_str() {
+        "sha256" => Some(Sha256::digest(&zeroed).to_vec()),
+        "sha512" => Some(Sha512::digest(&zeroed).to_vec()),
+        _ => None,
+    };
+    let digest_len = computed.as_ref().map(Vec::len).unwrap_or(LUKS2_CHECKSUM_SIZE);
+
+    let checksum = LuksChecksum {
+        offset,
+        algorithm,
+        sequence_id: u64::from_be_bytes(header[16..24].try_into().unwrap()),
+        stored: stored_field[..digest_len].to_vec(),
+        computed,
+    };
+    Ok((header, checksum))
+}
+
+/// The entries of a JSON object keyed by decimal index, such as `keyslots`, in index order.
+fn json_entries<'a>(json: &'a Value, key: &str) -> Vec<(u32, &'a Value)> {
+    let mut entries: Vec<(u32, &Value)> = match json[key].as_object() {
+        Some(object) => object.iter().filter_map(|(index, value)| Some((index.parse().ok()?, value))).collect(),
+        None => Vec::new(),
+    };
+    entries.sort_by_key(|(index, _)| *index);
+    entries
+}
+
+fn json_str(json: &Value, key: &str) -> String {
+    json[key].as_str().unwrap_or_default().to_string()
+}
+
+// LUKS2 stores 64-bit values as decimal strings, since JSON numbers may not hold them exactly.
+fn json_u64(json: &Value) -> u64 {
+    match json {
+        Value::String(s) => s.parse().unwrap_or(0),
+        _ => json.as_u64().unwrap_or(0),
+    }
+}
+
+fn json_indexes(json: &Value) -> Vec<u32> {
+    match json.as_array() {
+        Some(values) => values.iter().map(|value| json_u64(value) as u32).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Detect a LUKS header.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let data = match read_probe_bytes(reader, 0, LUKS1_HEADER_SIZE)? {
+        Some(data) if &data[0..6] == LUKS_MAGIC => data,
+        _ => return Ok(None),
+    };
+
+    let version = u16::from_be_bytes([data[6], data[7]]);
+    let mut result = ProbeResult::new("crypto_LUKS", ProbeUsage::Crypto, ProbeConfidence::High);
+    result.version = Some(version.to_string());
+    result.uuid = decode_label(&data[168..208]);
+    if version == 2 {
+        result.label = decode_label(&data[24..72]);
+    }
+    Ok(Some(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const METADATA: &str = r#"{
+        "keyslots": {
+            "1": {"type": "luks2", "key_size": 64, "af": {"type": "luks1", "stripes": 4000, "hash": "sha256"},
+                  "area": {"type": "raw", "offset": "290816", "size": "258048", "encryption": "aes-xts-plain64",
+                           "key_size": 64},
+                  "kdf": {"type": "pbkdf2", "hash": "sha256", "iterations": 1000, "salt": "c2FsdA=="}},
+            "0": {"type": "luks2", "key_size": 64, "af": {"type": "luks1", "stripes": 4000, "hash": "sha256"},
+                  "area": {"type": "raw", "offset": "32768", "size": "258048", "encryption": "aes-xts-plain64",
+                           "key_size": 64},
+                  "kdf": {"type": "argon2id", "time": 4, "memory": 1048576, "cpus": 4, "salt": "AAEC"}}
+        },
+        "tokens": {"0": {"type": "systemd-tpm2", "keyslots": ["0"]}},
+        "segments": {"0": {"type": "crypt", "offset": "16777216", "size": "dynamic", "iv_tweak": "0",
+                           "encryption": "aes-xts-plain64", "sector_size": 4096}},
+        "digests": {"0": {"type": "pbkdf2", "keyslots": ["0", "1"], "segments": ["0"], "hash": "sha256",
+                          "iterations": 1000, "salt": "AAEC", "digest": "AAECAw=="}},
+        "config": {"json_size": "12288", "keyslots_size": "16744448"}
+    }"#;
+
+    fn write_luks2_copy(image: &mut [u8], offset: usize, magic: &[u8; 6], sequence_id: u64) {
+        let header = &mut image[offset..offset + 16384];
+        header[0..6].copy_from_slice(magic);
+        header[6..8].copy_from_slice(&2u16.to_be_bytes());
+        header[8..16].copy_from_slice(&16384u64.to_be_bytes());
+        header[16..24].copy_from_slice(&sequence_id.to_be_bytes());
+        header[24..28].copy_from_slice(b"data");
+        header[72..78].copy_from_slice(b"sha256");
+        header[168..204].copy_from_slice(b"2f1bd2c8-5b5a-4a5c-8a4b-62d1e4b5a9f0");
+        header[256..264].copy_from_slice(&(offset as u64).to_be_bytes());
+        header[4096..4096 + METADATA.len()].copy_from_slice(METADATA.as_bytes());
+        let checksum = Sha256::digest(&header[..]);
+        header[448..480].copy_from_slice(&checksum);
+    }
+
+    #[test]
+    fn read_luks2_header() {
+        let mut image = vec![0u8; 32768];
+        write_luks2_copy(&mut image, 0, LUKS_MAGIC, 3);
+        write_luks2_copy(&mut image, 16384, LUKS2_SECONDARY_MAGIC, 3);
+        image[16384 + 448] ^= 0xff;
+
+        let header = LuksHeader::detect(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(header.label.as_deref(), Some("data"));
+        assert_eq!(header.cipher, "aes-xts-plain64");
+        assert_eq!(header.key_size, 64);
+        assert!(header.checksums[0].is_valid());
+        assert!(!header.checksums[1].is_valid());
+        assert!(!header.is_checksum_valid());
+
+        assert_eq!(header.keyslots.len(), 2);
+        assert_eq!(
+            header.keyslots[0].kdf,
+            LuksKdf::Argon2 {
+                variant: "argon2id".to_string(),
+                time: 4,
+                memory: 1048576,
+                cpus: 4
+            }
+        );
+        assert_eq!(header.keyslots[0].salt, vec![0, 1, 2]);
+        assert_eq!(header.keyslots[1].area_offset, 290816);
+        assert_eq!(header.segments[0].offset, 16777216);
+        assert_eq!(header.segments[0].size, None);
+        assert_eq!(header.digests[0].digest, vec![0, 1, 2, 3]);
+        assert_eq!(header.digests[0].keyslots, vec![0, 1]);
+        assert_eq!(header.tokens[0].token_type, "systemd-tpm2");
+    }
+
+    #[test]
+    fn read_unterminated_luks2_json() {
+        let mut image = vec![0u8; 16384];
+        write_luks2_copy(&mut image, 0, LUKS_MAGIC, 1);
+        image[4096 + METADATA.len()..].iter_mut().for_each(|b| *b = b' ');
+        image[448..480].fill(0);
+        let checksum = Sha256::digest(&image[..]);
+        image[448..480].copy_from_slice(&checksum);
+
+        let header = LuksHeader::detect(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(header.keyslots.len(), 2);
+    }
+
+    #[test]
+    fn read_luks1_header() {
+        let mut data = vec![0u8; LUKS1_HEADER_SIZE];
+        data[0..6].copy_from_slice(LUKS_MAGIC);
+        data[6..8].copy_from_slice(&1u16.to_be_bytes());
+        data[8..11].copy_from_slice(b"aes");
+        data[40..51].copy_from_slice(b"xts-plain64");
+        data[72..78].copy_from_slice(b"sha256");
+        data[104..108].copy_from_slice(&4096u32.to_be_bytes());
+        data[108..112].copy_from_slice(&64u32.to_be_bytes());
+        data[208..212].copy_from_slice(&LUKS1_KEY_ENABLED.to_be_bytes());
+        data[212..216].copy_from_slice(&2000u32.to_be_bytes());
+        data[248..252].copy_from_slice(&8u32.to_be_bytes());
+        data[252..256].copy_from_slice(&4000u32.to_be_bytes());
+
+        let header = LuksHeader::new_luks1(&data).unwrap();
+        assert_eq!(header.cipher, "aes-xts-plain64");
+        assert!(header.keyslots[0].enabled);
+        assert!(!header.keyslots[1].enabled);
+        assert_eq!(header.keyslots[0].area_offset, 4096);
+        assert_eq!(header.keyslots[0].area_size, 256000);
+        assert_eq!(header.segments[0].offset, 4096 * 512);
+    }
+}
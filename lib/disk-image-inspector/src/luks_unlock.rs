@@ -0,0 +1,526 @@
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128, Aes256,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use pbkdf2::pbkdf2_hmac;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    luks::{LuksHeader, LuksKdf, LuksKeySlot, LUKS_SECTOR_SIZE},
+};
+
+const XTS_BLOCK_SIZE: usize = 16;
+
+fn pbkdf2(hash: &str, password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) -> Result<(), ImageError> {
+    match hash {
+        "sha1" => pbkdf2_hmac::<Sha1>(password, salt, iterations, output),
+        "sha256" => pbkdf2_hmac::<Sha256>(password, salt, iterations, output),
+        "sha512" => pbkdf2_hmac::<Sha512>(password, salt, iterations, output),
+        _ => return Err(ImageError::Unsupported(format!("LUKS hash {}", hash))),
+    }
+    Ok(())
+}
+
+/// Derive the key that decrypts a key slot's area from the passphrase.
+fn derive_slot_key(slot: &LuksKeySlot, passphrase: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let mut key = vec![0u8; slot.area_key_size as usize];
+    match &slot.kdf {
+        LuksKdf::Pbkdf2 { hash, iterations } => pbkdf2(hash, passphrase, &slot.salt, *iterations, &mut key)?,
+        LuksKdf::Argon2 {
+            variant,
+            time,
+            memory,
+            cpus,
+        } => {
+            let algorithm = match variant.as_str() {
+                "argon2i" => Algorithm::Argon2i,
+                _ => Algorithm::Argon2id,
+            };
+            let params = Params::new(*memory, *time, *cpus, Some(key.len())).map_err(|e| {
+                ImageError::invalid_metadata(
+                    "LUKS",
+                    format!("key slot {} has invalid Argon2 parameters: {}", slot.index, e),
+                )
+            })?;
+            Argon2::new(algorithm, Version::V0x13, params)
+                .hash_password_into(passphrase, &slot.salt, &mut key)
+                .map_err(|e| {
+                    ImageError::invalid_metadata(
+                        "LUKS",
+                        format!("key slot {} Argon2 derivation failed: {}", slot.index, e),
+                    )
+                })?;
+        }
+        LuksKdf::Other(name) => return Err(ImageError::Unsupported(format!("LUKS KDF {}", name))),
+    }
+    Ok(key)
+}
+
+/// The LUKS anti-forensic diffuser: hash each digest-sized block together with its big-endian index.
+fn diffuse<D: Digest>(data: &mut [u8]) {
+    let digest_size = <D as Digest>::output_size();
+    for (i, block) in data.chunks_mut(digest_size).enumerate() {
+        let hash = D::new().chain_update((i as u32).to_be_bytes()).chain_update(&block).finalize();
+        block.copy_from_slice(&hash[..block.len()]);
+    }
+}
+
+fn diffuse_with(hash: &str, data: &mut [u8]) -> Result<(), ImageError> {
+    match hash {
+        "sha1" => diffuse::<Sha1>(data),
+        "sha256" => diffuse::<Sha256>(data),
+        "sha512" => diffuse::<Sha512>(data),
+        _ => return Err(ImageError::Unsupported(format!("LUKS hash {}", hash))),
+    }
+    Ok(())
+}
+
+/// Recover a key from its anti-forensic split: every stripe but the last is folded in through the diffuser, and the
+/// last stripe is XORed in directly.
+fn af_merge(material: &[u8], key_size: usize, stripes: usize, hash: &str) -> Result<Vec<u8>, ImageError> {
+    let mut key = vec![0u8; key_size];
+    for (i, stripe) in material.chunks_exact(key_size).take(stripes).enumerate() {
+        key.iter_mut().zip(stripe).for_each(|(k, s)| *k ^= s);
+        if i + 1 < stripes {
+            diffuse_with(hash, &mut key)?;
+        }
+    }
+    Ok(key)
+}
+
+enum AesCipher {
+    Aes128(Box<Aes128>),
+    Aes256(Box<Aes256>),
+}
+
+impl AesCipher {
+    fn new(key: &[u8]) -> Option<Self> {
+        match key.len() {
+            16 => Some(Self::Aes128(Box::new(Aes128::new(GenericArray::from_slice(key))))),
+            32 => Some(Self::Aes256(Box::new(Aes256::new(GenericArray::from_slice(key))))),
+            _ => None,
+        }
+    }
+
+    fn encrypt_block(&self, block: &mut [u8]) {
+        match self {
+            Self::Aes128(cipher) => cipher.encrypt_block(GenericArray::from_mut_slice(block)),
+            Self::Aes256(cipher) => cipher.encrypt_block(GenericArray::from_mut_slice(block)),
+        }
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) {
+        match self {
+            Self::Aes128(cipher) => cipher.decrypt_block(GenericArray::from_mut_slice(block)),
+            Self::Aes256(cipher) => cipher.decrypt_block(GenericArray::from_mut_slice(block)),
+        }
+    }
+}
+
+/// AES in XTS mode with the sector number as the tweak (dm-crypt's `aes-xts-plain64`).
+pub struct XtsCipher {
+    data: AesCipher,
+    tweak: AesCipher,
+}
+
+impl XtsCipher {
+    /// Set up the cipher for a LUKS encryption spec such as `aes-xts-plain64`, with a 256- or 512-bit key.
+    pub fn new(encryption: &str, key: &[u8]) -> Result<Self, ImageError> {
+        if encryption != "aes-xts-plain64" {
+            return Err(ImageError::Unsupported(format!("LUKS encryption {}", encryption)));
+        }
+
+        let (data_key, tweak_key) = key.split_at(key.len() / 2);
+        match (AesCipher::new(data_key), AesCipher::new(tweak_key)) {
+            (Some(data), Some(tweak)) => Ok(Self { data, tweak }),
+            _ => Err(ImageError::Unsupported(format!("{}-bit XTS key", key.len() * 8))),
+        }
+    }
+
+    pub fn decrypt_sector(&self, sector: &mut [u8], sector_number: u64) {
+        self.process_sector(sector, sector_number, false);
+    }
+
+    pub fn encrypt_sector(&self, sector: &mut [u8], sector_number: u64) {
+        self.process_sector(sector, sector_number, true);
+    }
+
+    // Sectors are always a whole number of cipher blocks, so ciphertext stealing is never needed.
+    fn process_sector(&self, sector: &mut [u8], sector_number: u64, encrypt: bool) {
+        let mut tweak = [0u8; XTS_BLOCK_SIZE];
+        tweak[..8].copy_from_slice(&sector_number.to_le_bytes());
+        self.tweak.encrypt_block(&mut tweak);
+
+        for block in sector.chunks_exact_mut(XTS_BLOCK_SIZE) {
+            block.iter_mut().zip(tweak.iter()).for_each(|(b, t)| *b ^= t);
+            if encrypt {
+                self.data.encrypt_block(block);
+            } else {
+                self.data.decrypt_block(block);
+            }
+            block.iter_mut().zip(tweak.iter()).for_each(|(b, t)| *b ^= t);
+
+            // Multiply the tweak by x in GF(2^128), little-endian.
+            let carry = tweak[XTS_BLOCK_SIZE - 1] >> 7;
+            for i in (1..XTS_BLOCK_SIZE).rev() {
+                tweak[i] = (tweak[i] << 1) | (tweak[i - 1] >> 7);
+            }
+            tweak[0] = (tweak[0] << 1) ^ (carry * 0x87);
+        }
+    }
+}
+
+impl Debug for XtsCipher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("XtsCipher { .. }")
+    }
+}
+
+/// The volume (master) key recovered from a key slot.
+pub struct LuksVolumeKey {
+    pub keyslot: u32,
+    pub digest: u32,
+    key: Vec<u8>,
+}
+
+impl LuksVolumeKey {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+impl Debug for LuksVolumeKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "LuksVolumeKey {{ keyslot: {}, digest: {}, .. }}", self.keyslot, self.digest)
+    }
+}
+
+impl LuksHeader {
+    /// Recover the volume key with a passphrase or key file contents, trying each key slot in priority order.
+    pub fn unlock<R: Read + Seek + ?Sized>(
+        &self,
+        reader: &mut R,
+        passphrase: &[u8],
+    ) -> Result<LuksVolumeKey, ImageError> {
+        // LUKS2 priority 2 slots are tried first and priority 0 slots only on request, which is not supported here.
+        let mut slots: Vec<&LuksKeySlot> = self
+            .keyslots
+            .iter()
+            .filter(|slot| slot.enabled && slot.slot_type != "reencrypt" && slot.priority != Some(0))
+            .collect();
+        slots.sort_by_key(|slot| slot.priority != Some(2));
+
+        for slot in slots {
+            match self.unlock_key_slot(reader, slot, passphrase) {
+                Ok(Some(key)) => return Ok(key),
+                Ok(None) => (),
+                Err(e) => log::warn!("Unable to try LUKS key slot {}: {}", slot.index, e),
+            }
+        }
+
+        Err(ImageError::IncorrectPassphrase)
+    }
+
+    fn unlock_key_slot<R: Read + Seek + ?Sized>(
+        &self,
+        reader: &mut R,
+        slot: &LuksKeySlot,
+        passphrase: &[u8],
+    ) -> Result<Option<LuksVolumeKey>, ImageError> {
+        let key_size = slot.key_size as usize;
+        let stripes = slot.af_stripes as usize;
+        let material_size = key_size * stripes;
+        if key_size == 0 || stripes == 0 || material_size as u64 > slot.area_size {
+            return Err(ImageError::invalid_metadata(
+                "LUKS",
+                format!("key slot {} area is too small for its key material", slot.index),
+            ));
+        }
+
+        let slot_key = derive_slot_key(slot, passphrase)?;
+        let cipher = XtsCipher::new(&slot.encryption, &slot_key)?;
+
+        // The area is encrypted in 512-byte sectors numbered from the start of the area.
+        let sector_size = LUKS_SECTOR_SIZE as usize;
+        let mut material = vec![0u8; material_size.div_ceil(sector_size) * sector_size];
+        read_exact_at(reader, slot.area_offset, &mut material)?;
+        for (i, sector) in material.chunks_exact_mut(sector_size).enumerate() {
+            cipher.decrypt_sector(sector, i as u64);
+        }
+
+        let key = af_merge(&material[..material_size], key_size, stripes, &slot.af_hash)?;
+        for digest in self.digests.iter().filter(|digest| digest.keyslots.contains(&slot.index)) {
+            if digest.digest_type != "pbkdf2" {
+                log::warn!("Unsupported LUKS digest type {}", digest.digest_type);
+                continue;
+            }
+
+            let mut computed = vec![0u8; digest.digest.len()];
+            pbkdf2(&digest.hash, &key, &digest.salt, digest.iterations, &mut computed)?;
+            if computed == digest.digest {
+                return Ok(Some(LuksVolumeKey {
+                    keyslot: slot.index,
+                    digest: digest.index,
+                    key,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// A `Read + Seek` view of the decrypted payload of a LUKS volume.
+///
+/// Positions are relative to the start of the first segment. Each read decrypts whole sectors and caches the last one.
+#[derive(Debug)]
+pub struct LuksReader<R: Read + Seek> {
+    inner: R,
+    cipher: XtsCipher,
+    start: u64,
+    length: u64,
+    sector_size: u64,
+    // The IV of the first sector, in segment sectors.
+    iv_offset: u64,
+    position: u64,
+    sector: Vec<u8>,
+    sector_number: Option<u64>,
+}
+
+impl<R: Read + Seek> LuksReader<R> {
+    pub fn new(mut inner: R, header: &LuksHeader, key: &LuksVolumeKey) -> Result<Self, ImageError> {
+        let segment = header
+            .segments
+            .iter()
+            .find(|segment| segment.segment_type == "crypt")
+            .ok_or_else(|| ImageError::invalid_metadata("LUKS", "no crypt segment".to_string()))?;
+        let sector_size = segment.sector_size as u64;
+        if sector_size == 0 || !sector_size.is_multiple_of(LUKS_SECTOR_SIZE) {
+            return Err(ImageError::invalid_metadata(
+                "LUKS",
+                format!("segment sector size {} is invalid", sector_size),
+            ));
+        }
+
+        // `iv_tweak` counts 512-byte sectors, like dm-crypt's IV offset, but larger sectors take their IV from the
+        // sector number in segment sectors.
+        let sectors_per_segment_sector = sector_size / LUKS_SECTOR_SIZE;
+        if !segment.iv_tweak.is_multiple_of(sectors_per_segment_sector) {
+            return Err(ImageError::invalid_metadata(
+                "LUKS",
+                format!("IV tweak {} is not a whole number of {}-byte sectors", segment.iv_tweak, sector_size),
+            ));
+        }
+
+        let length = match segment.size {
+            Some(size) => size,
+            None => {
+                let end = inner.seek(SeekFrom::End(0)).map_err(|source| ImageError::Io { offset: 0, source })?;
+                end.saturating_sub(segment.offset)
+            }
+        };
+
+        Ok(Self {
+            inner,
+            cipher: XtsCipher::new(&segment.encryption, key.key())?,
+            start: segment.offset,
+            length: length / sector_size * sector_size,
+            sector_size,
+            iv_offset: segment.iv_tweak / sectors_per_segment_sector,
+            position: 0,
+            sector: vec![0u8; sector_size as usize],
+            sector_number: None,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for LuksReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let sector_number = self.position / self.sector_size;
+        if self.sector_number != Some(sector_number) {
+            self.sector_number = None;
+            self.inner.seek(SeekFrom::Start(self.start + sector_number * self.sector_size))?;
+            self.inner.read_exact(&mut self.sector)?;
+            // LUKS2 counts IVs in units of the segment sector size.
+            self.cipher.decrypt_sector(&mut self.sector, self.iv_offset + sector_number);
+            self.sector_number = Some(sector_number);
+        }
+
+        let offset = (self.position % self.sector_size) as usize;
+        let n_read = buf.len().min(self.sector.len() - offset);
+        buf[..n_read].copy_from_slice(&self.sector[offset..offset + n_read]);
+        self.position += n_read as u64;
+        Ok(n_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for LuksReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let new_position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.length.checked_add_signed(delta),
+        };
+
+        match new_position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(IoError::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fat::{FatPartition, FatType},
+        luks::{LUKS1_HEADER_SIZE, LUKS1_KEY_ENABLED, LUKS_MAGIC},
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn xts_test_vector() {
+        // IEEE 1619-2007 XTS-AES-128 vector 1: all-zero keys, sector 0, 32 zero bytes.
+        let cipher = XtsCipher::new("aes-xts-plain64", &[0u8; 32]).unwrap();
+        let mut data = [0u8; 32];
+        cipher.encrypt_sector(&mut data, 0);
+        assert_eq!(hex::encode(data), "917cf69ebd68b2ec9b9fe9a3eadda692cd43d2f59598ed858c02c2652fbf922e");
+        cipher.decrypt_sector(&mut data, 0);
+        assert_eq!(data, [0u8; 32]);
+    }
+
+    const PASSPHRASE: &[u8] = b"correct horse";
+
+    /// Build a LUKS1 image with one key slot for `PASSPHRASE` and a 32-byte `volume_key`, whose payload decrypts to
+    /// `payload`.
+    fn make_luks1_volume(volume_key: &[u8], payload: &[u8]) -> Vec<u8> {
+        const STRIPES: usize = 4;
+        const SLOT_SECTOR: u32 = 8;
+        const PAYLOAD_SECTOR: u32 = 16;
+        let salt = [0x5au8; 32];
+        let digest_salt = [0xa5u8; 32];
+
+        let mut image = vec![0u8; PAYLOAD_SECTOR as usize * 512 + payload.len()];
+        let header = &mut image[..LUKS1_HEADER_SIZE];
+        header[0..6].copy_from_slice(LUKS_MAGIC);
+        header[6..8].copy_from_slice(&1u16.to_be_bytes());
+        header[8..11].copy_from_slice(b"aes");
+        header[40..51].copy_from_slice(b"xts-plain64");
+        header[72..78].copy_from_slice(b"sha256");
+        header[104..108].copy_from_slice(&PAYLOAD_SECTOR.to_be_bytes());
+        header[108..112].copy_from_slice(&32u32.to_be_bytes());
+        pbkdf2("sha256", volume_key, &digest_salt, 10, &mut header[112..132]).unwrap();
+        header[132..164].copy_from_slice(&digest_salt);
+        header[164..168].copy_from_slice(&10u32.to_be_bytes());
+        header[208..212].copy_from_slice(&LUKS1_KEY_ENABLED.to_be_bytes());
+        header[212..216].copy_from_slice(&10u32.to_be_bytes());
+        header[216..248].copy_from_slice(&salt);
+        header[248..252].copy_from_slice(&SLOT_SECTOR.to_be_bytes());
+        header[252..256].copy_from_slice(&(STRIPES as u32).to_be_bytes());
+
+        // Split the key with fixed stripes, so the last stripe carries the key.
+        let mut material = vec![0u8; 512];
+        let mut folded = vec![0u8; 32];
+        for i in 0..STRIPES - 1 {
+            let stripe = &mut material[i * 32..(i + 1) * 32];
+            stripe.iter_mut().for_each(|b| *b = i as u8 + 1);
+            folded.iter_mut().zip(stripe.iter()).for_each(|(f, s)| *f ^= s);
+            diffuse_with("sha256", &mut folded).unwrap();
+        }
+        for (i, b) in material[(STRIPES - 1) * 32..STRIPES * 32].iter_mut().enumerate() {
+            *b = folded[i] ^ volume_key[i];
+        }
+        let mut slot_key = vec![0u8; 32];
+        pbkdf2("sha256", PASSPHRASE, &salt, 10, &mut slot_key).unwrap();
+        XtsCipher::new("aes-xts-plain64", &slot_key).unwrap().encrypt_sector(&mut material, 0);
+        image[SLOT_SECTOR as usize * 512..(SLOT_SECTOR as usize + 1) * 512].copy_from_slice(&material);
+
+        let payload_cipher = XtsCipher::new("aes-xts-plain64", volume_key).unwrap();
+        image[PAYLOAD_SECTOR as usize * 512..].copy_from_slice(payload);
+        for (sector, data) in image[PAYLOAD_SECTOR as usize * 512..].chunks_exact_mut(512).enumerate() {
+            payload_cipher.encrypt_sector(data, sector as u64);
+        }
+        image
+    }
+
+    #[test]
+    fn unlock_luks1_volume() {
+        let volume_key: Vec<u8> = (0..32).collect();
+        let payload: Vec<u8> = (0..4).flat_map(|sector| vec![sector as u8; 512]).collect();
+        let image = make_luks1_volume(&volume_key, &payload);
+
+        let mut cursor = Cursor::new(image);
+        let header = LuksHeader::detect(&mut cursor).unwrap().unwrap();
+        assert!(matches!(header.unlock(&mut cursor, b"wrong"), Err(ImageError::IncorrectPassphrase)));
+        let key = header.unlock(&mut cursor, PASSPHRASE).unwrap();
+        assert_eq!(key.keyslot, 0);
+        assert_eq!(key.key(), &volume_key[..]);
+
+        let mut reader = LuksReader::new(cursor, &header, &key).unwrap();
+        assert_eq!(reader.len(), 4 * 512);
+        let mut buf = [0u8; 4];
+        reader.seek(SeekFrom::Start(1022)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn read_fat_filesystem_in_luks1_volume() {
+        let mut filesystem = vec![0u8; 8 * 512];
+        let boot_sector = &mut filesystem[..512];
+        boot_sector[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        boot_sector[3..11].copy_from_slice(b"mkfs.fat");
+        boot_sector[0x0b..0x0d].copy_from_slice(&512u16.to_le_bytes());
+        boot_sector[0x0d] = 1;
+        boot_sector[0x0e..0x10].copy_from_slice(&1u16.to_le_bytes());
+        boot_sector[0x10] = 1;
+        boot_sector[0x11..0x13].copy_from_slice(&16u16.to_le_bytes());
+        boot_sector[0x13..0x15].copy_from_slice(&8u16.to_le_bytes());
+        boot_sector[0x15] = 0xf8;
+        boot_sector[0x16..0x18].copy_from_slice(&1u16.to_le_bytes());
+        boot_sector[510..512].copy_from_slice(&[0x55, 0xaa]);
+        filesystem[512..515].copy_from_slice(&[0xf8, 0xff, 0xff]);
+        filesystem[1024..1035].copy_from_slice(b"HELLO   TXT");
+        filesystem[1024 + 11] = 0x20;
+
+        let volume_key = [0x3cu8; 32];
+        let mut cursor = Cursor::new(make_luks1_volume(&volume_key, &filesystem));
+        let header = LuksHeader::detect(&mut cursor).unwrap().unwrap();
+        let key = header.unlock(&mut cursor, PASSPHRASE).unwrap();
+
+        let mut fat = FatPartition::from_partition_image(LuksReader::new(cursor, &header, &key).unwrap(), 0).unwrap();
+        assert_eq!(fat.fat_type, FatType::Fat12);
+        let entries = fat.get_root_directory_entries().unwrap();
+        assert_eq!(entries[0].get_filename().as_deref(), Some("HELLO.TXT"));
+    }
+}
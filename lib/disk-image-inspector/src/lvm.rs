@@ -0,0 +1,958 @@
+use log::warn;
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+    iter::Peekable,
+    str::CharIndices,
+};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    probe::{decode_c_string, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+    reader::{Extent, ExtentReader},
+};
+
+// LVM always addresses its on-disk structures in 512-byte sectors, whatever the device's logical sector size.
+pub const LVM_SECTOR_SIZE: u64 = 512;
+
+// The label may be in any of the first four sectors.
+pub const LVM_LABEL_SCAN_SECTORS: u64 = 4;
+pub const LVM_LABEL_ID: &[u8; 8] = b"LABELONE";
+pub const LVM_LABEL_TYPE: &[u8; 8] = b"LVM2 001";
+pub const LVM_UUID_LENGTH: usize = 32;
+
+pub const LVM_MDA_HEADER_SIZE: u64 = 512;
+pub const LVM_MDA_MAGIC: &[u8; 16] = b"\x20\x4c\x56\x4d\x32\x20\x78\x5b\x35\x41\x25\x72\x30\x4e\x2a\x3e";
+pub const LVM_RAW_LOCN_IGNORED: u32 = 0x0000_0001;
+
+// Upper bound on the size of the text metadata read from a metadata area.
+pub const MAX_LVM_METADATA_SIZE: u64 = 16 * 1024 * 1024;
+
+// Upper bound on how deeply sections and arrays may nest in the text metadata.
+pub const MAX_LVM_METADATA_DEPTH: usize = 64;
+
+// LVM's CRC is the reflected CRC-32 without the final inversion, seeded with this value.
+pub const LVM_CRC_INITIAL: u32 = 0xf597_a6cf;
+
+/// Compute LVM's variant of CRC-32 over `data`.
+pub fn lvm_crc32(data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new_with_initial(!LVM_CRC_INITIAL);
+    hasher.update(data);
+    !hasher.finalize()
+}
+
+/// Format a 32-character LVM UUID with dashes, as LVM tools print it (6-4-4-4-4-4-6).
+pub fn format_lvm_uuid(uuid: &str) -> String {
+    if uuid.len() != LVM_UUID_LENGTH || !uuid.is_ascii() {
+        return uuid.to_string();
+    }
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for len in [6, 4, 4, 4, 4, 4, 6].iter() {
+        parts.push(&uuid[start..start + len]);
+        start += len;
+    }
+    parts.join("-")
+}
+
+/// A value in LVM's text metadata format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LvmValue {
+    Number(i64),
+    String(String),
+    Array(Vec<LvmValue>),
+    Section(Vec<(String, LvmValue)>),
+}
+
+impl LvmValue {
+    pub fn get(&self, key: &str) -> Option<&LvmValue> {
+        match self {
+            Self::Section(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn get_number(&self, key: &str) -> Option<i64> {
+        match self.get(key) {
+            Some(Self::Number(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn get_string(&self, key: &str) -> Option<&str> {
+        match self.get(key) {
+            Some(Self::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The string elements of an array value, such as a `status` list.
+    pub fn get_strings(&self, key: &str) -> Vec<String> {
+        match self.get(key) {
+            Some(Self::Array(values)) => values
+                .iter()
+                .filter_map(|value| match value {
+                    Self::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The subsections of a section value, in order.
+    pub fn sections(&self) -> Vec<(&str, &LvmValue)> {
+        match self {
+            Self::Section(entries) => entries
+                .iter()
+                .filter(|(_, value)| matches!(value, Self::Section(_)))
+                .map(|(name, value)| (name.as_str(), value))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Parse LVM text metadata into a top-level section.
+pub fn parse_lvm_metadata(text: &str) -> Result<LvmValue, ImageError> {
+    let mut parser = MetadataParser {
+        text,
+        chars: text.char_indices().peekable(),
+        depth: 0,
+    };
+    let entries = parser.parse_entries(None)?;
+    Ok(LvmValue::Section(entries))
+}
+
+struct MetadataParser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    depth: usize,
+}
+
+impl<'a> MetadataParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c == '#' {
+                for (_, c) in self.chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else if c.is_whitespace() || c == '\0' {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ImageError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.chars.next();
+                Ok(())
+            }
+            Some(c) => Err(ImageError::invalid_metadata("LVM", format!("expected '{}', found '{}'", expected, c))),
+            None => Err(ImageError::invalid_metadata("LVM", format!("expected '{}', found end of metadata", expected))),
+        }
+    }
+
+    fn parse_word(&mut self) -> Result<&'a str, ImageError> {
+        self.skip_whitespace();
+        let start = match self.chars.peek() {
+            Some(&(start, _)) => start,
+            None => return Err(ImageError::invalid_metadata("LVM", "unexpected end of metadata".to_string())),
+        };
+
+        let mut end = start;
+        while let Some(&(i, c)) = self.chars.peek() {
+            if c.is_alphanumeric() || "_.+-".contains(c) {
+                end = i + c.len_utf8();
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if end == start {
+            return Err(ImageError::invalid_metadata("LVM", format!("unexpected character at offset {}", start)));
+        }
+        Ok(&self.text[start..end])
+    }
+
+    fn parse_string(&mut self) -> Result<String, ImageError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(value),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                Some((_, c)) => value.push(c),
+                None => break,
+            }
+        }
+
+        Err(ImageError::invalid_metadata("LVM", "unterminated string".to_string()))
+    }
+
+    // Run `parse` one level deeper, failing if the metadata nests too deeply.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ImageError>) -> Result<T, ImageError> {
+        if self.depth == MAX_LVM_METADATA_DEPTH {
+            return Err(ImageError::invalid_metadata(
+                "LVM",
+                format!("sections and arrays are nested more than {} deep", MAX_LVM_METADATA_DEPTH),
+            ));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_value(&mut self) -> Result<LvmValue, ImageError> {
+        match self.peek() {
+            Some('"') => Ok(LvmValue::String(self.parse_string()?)),
+            Some('[') => {
+                self.chars.next();
+                self.nested(|parser| {
+                    let mut values = Vec::new();
+                    loop {
+                        match parser.peek() {
+                            Some(']') => {
+                                parser.chars.next();
+                                return Ok(LvmValue::Array(values));
+                            }
+                            Some(',') => {
+                                parser.chars.next();
+                            }
+                            _ => values.push(parser.parse_value()?),
+                        }
+                    }
+                })
+            }
+            _ => {
+                let word = self.parse_word()?;
+                Ok(match word.parse() {
+                    Ok(n) => LvmValue::Number(n),
+                    Err(_) => LvmValue::String(word.to_string()),
+                })
+            }
+        }
+    }
+
+    fn parse_entries(&mut self, terminator: Option<char>) -> Result<Vec<(String, LvmValue)>, ImageError> {
+        let mut entries = Vec::new();
+        loop {
+            match (self.peek(), terminator) {
+                (None, None) => return Ok(entries),
+                (None, Some(c)) => {
+                    return Err(ImageError::invalid_metadata("LVM", format!("expected '{}', found end of metadata", c)))
+                }
+                (Some(c), Some(t)) if c == t => {
+                    self.chars.next();
+                    return Ok(entries);
+                }
+                _ => (),
+            }
+
+            let name = self.parse_word()?.to_string();
+            match self.peek() {
+                Some('{') => {
+                    self.chars.next();
+                    let section = self.nested(|parser| parser.parse_entries(Some('}')))?;
+                    entries.push((name, LvmValue::Section(section)));
+                }
+                _ => {
+                    self.expect('=')?;
+                    entries.push((name, self.parse_value()?));
+                }
+            }
+        }
+    }
+}
+
+/// A physical volume listed in the volume group metadata.
+#[derive(Clone, Debug)]
+pub struct LvmPhysicalVolumeInfo {
+    pub name: String,
+    pub id: String,
+    pub device: Option<String>,
+    pub status: Vec<String>,
+    pub device_size: u64,
+    // In sectors, from the start of the PV.
+    pub pe_start: u64,
+    pub pe_count: u64,
+}
+
+/// One stripe of a segment: the PV it is on and the first physical extent on that PV.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LvmStripe {
+    pub pv_name: String,
+    pub start_extent: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct LvmSegment {
+    pub start_extent: u64,
+    pub extent_count: u64,
+    pub segment_type: String,
+    pub stripe_size: u64,
+    pub stripes: Vec<LvmStripe>,
+}
+
+impl LvmSegment {
+    /// Whether the segment maps onto a single run of extents on one PV.
+    pub fn is_linear(&self) -> bool {
+        self.segment_type == "striped" && self.stripes.len() == 1
+    }
+}
+
+impl Display for LvmSegment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Extents {}-{}: {}",
+            self.start_extent,
+            self.start_extent + self.extent_count.max(1) - 1,
+            if self.is_linear() { "linear" } else { &self.segment_type }
+        )?;
+
+        let stripes: Vec<String> =
+            self.stripes.iter().map(|stripe| format!("{} extent {}", stripe.pv_name, stripe.start_extent)).collect();
+        if !stripes.is_empty() {
+            write!(f, " on {}", stripes.join(", "))?;
+        }
+
+        if self.stripes.len() > 1 {
+            write!(f, " (stripe size {} sectors)", self.stripe_size)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LvmLogicalVolume {
+    pub name: String,
+    pub id: String,
+    pub status: Vec<String>,
+    pub segments: Vec<LvmSegment>,
+}
+
+impl LvmLogicalVolume {
+    pub fn get_extent_count(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.start_extent + segment.extent_count).max().unwrap_or(0)
+    }
+
+    pub fn is_linear(&self) -> bool {
+        !self.segments.is_empty() && self.segments.iter().all(LvmSegment::is_linear)
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.status.iter().any(|status| status == "VISIBLE")
+    }
+}
+
+/// A volume group, parsed from the text metadata in a PV's metadata area.
+#[derive(Clone, Debug)]
+pub struct LvmVolumeGroup {
+    pub name: String,
+    pub id: String,
+    pub seqno: u64,
+    pub status: Vec<String>,
+    // In sectors.
+    pub extent_size: u64,
+    pub physical_volumes: Vec<LvmPhysicalVolumeInfo>,
+    pub logical_volumes: Vec<LvmLogicalVolume>,
+}
+
+impl LvmVolumeGroup {
+    /// Build the volume group from parsed text metadata; the VG is the only top-level section.
+    pub fn from_metadata(metadata: &LvmValue) -> Result<Self, ImageError> {
+        let (name, vg) = match metadata.sections().into_iter().next() {
+            Some(section) => section,
+            None => return Err(ImageError::invalid_metadata("LVM", "no volume group section".to_string())),
+        };
+
+        let number = |section: &LvmValue, key: &str| section.get_number(key).unwrap_or(0).max(0) as u64;
+
+        let physical_volumes = match vg.get("physical_volumes") {
+            Some(pvs) => pvs
+                .sections()
+                .into_iter()
+                .map(|(pv_name, pv)| LvmPhysicalVolumeInfo {
+                    name: pv_name.to_string(),
+                    id: pv.get_string("id").unwrap_or_default().to_string(),
+                    device: pv.get_string("device").map(|s| s.to_string()),
+                    status: pv.get_strings("status"),
+                    device_size: number(pv, "dev_size"),
+                    pe_start: number(pv, "pe_start"),
+                    pe_count: number(pv, "pe_count"),
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut logical_volumes = Vec::new();
+        if let Some(lvs) = vg.get("logical_volumes") {
+            for (lv_name, lv) in lvs.sections() {
+                let mut segments = Vec::new();
+                for (_, segment) in lv.sections() {
+                    let mut stripes = Vec::new();
+                    if let Some(LvmValue::Array(values)) = segment.get("stripes") {
+                        for pair in values.chunks_exact(2) {
+                            if let (LvmValue::String(pv_name), LvmValue::Number(start_extent)) = (&pair[0], &pair[1]) {
+                                stripes.push(LvmStripe {
+                                    pv_name: pv_name.clone(),
+                                    start_extent: (*start_extent).max(0) as u64,
+                                });
+                            }
+                        }
+                    }
+
+                    segments.push(LvmSegment {
+                        start_extent: number(segment, "start_extent"),
+                        extent_count: number(segment, "extent_count"),
+                        segment_type: segment.get_string("type").unwrap_or("unknown").to_string(),
+                        stripe_size: number(segment, "stripe_size"),
+                        stripes,
+                    });
+                }
+
+                logical_volumes.push(LvmLogicalVolume {
+                    name: lv_name.to_string(),
+                    id: lv.get_string("id").unwrap_or_default().to_string(),
+                    status: lv.get_strings("status"),
+                    segments,
+                });
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            id: vg.get_string("id").unwrap_or_default().to_string(),
+            seqno: number(vg, "seqno"),
+            status: vg.get_strings("status"),
+            extent_size: number(vg, "extent_size"),
+            physical_volumes,
+            logical_volumes,
+        })
+    }
+
+    pub fn get_extent_size_bytes(&self) -> u64 {
+        self.extent_size * LVM_SECTOR_SIZE
+    }
+
+    pub fn get_logical_volume(&self, name: &str) -> Option<&LvmLogicalVolume> {
+        self.logical_volumes.iter().find(|lv| lv.name == name)
+    }
+
+    /// Find the metadata entry for a PV given the undashed UUID from its label.
+    pub fn get_physical_volume_by_uuid(&self, uuid: &str) -> Option<&LvmPhysicalVolumeInfo> {
+        self.physical_volumes.iter().find(|pv| pv.id.replace('-', "") == uuid)
+    }
+
+    /// Map a linear LV onto byte extents of the physical volume named `pv_name`.
+    pub fn get_linear_extents(&self, lv: &LvmLogicalVolume, pv_name: &str) -> Result<Vec<Extent>, ImageError> {
+        let pv = match self.physical_volumes.iter().find(|pv| pv.name == pv_name) {
+            Some(pv) => pv,
+            None => {
+                return Err(ImageError::invalid_metadata(
+                    "LVM",
+                    format!("volume group {} has no PV {}", self.name, pv_name),
+                ))
+            }
+        };
+
+        let overflow =
+            || ImageError::invalid_metadata("LVM", format!("LV {} has extents beyond the addressable range", lv.name));
+        let extent_size = self.extent_size.checked_mul(LVM_SECTOR_SIZE).ok_or_else(overflow)?;
+        let data_start = pv.pe_start.checked_mul(LVM_SECTOR_SIZE).ok_or_else(overflow)?;
+        let mut extents = Vec::new();
+        for segment in lv.segments.iter() {
+            if !segment.is_linear() {
+                return Err(ImageError::Unsupported(format!(
+                    "LV {} has a {} segment with {} stripes; only linear LVs can be read",
+                    lv.name,
+                    segment.segment_type,
+                    segment.stripes.len()
+                )));
+            }
+
+            let stripe = &segment.stripes[0];
+            if stripe.pv_name != pv_name {
+                return Err(ImageError::Unsupported(format!(
+                    "LV {} has a segment on {}, not {}; multi-PV LVs cannot be read from a single PV",
+                    lv.name, stripe.pv_name, pv_name
+                )));
+            }
+
+            extents.push(Extent {
+                logical_start: segment.start_extent.checked_mul(extent_size).ok_or_else(overflow)?,
+                physical_start: stripe
+                    .start_extent
+                    .checked_mul(extent_size)
+                    .and_then(|offset| offset.checked_add(data_start))
+                    .ok_or_else(overflow)?,
+                length: segment.extent_count.checked_mul(extent_size).ok_or_else(overflow)?,
+            });
+        }
+
+        Ok(extents)
+    }
+}
+
+impl Display for LvmVolumeGroup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Name: {}\nUUID: {}\nSequence number: {}\nStatus: {}\nExtent size: {} sectors",
+            self.name,
+            self.id,
+            self.seqno,
+            self.status.join(", "),
+            self.extent_size,
+        )?;
+
+        for pv in self.physical_volumes.iter() {
+            write!(
+                f,
+                "\n    PV {}: UUID {}, device {}, {} extents starting at sector {}",
+                pv.name,
+                pv.id,
+                pv.device.as_deref().unwrap_or("unknown"),
+                pv.pe_count,
+                pv.pe_start
+            )?;
+        }
+
+        for lv in self.logical_volumes.iter() {
+            write!(
+                f,
+                "\n    LV {}: UUID {}, {} extents ({} bytes), status {}",
+                lv.name,
+                lv.id,
+                lv.get_extent_count(),
+                lv.get_extent_count() * self.get_extent_size_bytes(),
+                lv.status.join(", ")
+            )?;
+            for segment in lv.segments.iter() {
+                write!(f, "\n        {}", segment)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A metadata area described by the PV header, and the location of the current metadata within it.
+#[derive(Clone, Debug)]
+pub struct LvmMetadataArea {
+    pub offset: u64,
+    pub size: u64,
+    pub header_checksum_valid: bool,
+    pub metadata_offset: u64,
+    pub metadata_size: u64,
+    pub metadata_checksum: u32,
+    pub metadata_checksum_valid: bool,
+}
+
+/// An LVM2 physical volume: its label, PV header, and the volume group metadata found in its metadata areas.
+#[derive(Clone, Debug)]
+pub struct LvmPhysicalVolume {
+    pub label_sector: u64,
+    pub label_checksum_valid: bool,
+    pub uuid: String,
+    pub device_size: u64,
+    // (offset, size) pairs in bytes; a size of zero means "to the end of the device".
+    pub data_areas: Vec<(u64, u64)>,
+    pub metadata_areas: Vec<LvmMetadataArea>,
+    pub metadata_text: Option<String>,
+    pub volume_group: Option<LvmVolumeGroup>,
+}
+
+impl LvmPhysicalVolume {
+    /// Look for an LVM2 label in the first four sectors of `reader` and read the PV, or return `None` if there is no
+    /// label.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        for sector in 0..LVM_LABEL_SCAN_SECTORS {
+            let label = match read_probe_bytes(reader, sector * LVM_SECTOR_SIZE, LVM_SECTOR_SIZE as usize)? {
+                Some(label) => label,
+                None => return Ok(None),
+            };
+
+            if &label[0..8] == LVM_LABEL_ID && &label[24..32] == LVM_LABEL_TYPE {
+                return Ok(Some(Self::read(reader, sector, &label)?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn read<R: Read + Seek + ?Sized>(reader: &mut R, label_sector: u64, label: &[u8]) -> Result<Self, ImageError> {
+        let le32 = |data: &[u8], offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let le64 = |data: &[u8], offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        let label_checksum_valid = le32(label, 16) == lvm_crc32(&label[20..]);
+        let header_offset = le32(label, 20) as usize;
+        if header_offset + LVM_UUID_LENGTH + 8 > label.len() {
+            return Err(ImageError::invalid_metadata(
+                "LVM",
+                format!("PV header offset {} is outside the label sector", header_offset),
+            ));
+        }
+
+        let header = &label[header_offset..];
+        let uuid = String::from_utf8_lossy(&header[0..LVM_UUID_LENGTH]).to_string();
+        let device_size = le64(header, 32);
+
+        // Two lists of (offset, size) disk locations follow, each terminated by an all-zero entry.
+        let mut lists: Vec<Vec<(u64, u64)>> = vec![Vec::new(), Vec::new()];
+        let mut pos = 40;
+        for list in lists.iter_mut() {
+            while pos + 16 <= header.len() {
+                let (offset, size) = (le64(header, pos), le64(header, pos + 8));
+                pos += 16;
+                if offset == 0 {
+                    break;
+                }
+                list.push((offset, size));
+            }
+        }
+        let metadata_locations = lists.pop().unwrap();
+        let data_areas = lists.pop().unwrap();
+
+        let mut pv = Self {
+            label_sector,
+            label_checksum_valid,
+            uuid,
+            device_size,
+            data_areas,
+            metadata_areas: Vec::new(),
+            metadata_text: None,
+            volume_group: None,
+        };
+
+        for (offset, size) in metadata_locations {
+            match read_metadata_area(reader, offset, size) {
+                Ok((area, text)) => {
+                    if pv.metadata_text.is_none() {
+                        pv.metadata_text = text;
+                    }
+                    pv.metadata_areas.push(area);
+                }
+                Err(e) => warn!("Unable to read LVM metadata area at offset 0x{:x}: {}", offset, e),
+            }
+        }
+
+        if let Some(text) = &pv.metadata_text {
+            pv.volume_group = Some(LvmVolumeGroup::from_metadata(&parse_lvm_metadata(text)?)?);
+        }
+
+        Ok(pv)
+    }
+
+    /// This PV's name (such as `pv0`) in the volume group metadata.
+    pub fn get_pv_name(&self) -> Option<&str> {
+        let vg = self.volume_group.as_ref()?;
+        vg.get_physical_volume_by_uuid(&self.uuid).map(|pv| pv.name.as_str())
+    }
+
+    /// A reader over a linear logical volume stored on this PV; `reader` must be positioned over the PV itself.
+    pub fn get_logical_volume_reader<R: Read + Seek>(
+        &self,
+        reader: R,
+        lv_name: &str,
+    ) -> Result<ExtentReader<R>, ImageError> {
+        let vg = match &self.volume_group {
+            Some(vg) => vg,
+            None => return Err(ImageError::invalid_metadata("LVM", "PV has no volume group metadata".to_string())),
+        };
+
+        let lv = match vg.get_logical_volume(lv_name) {
+            Some(lv) => lv,
+            None => {
+                return Err(ImageError::invalid_metadata(
+                    "LVM",
+                    format!("volume group {} has no LV {}", vg.name, lv_name),
+                ))
+            }
+        };
+
+        let pv_name = match self.get_pv_name() {
+            Some(pv_name) => pv_name,
+            None => {
+                return Err(ImageError::invalid_metadata(
+                    "LVM",
+                    format!("PV {} is not in volume group {}", self.uuid, vg.name),
+                ))
+            }
+        };
+
+        Ok(ExtentReader::new(reader, vg.get_linear_extents(lv, pv_name)?))
+    }
+}
+
+impl Display for LvmPhysicalVolume {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Label sector: {}\nLabel checksum: {}\nPV UUID: {}\nDevice size: {} bytes",
+            self.label_sector,
+            if self.label_checksum_valid { "valid" } else { "mismatch" },
+            format_lvm_uuid(&self.uuid),
+            self.device_size,
+        )?;
+
+        for (offset, size) in self.data_areas.iter() {
+            write!(f, "\nData area: offset 0x{:x}, size {}", offset, size)?;
+        }
+
+        for area in self.metadata_areas.iter() {
+            write!(
+                f,
+                "\nMetadata area: offset 0x{:x}, size {}, header checksum {}, metadata at 0x{:x} ({} bytes, checksum {})",
+                area.offset,
+                area.size,
+                if area.header_checksum_valid { "valid" } else { "mismatch" },
+                area.metadata_offset,
+                area.metadata_size,
+                if area.metadata_checksum_valid { "valid" } else { "mismatch" },
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read a metadata area header and the current metadata text it points to, which may wrap around the end of the
+/// circular buffer.
+fn read_metadata_area<R: Read + Seek + ?Sized>(
+    reader: &mut R,
+    offset: u64,
+    size: u64,
+) -> Result<(LvmMetadataArea, Option<String>), ImageError> {
+    let mut header = [0u8; LVM_MDA_HEADER_SIZE as usize];
+    read_exact_at(reader, offset, &mut header)?;
+    if &header[4..20] != LVM_MDA_MAGIC {
+        return Err(ImageError::invalid_metadata(
+            "LVM",
+            format!("metadata area at offset 0x{:x} has a bad magic number", offset),
+        ));
+    }
+
+    let header_checksum = u32::from_le_bytes(header[0..4].try_into().unwrap());
+    // The circular buffer size comes from the area header; fall back to the PV header's size if it is unset.
+    let size = match u64::from_le_bytes(header[32..40].try_into().unwrap()) {
+        0 => size,
+        header_size => header_size,
+    };
+    let locn = &header[40..64];
+    let metadata_offset = u64::from_le_bytes(locn[0..8].try_into().unwrap());
+    let metadata_size = u64::from_le_bytes(locn[8..16].try_into().unwrap());
+    let metadata_checksum = u32::from_le_bytes(locn[16..20].try_into().unwrap());
+    let flags = u32::from_le_bytes(locn[20..24].try_into().unwrap());
+
+    let mut area = LvmMetadataArea {
+        offset,
+        size,
+        header_checksum_valid: header_checksum == lvm_crc32(&header[4..]),
+        metadata_offset,
+        metadata_size,
+        metadata_checksum,
+        metadata_checksum_valid: false,
+    };
+
+    if metadata_offset == 0 || metadata_size == 0 || flags & LVM_RAW_LOCN_IGNORED != 0 {
+        return Ok((area, None));
+    }
+
+    if metadata_size > MAX_LVM_METADATA_SIZE || metadata_offset >= size {
+        return Err(ImageError::invalid_metadata(
+            "LVM",
+            format!("metadata location 0x{:x}+{} is invalid", metadata_offset, metadata_size),
+        ));
+    }
+
+    let mut text = vec![0u8; metadata_size as usize];
+    let first_len = metadata_size.min(size - metadata_offset) as usize;
+    read_exact_at(reader, offset + metadata_offset, &mut text[..first_len])?;
+    if first_len < text.len() {
+        read_exact_at(reader, offset + LVM_MDA_HEADER_SIZE, &mut text[first_len..])?;
+    }
+
+    area.metadata_checksum_valid = metadata_checksum == lvm_crc32(&text);
+    Ok((area, Some(decode_c_string(&text))))
+}
+
+/// Detect an LVM2 physical volume.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    for sector in 0..LVM_LABEL_SCAN_SECTORS {
+        let label = match read_probe_bytes(reader, sector * LVM_SECTOR_SIZE, LVM_SECTOR_SIZE as usize)? {
+            Some(label) => label,
+            None => return Ok(None),
+        };
+
+        if &label[0..8] == LVM_LABEL_ID && &label[24..32] == LVM_LABEL_TYPE {
+            let header_offset = u32::from_le_bytes(label[20..24].try_into().unwrap()) as usize;
+            let mut result = ProbeResult::new("LVM2_member", ProbeUsage::Raid, ProbeConfidence::High);
+            result.version = Some("LVM2 001".to_string());
+            if header_offset + LVM_UUID_LENGTH <= label.len() {
+                let uuid = String::from_utf8_lossy(&label[header_offset..header_offset + LVM_UUID_LENGTH]);
+                result.uuid = Some(format_lvm_uuid(&uuid));
+            }
+            return Ok(Some(result));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const METADATA: &str = r#"vg0 {
+id = "abcdef-ghij-klmn-opqr-stuv-wxyz-012345"
+seqno = 3
+format = "lvm2" # informational
+status = ["RESIZEABLE", "READ", "WRITE"]
+extent_size = 8
+
+physical_volumes {
+pv0 {
+id = "ABCDEF-GHIJ-KLMN-OPQR-STUV-WXYZ-012345"
+device = "/dev/vda2"
+status = ["ALLOCATABLE"]
+dev_size = 4096
+pe_start = 2048
+pe_count = 256
+}
+}
+
+logical_volumes {
+root {
+id = "rootid"
+status = ["READ", "WRITE", "VISIBLE"]
+segment_count = 2
+segment1 {
+start_extent = 0
+extent_count = 1
+type = "striped"
+stripe_count = 1
+stripes = [
+"pv0", 4
+]
+}
+segment2 {
+start_extent = 1
+extent_count = 1
+type = "striped"
+stripe_count = 1
+stripes = [
+"pv0", 0
+]
+}
+}
+}
+}
+contents = "Text Format Volume Group"
+version = 1
+"#;
+
+    fn make_pv() -> Vec<u8> {
+        let mut image = vec![0u8; 4096 * 512];
+
+        let label = &mut image[512..1024];
+        label[0..8].copy_from_slice(LVM_LABEL_ID);
+        label[8..16].copy_from_slice(&1u64.to_le_bytes());
+        label[20..24].copy_from_slice(&32u32.to_le_bytes());
+        label[24..32].copy_from_slice(LVM_LABEL_TYPE);
+        label[32..64].copy_from_slice(b"ABCDEFGHIJKLMNOPQRSTUVWXYZ012345");
+        label[64..72].copy_from_slice(&(4096u64 * 512).to_le_bytes());
+        label[72..80].copy_from_slice(&(2048u64 * 512).to_le_bytes());
+        // Data area list terminator at 88, then one metadata area at 4096 and its terminator.
+        label[104..112].copy_from_slice(&4096u64.to_le_bytes());
+        label[112..120].copy_from_slice(&8192u64.to_le_bytes());
+        let crc = lvm_crc32(&label[20..]);
+        label[16..20].copy_from_slice(&crc.to_le_bytes());
+
+        // Place the metadata so that it wraps around the end of the 8 KiB circular buffer.
+        let area_size = 8192u64;
+        let text = METADATA.as_bytes();
+        let metadata_offset = area_size - 100;
+        let mda = &mut image[4096..4096 + area_size as usize];
+        mda[4..20].copy_from_slice(LVM_MDA_MAGIC);
+        mda[20..24].copy_from_slice(&1u32.to_le_bytes());
+        mda[24..32].copy_from_slice(&4096u64.to_le_bytes());
+        mda[32..40].copy_from_slice(&area_size.to_le_bytes());
+        mda[40..48].copy_from_slice(&metadata_offset.to_le_bytes());
+        mda[48..56].copy_from_slice(&(text.len() as u64).to_le_bytes());
+        mda[56..60].copy_from_slice(&lvm_crc32(text).to_le_bytes());
+        mda[metadata_offset as usize..].copy_from_slice(&text[..100]);
+        mda[512..512 + text.len() - 100].copy_from_slice(&text[100..]);
+        let crc = lvm_crc32(&mda[4..512]);
+        mda[0..4].copy_from_slice(&crc.to_le_bytes());
+
+        // Extents are 8 sectors; mark extent 0 and extent 4 so the mapping can be checked.
+        image[2048 * 512] = 0xaa;
+        image[(2048 + 4 * 8) * 512] = 0xbb;
+        image
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let metadata = parse_lvm_metadata(METADATA).unwrap();
+        assert_eq!(metadata.get_number("version"), Some(1));
+
+        let vg = LvmVolumeGroup::from_metadata(&metadata).unwrap();
+        assert_eq!(vg.name, "vg0");
+        assert_eq!(vg.status, vec!["RESIZEABLE", "READ", "WRITE"]);
+        assert_eq!(vg.physical_volumes[0].pe_start, 2048);
+        assert_eq!(vg.logical_volumes[0].segments.len(), 2);
+        assert_eq!(
+            vg.logical_volumes[0].segments[0].stripes,
+            vec![LvmStripe {
+                pv_name: "pv0".to_string(),
+                start_extent: 4
+            }]
+        );
+        assert!(vg.logical_volumes[0].is_linear());
+        assert!(vg.logical_volumes[0].is_visible());
+
+        let nested = "a = ".to_string() + &"[".repeat(100_000);
+        assert!(matches!(parse_lvm_metadata(&nested), Err(ImageError::InvalidMetadata { .. })));
+    }
+
+    #[test]
+    fn read_physical_volume() {
+        let mut image = Cursor::new(make_pv());
+        let pv = LvmPhysicalVolume::detect(&mut image).unwrap().unwrap();
+        assert_eq!(pv.label_sector, 1);
+        assert!(pv.label_checksum_valid);
+        assert_eq!(format_lvm_uuid(&pv.uuid), "ABCDEF-GHIJ-KLMN-OPQR-STUV-WXYZ-012345");
+        assert!(pv.metadata_areas[0].header_checksum_valid);
+        assert!(pv.metadata_areas[0].metadata_checksum_valid);
+        assert_eq!(pv.get_pv_name(), Some("pv0"));
+
+        let mut lv = pv.get_logical_volume_reader(&mut image, "root").unwrap();
+        assert_eq!(lv.len(), 2 * 8 * 512);
+        let mut data = vec![0u8; lv.len() as usize];
+        lv.read_exact(&mut data).unwrap();
+        assert_eq!(data[0], 0xbb);
+        assert_eq!(data[8 * 512], 0xaa);
+    }
+}
@@ -0,0 +1,546 @@
+use chrono::DateTime;
+use log::warn;
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::{
+    errors::ImageError,
+    probe::{decode_c_string, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+    reader::{PartitionReader, StripedReader},
+};
+
+pub const MD_SUPERBLOCK_MAGIC: u32 = 0xa92b_4efc;
+
+// Version 0.90 superblocks fill a 4 KiB block in the last 64 KiB-aligned 64 KiB of the device, in host byte order.
+pub const MD_SB0_RESERVED_SIZE: u64 = 64 * 1024;
+pub const MD_SB0_SIZE: usize = 4096;
+pub const MD_SB0_THIS_DISK_WORD: usize = 992;
+pub const MD_SB0_DISK_FAULTY: u32 = 0;
+pub const MD_SB0_DISK_ACTIVE: u32 = 1;
+pub const MD_SB0_DISK_SYNC: u32 = 2;
+
+// Version 1.x superblocks are little-endian: a 256-byte header followed by a 16-bit role for each device.
+pub const MD_SB1_HEADER_SIZE: usize = 256;
+pub const MD_SB1_MAX_DEVICES: u32 = 384;
+pub const MD_SB1_V1_2_OFFSET: u64 = 4096;
+
+pub const MD_ROLE_SPARE: u16 = 0xffff;
+pub const MD_ROLE_FAULTY: u16 = 0xfffe;
+pub const MD_ROLE_JOURNAL: u16 = 0xfffd;
+
+pub const MD_LEVELS: [(i32, &str); 9] = [
+    (-4, "multipath"),
+    (-1, "linear"),
+    (0, "raid0"),
+    (1, "raid1"),
+    (4, "raid4"),
+    (5, "raid5"),
+    (6, "raid6"),
+    (10, "raid10"),
+    (-5, "faulty"),
+];
+
+/// The name of an md RAID level, as mdadm prints it.
+pub fn get_md_level_name(level: i32) -> &'static str {
+    MD_LEVELS.iter().find(|(code, _)| *code == level).map(|(_, name)| *name).unwrap_or("Unknown")
+}
+
+/// Format an md UUID the way mdadm does: four groups of eight hex digits separated by colons.
+pub fn format_md_uuid(uuid: &[u8; 16]) -> String {
+    uuid.chunks_exact(4).map(hex::encode).collect::<Vec<String>>().join(":")
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MdSuperblockVersion {
+    V0_90,
+    V1_0,
+    V1_1,
+    V1_2,
+}
+
+impl Display for MdSuperblockVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(match self {
+            Self::V0_90 => "0.90",
+            Self::V1_0 => "1.0",
+            Self::V1_1 => "1.1",
+            Self::V1_2 => "1.2",
+        })
+    }
+}
+
+/// The role a member device plays in its array.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MdRole {
+    Active(u32),
+    Spare,
+    Faulty,
+    Journal,
+}
+
+impl Display for MdRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Active(slot) => write!(f, "active device {}", slot),
+            Self::Spare => f.write_str("spare"),
+            Self::Faulty => f.write_str("faulty"),
+            Self::Journal => f.write_str("journal"),
+        }
+    }
+}
+
+/// A Linux md RAID superblock, describing the array and this device's place in it.
+#[derive(Clone, Debug)]
+pub struct MdSuperblock {
+    pub version: MdSuperblockVersion,
+    // Byte offset of the superblock from the start of the device.
+    pub offset: u64,
+    pub array_uuid: [u8; 16],
+    pub name: Option<String>,
+    pub level: i32,
+    pub layout: u32,
+    // In bytes.
+    pub chunk_size: u64,
+    pub raid_disks: u32,
+    pub creation_time: u64,
+    pub update_time: u64,
+    pub events: u64,
+    pub role: MdRole,
+    pub device_uuid: Option<[u8; 16]>,
+    // The member's data area, in bytes from the start of the device.
+    pub data_offset: u64,
+    pub data_size: u64,
+    // Bytes of each member used by the array, or 0 if the superblock does not say.
+    pub component_size: u64,
+    pub checksum: u32,
+    /// Checksum computed over the superblock as read, with the checksum field zeroed.
+    pub computed_checksum: u32,
+}
+
+impl MdSuperblock {
+    /// Parse a version 0.90 superblock found at byte `offset` of the device.
+    pub fn new_v0_90(data: &[u8], offset: u64) -> Result<Self, ImageError> {
+        if data.len() < MD_SB0_SIZE {
+            return Err(ImageError::Truncated {
+                offset,
+                expected: MD_SB0_SIZE,
+                actual: data.len(),
+            });
+        }
+
+        let word = |index: usize| u32::from_le_bytes(data[4 * index..4 * index + 4].try_into().unwrap());
+        if word(0) != MD_SUPERBLOCK_MAGIC || word(1) != 0 || word(2) != 90 {
+            return Err(ImageError::invalid_metadata(
+                "md",
+                format!("no version 0.90 superblock at offset 0x{:x}", offset),
+            ));
+        }
+
+        // The checksum is the 64-bit sum of every word, folded into 32 bits.
+        let sum = (0..MD_SB0_SIZE / 4).filter(|&i| i != 38).map(|i| word(i) as u64).sum::<u64>();
+        let computed_checksum = ((sum & 0xffff_ffff) + (sum >> 32)) as u32;
+
+        let mut array_uuid = [0u8; 16];
+        for (i, index) in [5, 13, 14, 15].iter().enumerate() {
+            array_uuid[4 * i..4 * i + 4].copy_from_slice(&word(*index).to_be_bytes());
+        }
+
+        let state = word(MD_SB0_THIS_DISK_WORD + 4);
+        let role = if state & (1 << MD_SB0_DISK_FAULTY) != 0 {
+            MdRole::Faulty
+        } else if state & (1 << MD_SB0_DISK_ACTIVE | 1 << MD_SB0_DISK_SYNC) != 0 {
+            MdRole::Active(word(MD_SB0_THIS_DISK_WORD + 3))
+        } else {
+            MdRole::Spare
+        };
+
+        Ok(Self {
+            version: MdSuperblockVersion::V0_90,
+            offset,
+            array_uuid,
+            name: None,
+            level: word(7) as i32,
+            layout: word(64),
+            chunk_size: word(65) as u64,
+            raid_disks: word(10),
+            creation_time: word(6) as u64,
+            update_time: word(32) as u64,
+            events: (word(40) as u64) << 32 | word(39) as u64,
+            role,
+            device_uuid: None,
+            data_offset: 0,
+            data_size: offset,
+            component_size: word(8) as u64 * 1024,
+            checksum: word(38),
+            computed_checksum,
+        })
+    }
+
+    /// Parse a version 1.x superblock found at byte `offset` of the device.
+    pub fn new_v1(data: &[u8], offset: u64, version: MdSuperblockVersion) -> Result<Self, ImageError> {
+        if data.len() < MD_SB1_HEADER_SIZE {
+            return Err(ImageError::Truncated {
+                offset,
+                expected: MD_SB1_HEADER_SIZE,
+                actual: data.len(),
+            });
+        }
+
+        let le32 = |pos: usize| u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+        let le64 = |pos: usize| u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
+        if le32(0) != MD_SUPERBLOCK_MAGIC || le32(4) != 1 {
+            return Err(ImageError::invalid_metadata(
+                "md",
+                format!("no version 1 superblock at offset 0x{:x}", offset),
+            ));
+        }
+
+        let max_dev = le32(220);
+        if max_dev > MD_SB1_MAX_DEVICES {
+            return Err(ImageError::invalid_metadata("md", format!("superblock lists {} devices", max_dev)));
+        }
+
+        let size = MD_SB1_HEADER_SIZE + 2 * max_dev as usize;
+        if data.len() < size {
+            return Err(ImageError::Truncated {
+                offset,
+                expected: size,
+                actual: data.len(),
+            });
+        }
+
+        // The checksum sums little-endian words (plus a trailing 16-bit word if present) and folds into 32 bits.
+        let mut sum = data[..size]
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(i, _)| *i != 216 / 4)
+            .map(|(_, word)| u32::from_le_bytes(word.try_into().unwrap()) as u64)
+            .sum::<u64>();
+        if size % 4 == 2 {
+            sum += u16::from_le_bytes(data[size - 2..size].try_into().unwrap()) as u64;
+        }
+        let computed_checksum = ((sum & 0xffff_ffff) + (sum >> 32)) as u32;
+
+        let dev_number = le32(160) as usize;
+        let role = if dev_number < max_dev as usize {
+            match u16::from_le_bytes(data[256 + 2 * dev_number..258 + 2 * dev_number].try_into().unwrap()) {
+                MD_ROLE_SPARE => MdRole::Spare,
+                MD_ROLE_FAULTY => MdRole::Faulty,
+                MD_ROLE_JOURNAL => MdRole::Journal,
+                slot => MdRole::Active(slot as u32),
+            }
+        } else {
+            MdRole::Spare
+        };
+
+        let name = decode_c_string(&data[32..64]);
+
+        Ok(Self {
+            version,
+            offset,
+            array_uuid: data[16..32].try_into().unwrap(),
+            name: if name.is_empty() { None } else { Some(name) },
+            level: le32(72) as i32,
+            layout: le32(76),
+            chunk_size: le32(88) as u64 * 512,
+            raid_disks: le32(92),
+            // Only the low 40 bits hold seconds; the rest are microseconds.
+            creation_time: le64(64) & 0xff_ffff_ffff,
+            update_time: le64(192) & 0xff_ffff_ffff,
+            events: le64(200),
+            role,
+            device_uuid: Some(data[168..184].try_into().unwrap()),
+            data_offset: le64(128) * 512,
+            data_size: le64(136) * 512,
+            component_size: le64(80) * 512,
+            checksum: le32(216),
+            computed_checksum,
+        })
+    }
+
+    /// Look for an md superblock at each of the places the formats put it (1.2, 1.1, 1.0, then 0.90), or return `None`
+    /// if there is none.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        let device_size = reader.seek(SeekFrom::End(0)).map_err(|e| ImageError::Io { offset: 0, source: e })?;
+
+        let sb1_len = MD_SB1_HEADER_SIZE + 2 * MD_SB1_MAX_DEVICES as usize;
+        let mut v1_locations = vec![(MD_SB1_V1_2_OFFSET, MdSuperblockVersion::V1_2), (0, MdSuperblockVersion::V1_1)];
+        if device_size >= 8192 {
+            v1_locations.push((((device_size / 512 - 16) & !7) * 512, MdSuperblockVersion::V1_0));
+        }
+
+        for (offset, version) in v1_locations {
+            let len = sb1_len.min(device_size.saturating_sub(offset) as usize);
+            if let Some(data) = read_probe_bytes(reader, offset, len)? {
+                if data.len() >= 8 && data[0..4] == MD_SUPERBLOCK_MAGIC.to_le_bytes() && data[4..8] == [1, 0, 0, 0] {
+                    match Self::new_v1(&data, offset, version) {
+                        Ok(superblock) => return Ok(Some(superblock)),
+                        Err(e) => warn!("Ignoring md superblock at offset 0x{:x}: {}", offset, e),
+                    }
+                }
+            }
+        }
+
+        if device_size >= MD_SB0_RESERVED_SIZE {
+            let offset = (device_size & !(MD_SB0_RESERVED_SIZE - 1)) - MD_SB0_RESERVED_SIZE;
+            if let Some(data) = read_probe_bytes(reader, offset, MD_SB0_SIZE)? {
+                if data[0..4] == MD_SUPERBLOCK_MAGIC.to_le_bytes() {
+                    match Self::new_v0_90(&data, offset) {
+                        Ok(superblock) => return Ok(Some(superblock)),
+                        Err(e) => warn!("Ignoring md superblock at offset 0x{:x}: {}", offset, e),
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn is_checksum_valid(&self) -> bool {
+        self.checksum == self.computed_checksum
+    }
+
+    /// Bytes of this member's data area that belong to the array.
+    pub fn get_member_length(&self) -> u64 {
+        if self.component_size > 0 && (self.data_size == 0 || self.component_size <= self.data_size) {
+            self.component_size
+        } else {
+            self.data_size
+        }
+    }
+}
+
+impl Display for MdSuperblock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let format_time = |secs: u64| match DateTime::from_timestamp(secs as i64, 0) {
+            Some(time) => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            None => format!("{} (invalid)", secs),
+        };
+
+        write!(
+            f,
+            "Version: {} (superblock at offset 0x{:x})\nArray UUID: {}\nName: {}\nLevel: {} ({})\nRAID devices: {}",
+            self.version,
+            self.offset,
+            format_md_uuid(&self.array_uuid),
+            self.name.as_deref().unwrap_or(""),
+            get_md_level_name(self.level),
+            self.level,
+            self.raid_disks,
+        )?;
+
+        if self.level == 0 || self.level >= 4 {
+            write!(f, "\nChunk size: {} bytes\nLayout: {}", self.chunk_size, self.layout)?;
+        }
+
+        write!(
+            f,
+            "\nCreated: {}\nUpdated: {}\nEvents: {}\nMember role: {}",
+            format_time(self.creation_time),
+            format_time(self.update_time),
+            self.events,
+            self.role,
+        )?;
+
+        if let Some(device_uuid) = &self.device_uuid {
+            write!(f, "\nDevice UUID: {}", format_md_uuid(device_uuid))?;
+        }
+
+        write!(
+            f,
+            "\nData offset: {} bytes\nData size: {} bytes\nChecksum: 0x{:08x} ({})",
+            self.data_offset,
+            self.data_size,
+            self.checksum,
+            if self.is_checksum_valid() {
+                "valid".to_string()
+            } else {
+                format!("mismatch, computed 0x{:08x}", self.computed_checksum)
+            },
+        )
+    }
+}
+
+/// Assemble a RAID 1 or RAID 0 array from its members, returning a reader over the array's contents.
+///
+/// A mirror is read from its active member with the highest event count, so a degraded array works with one member.
+/// A stripe set needs every active member; members of different sizes are only read up to the smallest one (md's
+/// first zone).
+pub fn assemble_md_array<R: Read + Seek>(
+    members: Vec<(MdSuperblock, R)>,
+) -> Result<StripedReader<PartitionReader<R>>, ImageError> {
+    let first = match members.first() {
+        Some((superblock, _)) => superblock.clone(),
+        None => return Err(ImageError::Unsupported("no md members to assemble".to_string())),
+    };
+
+    if let Some((superblock, _)) = members.iter().find(|(superblock, _)| superblock.array_uuid != first.array_uuid) {
+        return Err(ImageError::invalid_metadata(
+            "md",
+            format!(
+                "members belong to different arrays ({} and {})",
+                format_md_uuid(&first.array_uuid),
+                format_md_uuid(&superblock.array_uuid)
+            ),
+        ));
+    }
+
+    let mut active: Vec<(u32, MdSuperblock, R)> = members
+        .into_iter()
+        .filter_map(|(superblock, reader)| match superblock.role {
+            MdRole::Active(slot) => Some((slot, superblock, reader)),
+            _ => None,
+        })
+        .collect();
+
+    match first.level {
+        1 => {
+            active.sort_by_key(|(slot, superblock, _)| (std::cmp::Reverse(superblock.events), *slot));
+            let (_, superblock, reader) = match active.into_iter().next() {
+                Some(member) => member,
+                None => return Err(ImageError::Unsupported("RAID 1 array has no active members".to_string())),
+            };
+
+            let length = superblock.get_member_length();
+            let member = PartitionReader::new(reader, superblock.data_offset, length);
+            Ok(StripedReader::new(vec![member], superblock.chunk_size, length))
+        }
+        0 => {
+            active.sort_by_key(|(slot, _, _)| *slot);
+            let slots: Vec<u32> = active.iter().map(|(slot, _, _)| *slot).collect();
+            if slots != (0..first.raid_disks).collect::<Vec<u32>>() {
+                return Err(ImageError::Unsupported(format!(
+                    "RAID 0 array needs devices 0 to {}; have {:?}",
+                    first.raid_disks.saturating_sub(1),
+                    slots
+                )));
+            }
+
+            let lengths: Vec<u64> = active.iter().map(|(_, superblock, _)| superblock.get_member_length()).collect();
+            let length = lengths.iter().copied().min().unwrap_or(0);
+            if lengths.iter().any(|&l| l - l % first.chunk_size.max(1) != length - length % first.chunk_size.max(1)) {
+                warn!("RAID 0 members differ in size; only the first {} bytes of each are read", length);
+            }
+
+            let members = active
+                .into_iter()
+                .map(|(_, superblock, reader)| PartitionReader::new(reader, superblock.data_offset, length))
+                .collect();
+            Ok(StripedReader::new(members, first.chunk_size, length))
+        }
+        level => Err(ImageError::Unsupported(format!(
+            "assembling {} arrays is not supported; only raid0 and raid1 are",
+            get_md_level_name(level)
+        ))),
+    }
+}
+
+/// Detect a Linux md RAID member.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    Ok(MdSuperblock::detect(reader)?.map(|superblock| {
+        let confidence = if superblock.is_checksum_valid() { ProbeConfidence::High } else { ProbeConfidence::Medium };
+        let mut result = ProbeResult::new("linux_raid_member", ProbeUsage::Raid, confidence);
+        result.version = Some(superblock.version.to_string());
+        result.uuid = Some(format_md_uuid(&superblock.array_uuid));
+        result.label = superblock.name;
+        result
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A version 1.2 member with 64 KiB of data at 8 KiB, filled with `fill` and holding role `slot`.
+    fn make_v1_2_member(level: u32, slot: u16, fill: u8) -> Vec<u8> {
+        let mut image = vec![0u8; 8192 + 65536];
+        image[8192..].iter_mut().for_each(|b| *b = fill);
+
+        let sb = &mut image[4096..4096 + 260];
+        sb[0..4].copy_from_slice(&MD_SUPERBLOCK_MAGIC.to_le_bytes());
+        sb[4..8].copy_from_slice(&1u32.to_le_bytes());
+        sb[16..32].copy_from_slice(&[0x11; 16]);
+        sb[32..38].copy_from_slice(b"host:0");
+        sb[72..76].copy_from_slice(&level.to_le_bytes());
+        sb[80..88].copy_from_slice(&128u64.to_le_bytes());
+        sb[88..92].copy_from_slice(&8u32.to_le_bytes());
+        sb[92..96].copy_from_slice(&2u32.to_le_bytes());
+        sb[128..136].copy_from_slice(&16u64.to_le_bytes());
+        sb[136..144].copy_from_slice(&128u64.to_le_bytes());
+        sb[144..152].copy_from_slice(&8u64.to_le_bytes());
+        sb[160..164].copy_from_slice(&(slot as u32).to_le_bytes());
+        sb[200..208].copy_from_slice(&42u64.to_le_bytes());
+        sb[220..224].copy_from_slice(&2u32.to_le_bytes());
+        sb[256 + 2 * slot as usize..258 + 2 * slot as usize].copy_from_slice(&slot.to_le_bytes());
+        sb[258 - 2 * slot as usize..260 - 2 * slot as usize].copy_from_slice(&(1 - slot).to_le_bytes());
+
+        let sum = sb.chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap()) as u64).sum::<u64>();
+        let checksum = ((sum & 0xffff_ffff) + (sum >> 32)) as u32;
+        sb[216..220].copy_from_slice(&checksum.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn detect_superblocks() {
+        let member = make_v1_2_member(1, 1, 0);
+        let sb = MdSuperblock::detect(&mut Cursor::new(member)).unwrap().unwrap();
+        assert_eq!(sb.version, MdSuperblockVersion::V1_2);
+        assert!(sb.is_checksum_valid());
+        assert_eq!(format_md_uuid(&sb.array_uuid), "11111111:11111111:11111111:11111111");
+        assert_eq!(sb.name.as_deref(), Some("host:0"));
+        assert_eq!(sb.role, MdRole::Active(1));
+        assert_eq!(sb.events, 42);
+        assert_eq!(sb.data_offset, 8192);
+
+        // A 0.90 superblock sits in the last 64 KiB of the device.
+        let mut image = vec![0u8; 200 * 1024];
+        let offset = 128 * 1024;
+        let words: [(usize, u32); 9] = [
+            (0, MD_SUPERBLOCK_MAGIC),
+            (2, 90),
+            (5, 0xdeadbeef),
+            (7, 1),
+            (8, 128),
+            (10, 2),
+            (39, 7),
+            (MD_SB0_THIS_DISK_WORD + 3, 0),
+            (MD_SB0_THIS_DISK_WORD + 4, 1 << MD_SB0_DISK_ACTIVE | 1 << MD_SB0_DISK_SYNC),
+        ];
+        for (index, value) in words.iter() {
+            image[offset + 4 * index..offset + 4 * index + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        let sb = MdSuperblock::detect(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(sb.version, MdSuperblockVersion::V0_90);
+        assert_eq!(sb.offset, offset as u64);
+        assert_eq!(format_md_uuid(&sb.array_uuid), "deadbeef:00000000:00000000:00000000");
+        assert_eq!(sb.level, 1);
+        assert_eq!(sb.events, 7);
+        assert_eq!(sb.role, MdRole::Active(0));
+        assert_eq!(sb.get_member_length(), 128 * 1024);
+    }
+
+    #[test]
+    fn assemble_raid0() {
+        let members: Vec<(MdSuperblock, Cursor<Vec<u8>>)> = [(1, 0xbb), (0, 0xaa)]
+            .iter()
+            .map(|&(slot, fill)| {
+                let mut reader = Cursor::new(make_v1_2_member(0, slot, fill));
+                (MdSuperblock::detect(&mut reader).unwrap().unwrap(), reader)
+            })
+            .collect();
+
+        let mut array = assemble_md_array(members).unwrap();
+        assert_eq!(array.len(), 2 * 65536);
+
+        let mut data = Vec::new();
+        array.read_to_end(&mut data).unwrap();
+        assert!(data[..4096].iter().all(|&b| b == 0xaa));
+        assert!(data[4096..8192].iter().all(|&b| b == 0xbb));
+        assert!(data[8192..12288].iter().all(|&b| b == 0xaa));
+    }
+}
@@ -0,0 +1,497 @@
+use log::warn;
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+use uuid::Uuid;
+
+use crate::{
+    ahdi::{get_ahdi_partition_type_name, AhdiPartitionTable, AHDI_FLAG_BOOTABLE},
+    apm::{describe_apm_status, ApmPartitionMap, APM_STATUS_BOOT_INFO},
+    blockdev::BlockDevice,
+    bootsector::{BootSector, EbrChain, MBRPartitionType, PartitionStatusFlag, BOOT_SECTOR_SIGNATURE},
+    bsdlabel::{get_bsd_fstype_name, is_bsd_slice_type, BsdDisklabel},
+    errors::ImageError,
+    gpt::{
+        describe_gpt_attributes, GptHeader, GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE, GPT_PARTITION_TYPES,
+        GPT_PRIMARY_HEADER_LBA, MBR_GPT_PARTITION_TYPE,
+    },
+    rdb::{describe_rdb_partition_flags, format_dos_type, RigidDiskBlock, RDB_PARTITION_BOOTABLE},
+    reader::PartitionReader,
+    sgi::{get_sgi_partition_type_name, SgiVolumeHeader},
+    sun::{describe_sun_flags, get_sun_tag_name, SunVtoc},
+};
+
+// Logical partitions are numbered from 5, as Linux does.
+pub const FIRST_LOGICAL_PARTITION_INDEX: usize = 5;
+
+/// Which partitioning scheme a partition was found in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PartitionScheme {
+    Mbr,
+    Ebr,
+    Gpt,
+    Apm,
+    Bsd,
+    Sun,
+    Sgi,
+    Rdb,
+    Ahdi,
+}
+
+impl Display for PartitionScheme {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Mbr => f.write_str("MBR"),
+            Self::Ebr => f.write_str("EBR"),
+            Self::Gpt => f.write_str("GPT"),
+            Self::Apm => f.write_str("APM"),
+            Self::Bsd => f.write_str("BSD"),
+            Self::Sun => f.write_str("Sun"),
+            Self::Sgi => f.write_str("SGI"),
+            Self::Rdb => f.write_str("RDB"),
+            Self::Ahdi => f.write_str("AHDI"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PartitionType {
+    Mbr(&'static MBRPartitionType),
+    Gpt(Uuid),
+    // APM types are free-form strings such as "Apple_HFS".
+    Apm(String),
+    // BSD disklabel filesystem type (`p_fstype`).
+    Bsd(u8),
+    // Sun VTOC slice tag.
+    Sun(u16),
+    // SGI volume header partition type.
+    Sgi(u32),
+    // AmigaDOS DOS type from the partition's DosEnvec, such as `DOS\3`.
+    Rdb(u32),
+    // Three-character AHDI partition ID such as "BGM".
+    Ahdi(String),
+}
+
+impl PartitionType {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Self::Mbr(mbr_type) => mbr_type.name,
+            Self::Gpt(guid) => GPT_PARTITION_TYPES.get(&guid.as_u128()).unwrap_or(&"Unknown"),
+            Self::Apm(name) => name,
+            Self::Bsd(fstype) => get_bsd_fstype_name(*fstype),
+            Self::Sun(tag) => get_sun_tag_name(*tag),
+            Self::Sgi(partition_type) => get_sgi_partition_type_name(*partition_type),
+            Self::Rdb(_) => "AmigaDOS",
+            Self::Ahdi(id) => get_ahdi_partition_type_name(id),
+        }
+    }
+}
+
+impl PartialEq for PartitionType {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Mbr(a), Self::Mbr(b)) => a.code == b.code,
+            (Self::Gpt(a), Self::Gpt(b)) => a == b,
+            (Self::Apm(a), Self::Apm(b)) => a == b,
+            (Self::Bsd(a), Self::Bsd(b)) => a == b,
+            (Self::Sun(a), Self::Sun(b)) => a == b,
+            (Self::Sgi(a), Self::Sgi(b)) => a == b,
+            (Self::Rdb(a), Self::Rdb(b)) => a == b,
+            (Self::Ahdi(a), Self::Ahdi(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Display for PartitionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Mbr(mbr_type) => mbr_type.fmt(f),
+            Self::Gpt(guid) => write!(f, "{} ({})", guid, self.get_name()),
+            Self::Apm(name) => f.write_str(name),
+            Self::Bsd(fstype) => write!(f, "{} ({})", fstype, self.get_name()),
+            Self::Sun(tag) => write!(f, "0x{:02x} ({})", tag, self.get_name()),
+            Self::Sgi(partition_type) => write!(f, "{} ({})", partition_type, self.get_name()),
+            Self::Rdb(dos_type) => write!(f, "{} (0x{:08x})", format_dos_type(*dos_type), dos_type),
+            Self::Ahdi(id) => write!(f, "{} ({})", id, self.get_name()),
+        }
+    }
+}
+
+/// A partition from any supported partitioning scheme, with absolute addresses.
+#[derive(Clone, Debug)]
+pub struct Partition {
+    pub index: usize,
+    pub scheme: PartitionScheme,
+    pub partition_type: PartitionType,
+    pub name: Option<String>,
+    pub unique_guid: Option<Uuid>,
+    // MBR status byte, GPT attribute bits, APM status bits, Sun VTOC slice flags, RDB partition flags or AHDI flags,
+    // depending on the scheme.
+    pub flags: u64,
+    pub sector_size: u32,
+    pub start_lba: u64,
+    // Inclusive, as in GPT.
+    pub end_lba: u64,
+}
+
+impl Partition {
+    // Offsets and sizes saturate, since the LBAs come straight from the partition table.
+    pub fn get_start_offset(&self) -> u64 {
+        self.start_lba.saturating_mul(self.sector_size as u64)
+    }
+
+    // Exclusive.
+    pub fn get_end_offset(&self) -> u64 {
+        self.end_lba.saturating_add(1).saturating_mul(self.sector_size as u64)
+    }
+
+    pub fn get_sector_count(&self) -> u64 {
+        self.end_lba.saturating_add(1).saturating_sub(self.start_lba)
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.get_sector_count().saturating_mul(self.sector_size as u64)
+    }
+
+    pub fn is_extended(&self) -> bool {
+        matches!(self.partition_type, PartitionType::Mbr(mbr_type) if mbr_type.is_extended)
+    }
+
+    pub fn is_bootable(&self) -> bool {
+        match self.scheme {
+            PartitionScheme::Mbr | PartitionScheme::Ebr => self.flags & 0x80 != 0,
+            PartitionScheme::Gpt => self.flags & GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE != 0,
+            PartitionScheme::Apm => self.flags & APM_STATUS_BOOT_INFO as u64 != 0,
+            PartitionScheme::Rdb => self.flags & RDB_PARTITION_BOOTABLE as u64 != 0,
+            PartitionScheme::Ahdi => self.flags & AHDI_FLAG_BOOTABLE as u64 != 0,
+            PartitionScheme::Bsd | PartitionScheme::Sun | PartitionScheme::Sgi => false,
+        }
+    }
+
+    pub fn get_partition_reader<R: Read + Seek>(&self, reader: R) -> PartitionReader<R> {
+        PartitionReader::new(reader, self.get_start_offset(), self.get_size())
+    }
+
+    /// The partition's number as shown to users; BSD disklabel partitions are lettered from `a`.
+    pub fn get_index_name(&self) -> String {
+        match self.scheme {
+            PartitionScheme::Bsd => ((b'a' + (self.index - 1) as u8) as char).to_string(),
+            _ => self.index.to_string(),
+        }
+    }
+}
+
+impl Display for Partition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{} partition {}: {} LBA {}-{} (bytes 0x{:x}-0x{:x}, {} bytes)",
+            self.scheme,
+            self.get_index_name(),
+            self.partition_type,
+            self.start_lba,
+            self.end_lba,
+            self.get_start_offset(),
+            self.get_end_offset(),
+            self.get_size(),
+        )?;
+
+        if let Some(name) = &self.name {
+            write!(f, " \"{}\"", name)?;
+        }
+
+        if let Some(guid) = &self.unique_guid {
+            write!(f, " GUID {}", guid)?;
+        }
+
+        if self.flags != 0 {
+            write!(f, " flags 0x{:x}", self.flags)?;
+            match &self.partition_type {
+                PartitionType::Gpt(guid) => write!(f, " ({})", describe_gpt_attributes(guid, self.flags).join(", "))?,
+                PartitionType::Apm(_) => write!(f, " ({})", describe_apm_status(self.flags as u32).join(", "))?,
+                PartitionType::Sun(_) => write!(f, " ({})", describe_sun_flags(self.flags as u16).join(", "))?,
+                PartitionType::Rdb(_) => {
+                    write!(f, " ({})", describe_rdb_partition_flags(self.flags as u32).join(", "))?
+                }
+                PartitionType::Mbr(_) | PartitionType::Bsd(_) | PartitionType::Sgi(_) | PartitionType::Ahdi(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Discover every partition on a disk image, detecting the logical sector size automatically.
+pub fn discover_partitions<R: Read + Seek>(reader: &mut R) -> Result<Vec<Partition>, ImageError> {
+    let mut device = BlockDevice::detect_sector_size(reader)?;
+    discover_device_partitions(&mut device)
+}
+
+/// Discover every partition on a block device.
+///
+/// If the MBR has a GPT protective entry, the GPT partitions are returned. Otherwise, if the disk starts with an Apple
+/// Partition Map, a Sun disk label, an SGI volume header or an Amiga Rigid Disk Block, its entries are returned.
+/// Otherwise, if the disk has a boot sector signature, the MBR primary partitions, any logical partitions in extended
+/// partition chains, and the partitions in any BSD slice's disklabel are returned. Failing that, an Atari AHDI root
+/// sector is tried.
+pub fn discover_device_partitions<R: Read + Seek>(device: &mut BlockDevice<R>) -> Result<Vec<Partition>, ImageError> {
+    let boot_sector = BootSector::from_disk_image(device, 0)?;
+    let has_signature = &boot_sector.signature == BOOT_SECTOR_SIGNATURE;
+
+    if has_signature && boot_sector.partitions.iter().any(|entry| entry.partition_type.code == MBR_GPT_PARTITION_TYPE) {
+        return discover_gpt_partitions(device);
+    }
+
+    // APM, Sun, SGI, Amiga and Atari disks have no boot sector signature unless they are hybrid images.
+    if let Some(map) = ApmPartitionMap::detect(device)? {
+        return Ok(map.get_partitions());
+    }
+
+    if let Some(vtoc) = SunVtoc::detect(device)? {
+        return Ok(vtoc.get_partitions());
+    }
+
+    if let Some(header) = SgiVolumeHeader::detect(device)? {
+        return Ok(header.get_partitions());
+    }
+
+    if let Some(rdb) = RigidDiskBlock::detect(device)? {
+        return Ok(rdb.get_partitions());
+    }
+
+    if !has_signature {
+        if let Some(table) = AhdiPartitionTable::detect(device)? {
+            return Ok(table.get_partitions());
+        }
+
+        return Err(ImageError::InvalidSignature(boot_sector.signature));
+    }
+
+    discover_mbr_partitions(device, &boot_sector)
+}
+
+fn discover_gpt_partitions<R: Read + Seek>(device: &mut BlockDevice<R>) -> Result<Vec<Partition>, ImageError> {
+    let header = GptHeader::from_block_device(device, GPT_PRIMARY_HEADER_LBA)?;
+    let mut partitions = Vec::new();
+
+    for (i, entry) in header.read_partition_entries(device)?.into_iter().enumerate() {
+        if !entry.is_used() {
+            continue;
+        }
+
+        let name = entry.get_name();
+        partitions.push(Partition {
+            index: i + 1,
+            scheme: PartitionScheme::Gpt,
+            partition_type: PartitionType::Gpt(entry.partition_type),
+            name: if name.is_empty() { None } else { Some(name) },
+            unique_guid: Some(entry.unique_partition_guid),
+            flags: entry.attributes,
+            sector_size: device.sector_size(),
+            start_lba: entry.starting_lba,
+            end_lba: entry.ending_lba,
+        });
+    }
+
+    Ok(partitions)
+}
+
+fn discover_mbr_partitions<R: Read + Seek>(
+    device: &mut BlockDevice<R>,
+    boot_sector: &BootSector,
+) -> Result<Vec<Partition>, ImageError> {
+    let mut partitions = Vec::new();
+
+    for (i, entry) in boot_sector.partitions.iter().enumerate() {
+        if entry.partition_type.code == 0 || entry.sector_count == 0 {
+            continue;
+        }
+
+        partitions.push(Partition {
+            index: i + 1,
+            scheme: PartitionScheme::Mbr,
+            partition_type: PartitionType::Mbr(entry.partition_type),
+            name: None,
+            unique_guid: None,
+            flags: PartitionStatusFlag::to_u8(&entry.status) as u64,
+            sector_size: device.sector_size(),
+            start_lba: entry.lba_start as u64,
+            end_lba: entry.lba_start as u64 + entry.sector_count as u64 - 1,
+        });
+    }
+
+    let sector_size = device.sector_size();
+    for entry in boot_sector.partitions.iter().filter(|entry| is_bsd_slice_type(entry.partition_type.code)) {
+        let mut slice_reader = entry.get_partition_reader(device, 0)?;
+        if let Some(label) = BsdDisklabel::detect(&mut slice_reader, sector_size)? {
+            partitions.extend(label.get_partitions(entry.lba_start as u64, sector_size));
+        }
+    }
+
+    let mut logical_index = FIRST_LOGICAL_PARTITION_INDEX;
+    for entry in boot_sector.partitions.iter().filter(|entry| entry.is_extended()) {
+        let chain = EbrChain::read(device, entry)?;
+        for logical in chain.logical_partitions.iter() {
+            let start_lba = logical.get_start_lba();
+            if logical.entry.sector_count == 0 {
+                warn!("Ignoring logical partition {} at LBA {}: it has no sectors", logical_index, start_lba);
+                logical_index += 1;
+                continue;
+            }
+
+            partitions.push(Partition {
+                index: logical_index,
+                scheme: PartitionScheme::Ebr,
+                partition_type: PartitionType::Mbr(logical.entry.partition_type),
+                name: None,
+                unique_guid: None,
+                flags: PartitionStatusFlag::to_u8(&logical.entry.status) as u64,
+                sector_size: device.sector_size(),
+                start_lba,
+                end_lba: start_lba + logical.entry.sector_count as u64 - 1,
+            });
+            logical_index += 1;
+        }
+    }
+
+    Ok(partitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpt::{GPT_HEADER_1_0_SIZE, GPT_HEADER_SIGNATURE, GPT_REVISION_1_0};
+    use std::io::Cursor;
+
+    fn write_entry(sector: &mut [u8], slot: usize, status: u8, partition_type: u8, lba_start: u32, sector_count: u32) {
+        let entry = &mut sector[446 + 16 * slot..446 + 16 * (slot + 1)];
+        entry[0] = status;
+        entry[4] = partition_type;
+        entry[8..12].copy_from_slice(&lba_start.to_le_bytes());
+        entry[12..16].copy_from_slice(&sector_count.to_le_bytes());
+    }
+
+    #[test]
+    fn discover_mbr_and_logical_partitions() {
+        let mut image = vec![0u8; 4096 * 512];
+        write_entry(&mut image[0..512], 0, 0x80, 0x0c, 2048, 1024);
+        write_entry(&mut image[0..512], 1, 0x00, 0x05, 3072, 1024);
+        image[510..512].copy_from_slice(BOOT_SECTOR_SIGNATURE);
+
+        let ebr = &mut image[3072 * 512..3073 * 512];
+        write_entry(ebr, 0, 0x00, 0x83, 63, 512);
+        ebr[510..512].copy_from_slice(BOOT_SECTOR_SIGNATURE);
+
+        let partitions = discover_partitions(&mut Cursor::new(image)).unwrap();
+        assert_eq!(partitions.len(), 3);
+
+        assert_eq!(partitions[0].index, 1);
+        assert!(partitions[0].is_bootable());
+        assert_eq!(partitions[0].get_start_offset(), 2048 * 512);
+        assert_eq!(partitions[0].end_lba, 3071);
+
+        assert!(partitions[1].is_extended());
+
+        assert_eq!(partitions[2].index, FIRST_LOGICAL_PARTITION_INDEX);
+        assert_eq!(partitions[2].scheme, PartitionScheme::Ebr);
+        assert_eq!(partitions[2].start_lba, 3072 + 63);
+        assert_eq!(partitions[2].get_size(), 512 * 512);
+    }
+
+    #[test]
+    fn skip_zero_length_logical_partition() {
+        let mut image = vec![0u8; 4096 * 512];
+        write_entry(&mut image[0..512], 0, 0x00, 0x05, 2048, 2048);
+        image[510..512].copy_from_slice(BOOT_SECTOR_SIGNATURE);
+
+        let ebr = &mut image[2048 * 512..2049 * 512];
+        write_entry(ebr, 0, 0x00, 0x83, 63, 0);
+        write_entry(ebr, 1, 0x00, 0x05, 1024, 1024);
+        ebr[510..512].copy_from_slice(BOOT_SECTOR_SIGNATURE);
+
+        let ebr = &mut image[3072 * 512..3073 * 512];
+        write_entry(ebr, 0, 0x00, 0x83, 63, 512);
+        ebr[510..512].copy_from_slice(BOOT_SECTOR_SIGNATURE);
+
+        let partitions = discover_partitions(&mut Cursor::new(image)).unwrap();
+        assert_eq!(partitions.len(), 2);
+        assert_eq!(partitions[1].index, FIRST_LOGICAL_PARTITION_INDEX + 1);
+        assert_eq!(partitions[1].start_lba, 3072 + 63);
+    }
+
+    fn build_gpt_image() -> Vec<u8> {
+        let mut image = vec![0u8; 128 * 512];
+        write_entry(&mut image[0..512], 0, 0x00, MBR_GPT_PARTITION_TYPE, 1, 127);
+        image[510..512].copy_from_slice(BOOT_SECTOR_SIGNATURE);
+
+        let header = &mut image[512..1024];
+        header[0..8].copy_from_slice(&GPT_HEADER_SIGNATURE);
+        header[8..12].copy_from_slice(&GPT_REVISION_1_0.to_le_bytes());
+        header[12..16].copy_from_slice(&GPT_HEADER_1_0_SIZE.to_le_bytes());
+        header[24..32].copy_from_slice(&1u64.to_le_bytes());
+        header[32..40].copy_from_slice(&127u64.to_le_bytes());
+        header[40..48].copy_from_slice(&34u64.to_le_bytes());
+        header[48..56].copy_from_slice(&94u64.to_le_bytes());
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+
+        // Leave the first slot unused so that indices follow slot numbers.
+        let entry = &mut image[2 * 512 + 128..2 * 512 + 256];
+        entry[0..16].copy_from_slice(&[
+            0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b,
+        ]);
+        entry[16] = 0x01;
+        entry[32..40].copy_from_slice(&34u64.to_le_bytes());
+        entry[40..48].copy_from_slice(&61u64.to_le_bytes());
+        entry[48..56].copy_from_slice(&GPT_ATTRIBUTE_LEGACY_BIOS_BOOTABLE.to_le_bytes());
+        for (i, unit) in "EFI".encode_utf16().enumerate() {
+            entry[56 + 2 * i..58 + 2 * i].copy_from_slice(&unit.to_le_bytes());
+        }
+
+        image
+    }
+
+    #[test]
+    fn discover_gpt_partitions_behind_protective_mbr() {
+        let image = build_gpt_image();
+        let partitions = discover_partitions(&mut Cursor::new(image)).unwrap();
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].index, 2);
+        assert_eq!(partitions[0].scheme, PartitionScheme::Gpt);
+        assert_eq!(partitions[0].name.as_deref(), Some("EFI"));
+        assert_ne!(partitions[0].unique_guid, Some(Uuid::nil()));
+        assert!(partitions[0].is_bootable());
+        assert_eq!(partitions[0].start_lba, 34);
+        assert_eq!(partitions[0].get_size(), 28 * 512);
+    }
+
+    #[test]
+    fn reject_invalid_gpt_entry_array() {
+        for (entry_size, count) in [(0u32, u32::MAX), (100, 128), (128, 0x20001)] {
+            let mut image = build_gpt_image();
+            image[512 + 80..512 + 84].copy_from_slice(&count.to_le_bytes());
+            image[512 + 84..512 + 88].copy_from_slice(&entry_size.to_le_bytes());
+            assert!(matches!(discover_partitions(&mut Cursor::new(image)), Err(ImageError::InvalidGeometry(_))));
+        }
+    }
+
+    #[test]
+    fn offsets_saturate_on_corrupt_lbas() {
+        let partition = Partition {
+            index: 1,
+            scheme: PartitionScheme::Gpt,
+            partition_type: PartitionType::Gpt(Uuid::nil()),
+            name: None,
+            unique_guid: None,
+            flags: 0,
+            sector_size: 512,
+            start_lba: u64::MAX - 1,
+            end_lba: u64::MAX,
+        };
+        assert_eq!(partition.get_start_offset(), u64::MAX);
+        assert_eq!(partition.get_end_offset(), u64::MAX);
+    }
+}
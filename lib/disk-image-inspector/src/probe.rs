@@ -0,0 +1,362 @@
+use std::{
+    cmp::Reverse,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+use uuid::Uuid;
+
+use crate::{
+    ahdi, apm, bsdlabel,
+    errors::{read_exact_at, ImageError},
+    fat, gpt, iso9660, ldm, luks, lvm, md, rdb, sgi, sun,
+};
+
+/// Helper trait so probe functions can take any `Read + Seek` as a trait object.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// How sure a detector is about its result.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ProbeConfidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Display for ProbeConfidence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Low => f.write_str("low"),
+            Self::Medium => f.write_str("medium"),
+            Self::High => f.write_str("high"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProbeUsage {
+    Filesystem,
+    PartitionTable,
+    Raid,
+    Crypto,
+    Other,
+}
+
+impl Display for ProbeUsage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Filesystem => f.write_str("filesystem"),
+            Self::PartitionTable => f.write_str("partition table"),
+            Self::Raid => f.write_str("raid"),
+            Self::Crypto => f.write_str("crypto"),
+            Self::Other => f.write_str("other"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProbeResult {
+    pub type_name: &'static str,
+    pub usage: ProbeUsage,
+    pub version: Option<String>,
+    pub confidence: ProbeConfidence,
+    pub label: Option<String>,
+    pub uuid: Option<String>,
+}
+
+impl ProbeResult {
+    pub fn new(type_name: &'static str, usage: ProbeUsage, confidence: ProbeConfidence) -> Self {
+        Self {
+            type_name,
+            usage,
+            version: None,
+            confidence,
+            label: None,
+            uuid: None,
+        }
+    }
+}
+
+impl Display for ProbeResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.type_name)?;
+        if let Some(version) = &self.version {
+            write!(f, " ({})", version)?;
+        }
+        write!(f, " [{}]", self.usage)?;
+        if let Some(label) = &self.label {
+            write!(f, ", label \"{}\"", label)?;
+        }
+        if let Some(uuid) = &self.uuid {
+            write!(f, ", UUID {}", uuid)?;
+        }
+        write!(f, ", confidence {}", self.confidence)
+    }
+}
+
+pub type ProbeFn = fn(&mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError>;
+
+#[derive(Clone, Copy)]
+pub struct Prober {
+    pub name: &'static str,
+    pub probe: ProbeFn,
+}
+
+/// A list of magic-based detectors, run in turn against a partition or volume.
+#[derive(Clone)]
+pub struct ProbeRegistry {
+    probers: Vec<Prober>,
+}
+
+impl ProbeRegistry {
+    /// Create a registry with no detectors.
+    pub fn new() -> Self {
+        Self { probers: Vec::new() }
+    }
+
+    pub fn register(&mut self, name: &'static str, probe: ProbeFn) {
+        self.probers.push(Prober { name, probe });
+    }
+
+    pub fn probers(&self) -> &[Prober] {
+        &self.probers
+    }
+
+    /// Run every detector against `reader` and return the matches, most confident first.
+    pub fn probe<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<ProbeResult>, ImageError> {
+        let mut results = Vec::new();
+        for prober in &self.probers {
+            if let Some(result) = (prober.probe)(reader)? {
+                results.push(result);
+            }
+        }
+
+        results.sort_by_key(|result| Reverse(result.confidence));
+        Ok(results)
+    }
+}
+
+impl Default for ProbeRegistry {
+    /// Create a registry with all of the detectors built into this crate.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("vfat", fat::probe);
+        registry.register("gpt", gpt::probe);
+        registry.register("apm", apm::probe);
+        registry.register("bsdlabel", bsdlabel::probe);
+        registry.register("sun", sun::probe);
+        registry.register("sgi", sgi::probe);
+        registry.register("amiga", rdb::probe);
+        registry.register("atari", ahdi::probe);
+        registry.register("ldm", ldm::probe);
+        registry.register("ext", probe_ext);
+        registry.register("ntfs", probe_ntfs);
+        registry.register("exfat", probe_exfat);
+        registry.register("xfs", probe_xfs);
+        registry.register("btrfs", probe_btrfs);
+        registry.register("swap", probe_swap);
+        registry.register("LVM2_member", lvm::probe);
+        registry.register("linux_raid_member", md::probe);
+        registry.register("crypto_LUKS", luks::probe);
+        registry.register("iso9660", iso9660::probe);
+        registry.register("squashfs", probe_squashfs);
+        registry.register("hfsplus", probe_hfsplus);
+        registry
+    }
+}
+
+/// Read `len` bytes at `offset`, returning `None` if the source is too short to hold them.
+pub(crate) fn read_probe_bytes<R: Read + Seek + ?Sized>(
+    reader: &mut R,
+    offset: u64,
+    len: usize,
+) -> Result<Option<Vec<u8>>, ImageError> {
+    let mut data = vec![0; len];
+    match read_exact_at(reader, offset, &mut data) {
+        Ok(()) => Ok(Some(data)),
+        Err(ImageError::Truncated { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decode a fixed-size string that ends at the first NUL, or fills the field.
+pub(crate) fn decode_c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_string()
+}
+
+/// Decode a fixed-size, NUL- or space-padded label, returning `None` if it is empty.
+pub(crate) fn decode_label(data: &[u8]) -> Option<String> {
+    let label = decode_c_string(data).trim_end().to_string();
+    if label.is_empty() {
+        None
+    } else {
+        Some(label)
+    }
+}
+
+fn decode_uuid(data: &[u8]) -> Option<String> {
+    match Uuid::from_slice(data) {
+        Ok(uuid) if !uuid.is_nil() => Some(uuid.to_string()),
+        _ => None,
+    }
+}
+
+const EXT_SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT_MAGIC: [u8; 2] = [0x53, 0xef];
+const EXT_FEATURE_COMPAT_HAS_JOURNAL: u32 = 0x0004;
+const EXT_FEATURE_INCOMPAT_EXT4: u32 = 0x0040 | 0x0080 | 0x0200;
+const EXT_FEATURE_RO_COMPAT_EXT4: u32 = 0x0008 | 0x0010 | 0x0020 | 0x0040;
+
+fn probe_ext(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let sb = match read_probe_bytes(reader, EXT_SUPERBLOCK_OFFSET, 1024)? {
+        Some(sb) if sb[0x38..0x3a] == EXT_MAGIC => sb,
+        _ => return Ok(None),
+    };
+
+    let feature_compat = u32::from_le_bytes([sb[0x5c], sb[0x5d], sb[0x5e], sb[0x5f]]);
+    let feature_incompat = u32::from_le_bytes([sb[0x60], sb[0x61], sb[0x62], sb[0x63]]);
+    let feature_ro_compat = u32::from_le_bytes([sb[0x64], sb[0x65], sb[0x66], sb[0x67]]);
+
+    let type_name =
+        if feature_incompat & EXT_FEATURE_INCOMPAT_EXT4 != 0 || feature_ro_compat & EXT_FEATURE_RO_COMPAT_EXT4 != 0 {
+            "ext4"
+        } else if feature_compat & EXT_FEATURE_COMPAT_HAS_JOURNAL != 0 {
+            "ext3"
+        } else {
+            "ext2"
+        };
+
+    let mut result = ProbeResult::new(type_name, ProbeUsage::Filesystem, ProbeConfidence::High);
+    result.uuid = decode_uuid(&sb[0x68..0x78]);
+    result.label = decode_label(&sb[0x78..0x88]);
+    Ok(Some(result))
+}
+
+fn probe_ntfs(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let bs = match read_probe_bytes(reader, 0, 512)? {
+        Some(bs) if &bs[3..11] == b"NTFS    " => bs,
+        _ => return Ok(None),
+    };
+
+    let mut serial = [0u8; 8];
+    serial.copy_from_slice(&bs[0x48..0x50]);
+    let mut result = ProbeResult::new("ntfs", ProbeUsage::Filesystem, ProbeConfidence::High);
+    result.uuid = Some(format!("{:016X}", u64::from_le_bytes(serial)));
+    Ok(Some(result))
+}
+
+fn probe_exfat(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let bs = match read_probe_bytes(reader, 0, 512)? {
+        Some(bs) if &bs[3..11] == b"EXFAT   " => bs,
+        _ => return Ok(None),
+    };
+
+    let serial = u32::from_le_bytes([bs[0x64], bs[0x65], bs[0x66], bs[0x67]]);
+    let mut result = ProbeResult::new("exfat", ProbeUsage::Filesystem, ProbeConfidence::High);
+    result.uuid = Some(format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff));
+    Ok(Some(result))
+}
+
+fn probe_xfs(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let sb = match read_probe_bytes(reader, 0, 512)? {
+        Some(sb) if &sb[0..4] == b"XFSB" => sb,
+        _ => return Ok(None),
+    };
+
+    let mut result = ProbeResult::new("xfs", ProbeUsage::Filesystem, ProbeConfidence::High);
+    result.uuid = decode_uuid(&sb[32..48]);
+    result.label = decode_label(&sb[108..120]);
+    Ok(Some(result))
+}
+
+const BTRFS_SUPERBLOCK_OFFSET: u64 = 0x10000;
+
+fn probe_btrfs(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let sb = match read_probe_bytes(reader, BTRFS_SUPERBLOCK_OFFSET, 0x22b)? {
+        Some(sb) if &sb[0x40..0x48] == b"_BHRfS_M" => sb,
+        _ => return Ok(None),
+    };
+
+    let mut result = ProbeResult::new("btrfs", ProbeUsage::Filesystem, ProbeConfidence::High);
+    result.uuid = decode_uuid(&sb[0x20..0x30]);
+    result.label = decode_label(&sb[0x12b..0x22b]);
+    Ok(Some(result))
+}
+
+const SWAP_PAGE_SIZES: [u64; 4] = [4096, 8192, 16384, 65536];
+
+fn probe_swap(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    for &page_size in SWAP_PAGE_SIZES.iter() {
+        let magic = match read_probe_bytes(reader, page_size - 10, 10)? {
+            Some(magic) => magic,
+            None => return Ok(None),
+        };
+
+        let version = match &magic[..] {
+            b"SWAPSPACE2" => "1",
+            b"SWAP-SPACE" => "0",
+            _ => continue,
+        };
+
+        let mut result = ProbeResult::new("swap", ProbeUsage::Other, ProbeConfidence::High);
+        result.version = Some(version.to_string());
+        if version == "1" {
+            if let Some(header) = read_probe_bytes(reader, 1024, 44)? {
+                result.uuid = decode_uuid(&header[0x0c..0x1c]);
+                result.label = decode_label(&header[0x1c..0x2c]);
+            }
+        }
+        return Ok(Some(result));
+    }
+
+    Ok(None)
+}
+
+fn probe_squashfs(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    match read_probe_bytes(reader, 0, 4)? {
+        Some(magic) if &magic[..] == b"hsqs" => {
+            Ok(Some(ProbeResult::new("squashfs", ProbeUsage::Filesystem, ProbeConfidence::High)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn probe_hfsplus(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    let (type_name, confidence) = match read_probe_bytes(reader, 1024, 2)?.as_deref() {
+        Some(b"H+") => ("hfsplus", ProbeConfidence::Medium),
+        Some(b"HX") => ("hfsx", ProbeConfidence::Medium),
+        Some(b"BD") => ("hfs", ProbeConfidence::Low),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(ProbeResult::new(type_name, ProbeUsage::Filesystem, confidence)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn probe_ext4_superblock() {
+        let mut image = vec![0u8; 4096];
+        image[1024 + 0x38..1024 + 0x3a].copy_from_slice(&EXT_MAGIC);
+        image[1024 + 0x60] = 0x40;
+        image[1024 + 0x68..1024 + 0x78].copy_from_slice(&[0x11; 16]);
+        image[1024 + 0x78..1024 + 0x7c].copy_from_slice(b"root");
+
+        let results = ProbeRegistry::default().probe(&mut Cursor::new(image)).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].type_name, "ext4");
+        assert_eq!(results[0].label.as_deref(), Some("root"));
+        assert_eq!(results[0].uuid.as_deref(), Some("11111111-1111-1111-1111-111111111111"));
+    }
+
+    #[test]
+    fn probe_empty_image() {
+        let results = ProbeRegistry::default().probe(&mut Cursor::new(vec![0u8; 512])).unwrap();
+        assert!(results.is_empty());
+    }
+}
@@ -0,0 +1,563 @@
+use log::warn;
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::{read_exact_at, ImageError},
+    partition::{Partition, PartitionScheme, PartitionType},
+    probe::{decode_label, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const RDB_BLOCK_SIZE: u32 = 512;
+// The largest `rdb_BlockBytes` accepted; block sizes must also be a power of two.
+pub const MAX_RDB_BLOCK_SIZE: u32 = 65536;
+
+// The RigidDiskBlock may be in any of the first 16 blocks of the disk.
+pub const RDB_LOCATION_LIMIT: u64 = 16;
+
+pub const RDB_RIGID_DISK_ID: &[u8; 4] = b"RDSK";
+pub const RDB_PARTITION_ID: &[u8; 4] = b"PART";
+pub const RDB_FILESYSTEM_HEADER_ID: &[u8; 4] = b"FSHD";
+
+// Terminates the partition and filesystem header lists.
+pub const RDB_END_OF_LIST: u32 = 0xffff_ffff;
+
+// Upper bound on the length of a block list; guards against lists that loop without revisiting a block.
+pub const MAX_RDB_LIST_LENGTH: usize = 128;
+
+pub const RDB_PARTITION_BOOTABLE: u32 = 0x0000_0001;
+pub const RDB_PARTITION_NO_MOUNT: u32 = 0x0000_0002;
+
+/// Names of the flags set in a partition block's `pb_Flags`.
+pub fn describe_rdb_partition_flags(flags: u32) -> Vec<&'static str> {
+    let mut names = Vec::new();
+    if flags & RDB_PARTITION_BOOTABLE != 0 {
+        names.push("bootable");
+    }
+    if flags & RDB_PARTITION_NO_MOUNT != 0 {
+        names.push("no automount");
+    }
+    names
+}
+
+/// Format an AmigaDOS DOS type such as `0x444f5303` as `DOS\3`.
+pub fn format_dos_type(dos_type: u32) -> String {
+    dos_type
+        .to_be_bytes()
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() { (b as char).to_string() } else { format!("\\{}", b) })
+        .collect()
+}
+
+/// Verify the checksum of an RDB block: the first `rdb_SummedLongs` big-endian longs sum to zero.
+fn is_block_checksum_valid(data: &[u8]) -> bool {
+    let summed_longs = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+    if summed_longs < 3 || summed_longs * 4 > data.len() {
+        return false;
+    }
+
+    let sum = data[..summed_longs * 4]
+        .chunks_exact(4)
+        .fold(0u32, |acc, word| acc.wrapping_add(u32::from_be_bytes(word.try_into().unwrap())));
+    sum == 0
+}
+
+fn decode_bcpl_string(data: &[u8]) -> String {
+    let len = (data[0] as usize).min(data.len() - 1);
+    String::from_utf8_lossy(&data[1..1 + len]).to_string()
+}
+
+/// A problem found while walking the RDB block lists.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RdbWarning {
+    BadChecksum {
+        block: u32,
+        id: String,
+    },
+    Loop {
+        block: u32,
+    },
+    TooLong {
+        limit: usize,
+    },
+    ReadFailed {
+        block: u32,
+        error: String,
+    },
+    UnexpectedBlock {
+        block: u32,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for RdbWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::BadChecksum { block, id } => write!(f, "RDB {} block {} has a bad checksum", id, block),
+            Self::Loop { block } => write!(f, "RDB block list loops back to block {}", block),
+            Self::TooLong { limit } => {
+                write!(f, "RDB block list is longer than {} blocks; stopped following it", limit)
+            }
+            Self::ReadFailed { block, error } => write!(f, "Unable to read RDB block {}: {}", block, error),
+            Self::UnexpectedBlock {
+                block,
+                expected,
+                actual,
+            } => {
+                write!(f, "RDB block {} has ID {}, expected {}", block, actual, expected)
+            }
+        }
+    }
+}
+
+/// A partition block ("PART") from the RDB partition list.
+#[derive(Clone, Debug)]
+pub struct RdbPartition {
+    pub block: u32,
+    pub flags: u32,
+    pub device_flags: u32,
+    pub drive_name: String,
+    // Fields from the DosEnvec at pb_Environment.
+    pub size_block: u32,
+    pub surfaces: u32,
+    pub sectors_per_block: u32,
+    pub blocks_per_track: u32,
+    pub reserved_blocks: u32,
+    pub low_cylinder: u32,
+    pub high_cylinder: u32,
+    pub max_transfer: u32,
+    pub boot_priority: i32,
+    pub dos_type: u32,
+    pub checksum_valid: bool,
+}
+
+impl RdbPartition {
+    pub fn new(block: u32, data: &[u8]) -> Result<Self, ImageError> {
+        if &data[0..4] != RDB_PARTITION_ID {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "RDB partition block ID is {}, not \"PART\"",
+                hex::encode(&data[0..4])
+            )));
+        }
+
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let table_size = be32(128);
+        // Older DosEnvecs are shorter; fields beyond de_TableSize are absent.
+        let env = |index: usize| if index as u32 <= table_size { be32(128 + 4 * index) } else { 0 };
+
+        Ok(Self {
+            block,
+            flags: be32(20),
+            device_flags: be32(32),
+            drive_name: decode_bcpl_string(&data[36..68]),
+            size_block: env(1),
+            surfaces: env(3),
+            sectors_per_block: env(4),
+            blocks_per_track: env(5),
+            reserved_blocks: env(6),
+            low_cylinder: env(9),
+            high_cylinder: env(10),
+            max_transfer: env(13),
+            boot_priority: env(15) as i32,
+            dos_type: env(16),
+            checksum_valid: is_block_checksum_valid(data),
+        })
+    }
+
+    pub fn get_next_block(data: &[u8]) -> u32 {
+        u32::from_be_bytes(data[16..20].try_into().unwrap())
+    }
+
+    /// The size of a filesystem block in bytes; `de_SizeBlock` is in longs.
+    pub fn get_block_size(&self) -> u32 {
+        self.size_block * 4
+    }
+
+    pub fn get_blocks_per_cylinder(&self) -> u64 {
+        self.surfaces as u64 * self.blocks_per_track as u64
+    }
+
+    pub fn get_start_block(&self) -> u64 {
+        self.low_cylinder as u64 * self.get_blocks_per_cylinder()
+    }
+
+    pub fn get_block_count(&self) -> u64 {
+        (self.high_cylinder as u64 + 1).saturating_sub(self.low_cylinder as u64) * self.get_blocks_per_cylinder()
+    }
+
+    pub fn is_bootable(&self) -> bool {
+        self.flags & RDB_PARTITION_BOOTABLE != 0
+    }
+}
+
+impl Display for RdbPartition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Drive name: {}\nDOS type: {} (0x{:08x})\nFlags: 0x{:08x} ({})\nCylinders: {}-{}\nSurfaces: {}\n\
+             Blocks per track: {}\nBlock size: {}\nReserved blocks: {}\nBoot priority: {}\nChecksum: {}",
+            self.drive_name,
+            format_dos_type(self.dos_type),
+            self.dos_type,
+            self.flags,
+            describe_rdb_partition_flags(self.flags).join(", "),
+            self.low_cylinder,
+            self.high_cylinder,
+            self.surfaces,
+            self.blocks_per_track,
+            self.get_block_size(),
+            self.reserved_blocks,
+            self.boot_priority,
+            if self.checksum_valid { "valid" } else { "mismatch" },
+        )
+    }
+}
+
+/// A filesystem header block ("FSHD") describing a filesystem handler stored in the RDB.
+#[derive(Clone, Debug)]
+pub struct RdbFileSystemHeader {
+    pub block: u32,
+    pub flags: u32,
+    pub dos_type: u32,
+    pub version: u32,
+    pub patch_flags: u32,
+    pub stack_size: u32,
+    pub priority: i32,
+    pub global_vector: i32,
+    pub seg_list_block: u32,
+    pub checksum_valid: bool,
+}
+
+impl RdbFileSystemHeader {
+    pub fn new(block: u32, data: &[u8]) -> Result<Self, ImageError> {
+        if &data[0..4] != RDB_FILESYSTEM_HEADER_ID {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "RDB filesystem header block ID is {}, not \"FSHD\"",
+                hex::encode(&data[0..4])
+            )));
+        }
+
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        Ok(Self {
+            block,
+            flags: be32(20),
+            dos_type: be32(32),
+            version: be32(36),
+            patch_flags: be32(40),
+            stack_size: be32(60),
+            priority: be32(64) as i32,
+            seg_list_block: be32(72),
+            global_vector: be32(76) as i32,
+            checksum_valid: is_block_checksum_valid(data),
+        })
+    }
+}
+
+impl Display for RdbFileSystemHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "DOS type: {} (0x{:08x})\nVersion: {}.{}\nStack size: {}\nPriority: {}\nLoad segment block: {}\n\
+             Checksum: {}",
+            format_dos_type(self.dos_type),
+            self.dos_type,
+            self.version >> 16,
+            self.version & 0xffff,
+            self.stack_size,
+            self.priority,
+            self.seg_list_block,
+            if self.checksum_valid { "valid" } else { "mismatch" },
+        )
+    }
+}
+
+/// An Amiga Rigid Disk Block ("RDSK") and the partition and filesystem header lists it points to.
+#[derive(Clone, Debug)]
+pub struct RigidDiskBlock {
+    pub block: u32,
+    pub host_id: u32,
+    pub block_size: u32,
+    pub flags: u32,
+    pub bad_block_list: u32,
+    pub partition_list: u32,
+    pub filesystem_header_list: u32,
+    pub cylinders: u32,
+    pub sectors: u32,
+    pub heads: u32,
+    pub rdb_blocks_low: u32,
+    pub rdb_blocks_high: u32,
+    pub disk_vendor: String,
+    pub disk_product: String,
+    pub disk_revision: String,
+    pub checksum_valid: bool,
+    pub partitions: Vec<RdbPartition>,
+    pub filesystem_headers: Vec<RdbFileSystemHeader>,
+    pub warnings: Vec<RdbWarning>,
+}
+
+impl RigidDiskBlock {
+    pub fn new(block: u32, data: &[u8]) -> Result<Self, ImageError> {
+        if &data[0..4] != RDB_RIGID_DISK_ID {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "Rigid disk block ID is {}, not \"RDSK\"",
+                hex::encode(&data[0..4])
+            )));
+        }
+
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let block_size = be32(16).max(RDB_BLOCK_SIZE);
+        if block_size > MAX_RDB_BLOCK_SIZE || !block_size.is_power_of_two() {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "Rigid disk block size {} is not a power of two of at most {} bytes",
+                be32(16),
+                MAX_RDB_BLOCK_SIZE
+            )));
+        }
+
+        Ok(Self {
+            block,
+            host_id: be32(12),
+            block_size: be32(16),
+            flags: be32(20),
+            bad_block_list: be32(24),
+            partition_list: be32(28),
+            filesystem_header_list: be32(32),
+            cylinders: be32(64),
+            sectors: be32(68),
+            heads: be32(72),
+            rdb_blocks_low: be32(128),
+            rdb_blocks_high: be32(132),
+            disk_vendor: decode_label(&data[160..168]).unwrap_or_default(),
+            disk_product: decode_label(&data[168..184]).unwrap_or_default(),
+            disk_revision: decode_label(&data[184..188]).unwrap_or_default(),
+            checksum_valid: is_block_checksum_valid(data),
+            partitions: Vec::new(),
+            filesystem_headers: Vec::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Scan the first 16 blocks of `reader` for a Rigid Disk Block and walk its lists, or return `None` if there is
+    /// no RDB.
+    ///
+    /// Broken lists are not errors; the walk stops and the problem is recorded in `warnings`.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        for block in 0..RDB_LOCATION_LIMIT {
+            let data = match read_probe_bytes(reader, block * RDB_BLOCK_SIZE as u64, RDB_BLOCK_SIZE as usize)? {
+                Some(data) => data,
+                None => return Ok(None),
+            };
+
+            if &data[0..4] != RDB_RIGID_DISK_ID {
+                continue;
+            }
+
+            let mut rdb = Self::new(block as u32, &data)?;
+            if !rdb.checksum_valid {
+                rdb.warnings.push(RdbWarning::BadChecksum {
+                    block: block as u32,
+                    id: "RDSK".to_string(),
+                });
+            }
+
+            let partition_list = rdb.partition_list;
+            for (block, data) in rdb.read_list(reader, partition_list, RDB_PARTITION_ID) {
+                match RdbPartition::new(block, &data) {
+                    Ok(partition) => rdb.partitions.push(partition),
+                    Err(e) => warn!("RDB partition block {} is invalid: {}", block, e),
+                }
+            }
+
+            let filesystem_header_list = rdb.filesystem_header_list;
+            for (block, data) in rdb.read_list(reader, filesystem_header_list, RDB_FILESYSTEM_HEADER_ID) {
+                match RdbFileSystemHeader::new(block, &data) {
+                    Ok(header) => rdb.filesystem_headers.push(header),
+                    Err(e) => warn!("RDB filesystem header block {} is invalid: {}", block, e),
+                }
+            }
+
+            return Ok(Some(rdb));
+        }
+
+        Ok(None)
+    }
+
+    /// Follow a linked list of blocks with the given ID, returning each block's number and contents.
+    fn read_list<R: Read + Seek + ?Sized>(&mut self, reader: &mut R, first: u32, id: &[u8; 4]) -> Vec<(u32, Vec<u8>)> {
+        let block_size = self.block_size.max(RDB_BLOCK_SIZE);
+        let id_name = String::from_utf8_lossy(id).to_string();
+        let mut blocks = Vec::new();
+        let mut visited = HashSet::new();
+        let mut next = first;
+
+        while next != RDB_END_OF_LIST {
+            if !visited.insert(next) {
+                self.warnings.push(RdbWarning::Loop { block: next });
+                break;
+            }
+
+            if blocks.len() >= MAX_RDB_LIST_LENGTH {
+                self.warnings.push(RdbWarning::TooLong {
+                    limit: MAX_RDB_LIST_LENGTH,
+                });
+                break;
+            }
+
+            let mut data = vec![0u8; block_size as usize];
+            if let Err(e) = read_exact_at(reader, next as u64 * block_size as u64, &mut data) {
+                self.warnings.push(RdbWarning::ReadFailed {
+                    block: next,
+                    error: e.to_string(),
+                });
+                break;
+            }
+
+            if &data[0..4] != id {
+                self.warnings.push(RdbWarning::UnexpectedBlock {
+                    block: next,
+                    expected: id_name,
+                    actual: String::from_utf8_lossy(&data[0..4]).to_string(),
+                });
+                break;
+            }
+
+            if !is_block_checksum_valid(&data) {
+                self.warnings.push(RdbWarning::BadChecksum {
+                    block: next,
+                    id: id_name.clone(),
+                });
+            }
+
+            let block = next;
+            // PART and FSHD blocks both keep the next-block link at offset 16.
+            next = RdbPartition::get_next_block(&data);
+            blocks.push((block, data));
+        }
+
+        blocks
+    }
+
+    /// The partitions in the RDB partition list, numbered by their position in the list (starting from 1).
+    pub fn get_partitions(&self) -> Vec<Partition> {
+        self.partitions
+            .iter()
+            .enumerate()
+            .filter(|(_, partition)| partition.get_block_count() > 0 && partition.size_block > 0)
+            .map(|(i, partition)| Partition {
+                index: i + 1,
+                scheme: PartitionScheme::Rdb,
+                partition_type: PartitionType::Rdb(partition.dos_type),
+                name: if partition.drive_name.is_empty() { None } else { Some(partition.drive_name.clone()) },
+                unique_guid: None,
+                flags: partition.flags as u64,
+                sector_size: partition.get_block_size(),
+                start_lba: partition.get_start_block(),
+                end_lba: partition.get_start_block() + partition.get_block_count() - 1,
+            })
+            .collect()
+    }
+}
+
+impl Display for RigidDiskBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Block: {}\nBlock size: {}\nGeometry: {} cylinders, {} heads, {} sectors\nRDB blocks: {}-{}\n\
+             Disk: {} {} {}\nFlags: 0x{:08x}\nChecksum: {}\nPartitions: {}\nFilesystem headers: {}",
+            self.block,
+            self.block_size,
+            self.cylinders,
+            self.heads,
+            self.sectors,
+            self.rdb_blocks_low,
+            self.rdb_blocks_high,
+            self.disk_vendor,
+            self.disk_product,
+            self.disk_revision,
+            self.flags,
+            if self.checksum_valid { "valid" } else { "mismatch" },
+            self.partitions.len(),
+            self.filesystem_headers.len(),
+        )
+    }
+}
+
+/// Detect an Amiga Rigid Disk Block.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    Ok(RigidDiskBlock::detect(reader)?.map(|rdb| {
+        let confidence = if rdb.checksum_valid { ProbeConfidence::High } else { ProbeConfidence::Low };
+        let mut result = ProbeResult::new("amiga", ProbeUsage::PartitionTable, confidence);
+        result.version = Some(format!("RDSK at block {}, {} partitions", rdb.block, rdb.partitions.len()));
+        result
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_block(image: &mut [u8], block: usize, id: &[u8; 4], fields: &[(usize, u32)]) {
+        let data = &mut image[block * 512..(block + 1) * 512];
+        data[0..4].copy_from_slice(id);
+        data[4..8].copy_from_slice(&128u32.to_be_bytes());
+        for &(offset, value) in fields {
+            data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        }
+
+        let sum = data.chunks_exact(4).fold(0u32, |acc, w| acc.wrapping_add(u32::from_be_bytes(w.try_into().unwrap())));
+        data[8..12].copy_from_slice(&0u32.wrapping_sub(sum).to_be_bytes());
+    }
+
+    #[test]
+    fn read_rigid_disk_block() {
+        let mut image = vec![0u8; 64 * 512];
+        write_block(&mut image, 1, RDB_RIGID_DISK_ID, &[(16, 512), (28, 3), (32, 4), (64, 100), (68, 16), (72, 2)]);
+
+        // The drive name is the BCPL string "DH0"; the DosEnvec starts at offset 128.
+        let fields = [
+            (16, 5),
+            (20, RDB_PARTITION_BOOTABLE),
+            (36, u32::from_be_bytes(*b"\x03DH0")),
+            (128, 16),
+            (132, 128),
+            (140, 2),
+            (144, 1),
+            (148, 16),
+            (164, 2),
+            (168, 99),
+            (192, 0x444f_5303),
+        ];
+        write_block(&mut image, 3, RDB_PARTITION_ID, &fields);
+
+        // The second partition links back to the first.
+        write_block(&mut image, 5, RDB_PARTITION_ID, &[(16, 3)]);
+        write_block(&mut image, 4, RDB_FILESYSTEM_HEADER_ID, &[(16, RDB_END_OF_LIST), (32, 0x5046_5303)]);
+
+        let rdb = RigidDiskBlock::detect(&mut Cursor::new(image)).unwrap().unwrap();
+        assert_eq!(rdb.block, 1);
+        assert!(rdb.checksum_valid);
+        assert_eq!(rdb.partitions.len(), 2);
+        assert!(rdb.partitions[0].checksum_valid);
+        assert_eq!(rdb.partitions[0].drive_name, "DH0");
+        assert_eq!(format_dos_type(rdb.partitions[0].dos_type), "DOS\\3");
+        assert_eq!(rdb.filesystem_headers.len(), 1);
+        assert_eq!(format_dos_type(rdb.filesystem_headers[0].dos_type), "PFS\\3");
+        assert_eq!(rdb.warnings, vec![RdbWarning::Loop { block: 3 }]);
+
+        let partitions = rdb.get_partitions();
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].start_lba, 2 * 2 * 16);
+        assert_eq!(partitions[0].get_sector_count(), 98 * 2 * 16);
+        assert!(partitions[0].is_bootable());
+
+        let mut oversized = vec![0u8; 2 * 512];
+        write_block(&mut oversized, 1, RDB_RIGID_DISK_ID, &[(16, 0x1000_0000)]);
+        assert!(RigidDiskBlock::detect(&mut Cursor::new(oversized)).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    cmp::min,
+    io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+};
+
+// Resolve `pos` against the current position and length of a view, rejecting negative or overflowing positions.
+fn seek_position(position: u64, length: u64, pos: SeekFrom) -> IoResult<u64> {
+    let new_position = match pos {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::Current(delta) => position.checked_add_signed(delta),
+        SeekFrom::End(delta) => length.checked_add_signed(delta),
+    };
+
+    new_position
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))
+}
+
+/// A `Read + Seek` view onto a byte range of an underlying source.
+///
+/// Positions are relative to the start of the range, and reads stop at the end of the range.
+#[derive(Debug)]
+pub struct PartitionReader<R: Read + Seek> {
+    inner: R,
+    start: u64,
+    length: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> PartitionReader<R> {
+    pub fn new(inner: R, start: u64, length: u64) -> Self {
+        Self {
+            inner,
+            start,
+            length,
+            position: 0,
+        }
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for PartitionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.position >= self.length {
+            return Ok(0);
+        }
+
+        let max_len = min(buf.len() as u64, self.length - self.position) as usize;
+        self.inner.seek(SeekFrom::Start(self.start + self.position))?;
+        let n_read = self.inner.read(&mut buf[..max_len])?;
+        self.position += n_read as u64;
+        Ok(n_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for PartitionReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.position = seek_position(self.position, self.length, pos)?;
+        Ok(self.position)
+    }
+}
+
+/// A run of bytes in a stitched volume and where it lives in the underlying source.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Extent {
+    pub logical_start: u64,
+    pub physical_start: u64,
+    pub length: u64,
+}
+
+impl Extent {
+    pub fn logical_end(&self) -> u64 {
+        self.logical_start + self.length
+    }
+}
+
+/// A `Read + Seek` view that stitches extents of an underlying source into one contiguous volume.
+///
+/// Positions are relative to the start of the volume. Holes between extents read as zeros.
+#[derive(Debug)]
+pub struct ExtentReader<R: Read + Seek> {
+    inner: R,
+    extents: Vec<Extent>,
+    length: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> ExtentReader<R> {
+    pub fn new(inner: R, mut extents: Vec<Extent>) -> Self {
+        extents.sort_by_key(|extent| extent.logical_start);
+        let length = extents.iter().map(Extent::logical_end).max().unwrap_or(0);
+        Self {
+            inner,
+            extents,
+            length,
+            position: 0,
+        }
+    }
+
+    pub fn extents(&self) -> &[Extent] {
+        &self.extents
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for ExtentReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let position = self.position;
+        let max_len = min(buf.len() as u64, self.length - position);
+        let n_read = match self.extents.iter().find(|extent| position < extent.logical_end()) {
+            Some(extent) if position >= extent.logical_start => {
+                let len = min(max_len, extent.logical_end() - position) as usize;
+                self.inner.seek(SeekFrom::Start(extent.physical_start + position - extent.logical_start))?;
+                self.inner.read(&mut buf[..len])?
+            }
+            Some(extent) => {
+                let len = min(max_len, extent.logical_start - position) as usize;
+                buf[..len].iter_mut().for_each(|b| *b = 0);
+                len
+            }
+            None => 0,
+        };
+
+        self.position += n_read as u64;
+        Ok(n_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for ExtentReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.position = seek_position(self.position, self.length, pos)?;
+        Ok(self.position)
+    }
+}
+
+/// A `Read + Seek` view that interleaves fixed-size chunks across several members, as RAID 0 does.
+///
+/// Chunk `n` of the volume is chunk `n / members.len()` of member `n % members.len()`. With a single member this is a
+/// plain pass-through, which is how a mirror is read.
+#[derive(Debug)]
+pub struct StripedReader<R: Read + Seek> {
+    members: Vec<R>,
+    chunk_size: u64,
+    length: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> StripedReader<R> {
+    /// Stripe across `members`, using the first `member_length` bytes of each (rounded down to whole chunks when there
+    /// is more than one member).
+    pub fn new(members: Vec<R>, chunk_size: u64, member_length: u64) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let length = match members.len() {
+            0 => 0,
+            1 => member_length,
+            n => n as u64 * (member_length - member_length % chunk_size),
+        };
+        Self {
+            members,
+            chunk_size,
+            length,
+            position: 0,
+        }
+    }
+
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn members_mut(&mut self) -> &mut [R] {
+        &mut self.members
+    }
+
+    pub fn into_inner(self) -> Vec<R> {
+        self.members
+    }
+}
+
+impl<R: Read + Seek> Read for StripedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let member_count = self.members.len() as u64;
+        let chunk = self.position / self.chunk_size;
+        let chunk_offset = self.position % self.chunk_size;
+        let len = min(buf.len() as u64, self.length - self.position);
+        let len = if member_count > 1 { min(len, self.chunk_size - chunk_offset) } else { len } as usize;
+
+        let member = &mut self.members[(chunk % member_count) as usize];
+        member.seek(SeekFrom::Start((chunk / member_count) * self.chunk_size + chunk_offset))?;
+        let n_read = member.read(&mut buf[..len])?;
+        self.position += n_read as u64;
+        Ok(n_read)
+    }
+}
+
+impl<R: Read + Seek> Seek for StripedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.position = seek_position(self.position, self.length, pos)?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn bounded_reads() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut reader = PartitionReader::new(Cursor::new(data), 10, 20);
+
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [10, 11, 12, 13, 14, 15, 16, 17]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), 16);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![26, 27, 28, 29]);
+
+        reader.seek(SeekFrom::Start(18)).unwrap();
+        assert!(reader.read_exact(&mut buf).is_err());
+        assert!(reader.seek(SeekFrom::Current(-100)).is_err());
+    }
+
+    #[test]
+    fn stitched_reads() {
+        let data: Vec<u8> = (0..100).collect();
+        let extents = vec![
+            Extent {
+                logical_start: 8,
+                physical_start: 50,
+                length: 4,
+            },
+            Extent {
+                logical_start: 0,
+                physical_start: 90,
+                length: 4,
+            },
+        ];
+        let mut reader = ExtentReader::new(Cursor::new(data), extents);
+        assert_eq!(reader.len(), 12);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, vec![90, 91, 92, 93, 0, 0, 0, 0, 50, 51, 52, 53]);
+    }
+
+    #[test]
+    fn striped_reads() {
+        let members = vec![Cursor::new(vec![0, 1, 4, 5, 8]), Cursor::new(vec![2, 3, 6, 7, 9])];
+        let mut reader = StripedReader::new(members, 2, 5);
+        assert_eq!(reader.len(), 8);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, vec![0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+}
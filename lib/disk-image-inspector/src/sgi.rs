@@ -0,0 +1,275 @@
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::ImageError,
+    partition::{Partition, PartitionScheme, PartitionType},
+    probe::{decode_label, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const SGI_VOLUME_HEADER_SIZE: usize = 512;
+pub const SGI_VOLUME_HEADER_MAGIC: u32 = 0x0be5_a941;
+pub const SGI_VOLUME_DIRECTORY_ENTRIES: usize = 15;
+pub const SGI_PARTITION_COUNT: usize = 16;
+pub const SGI_BLOCK_SIZE: u32 = 512;
+
+// By convention partition 8 holds the volume header itself and partition 10 covers the whole volume.
+pub const SGI_VOLUME_HEADER_PARTITION: usize = 8;
+pub const SGI_VOLUME_PARTITION: usize = 10;
+
+// From IRIX sys/dvh.h.
+pub const SGI_PARTITION_TYPES: [&str; 14] = [
+    "volume header",
+    "track replacement",
+    "sector replacement",
+    "raw",
+    "BSD",
+    "System V",
+    "volume",
+    "EFS",
+    "logical volume",
+    "raw logical volume",
+    "XFS",
+    "XFS log",
+    "XLV",
+    "XVM",
+];
+
+/// The name of an SGI volume header partition type.
+pub fn get_sgi_partition_type_name(partition_type: u32) -> &'static str {
+    SGI_PARTITION_TYPES.get(partition_type as usize).copied().unwrap_or("Unknown")
+}
+
+/// A file stored in the volume header's own partition, such as `sash` or `ide`.
+#[derive(Clone, Debug)]
+pub struct SgiVolumeDirectoryEntry {
+    pub name: String,
+    pub block: u32,
+    pub size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct SgiPartition {
+    pub block_count: u32,
+    pub first_block: u32,
+    pub partition_type: u32,
+}
+
+impl SgiPartition {
+    pub fn is_used(&self) -> bool {
+        self.block_count > 0
+    }
+}
+
+/// An SGI disk volume header (DVH), as written by IRIX and used on SGI MIPS systems.
+#[derive(Clone, Debug)]
+pub struct SgiVolumeHeader {
+    pub root_partition: i16,
+    pub swap_partition: i16,
+    pub boot_file: String,
+    pub cylinders: u16,
+    pub heads: u16,
+    pub sectors_per_track: u16,
+    pub bytes_per_sector: u16,
+    pub interleave: u16,
+    pub flags: u32,
+    pub volume_directory: Vec<SgiVolumeDirectoryEntry>,
+    pub partitions: Vec<SgiPartition>,
+    pub checksum: u32,
+    /// The 32-bit sum of every word in the header, which is zero when the checksum is valid.
+    pub computed_sum: u32,
+}
+
+impl SgiVolumeHeader {
+    pub fn new(data: &[u8]) -> Result<Self, ImageError> {
+        if data.len() < SGI_VOLUME_HEADER_SIZE {
+            return Err(ImageError::Truncated {
+                offset: 0,
+                expected: SGI_VOLUME_HEADER_SIZE,
+                actual: data.len(),
+            });
+        }
+
+        let be16 = |offset: usize| u16::from_be_bytes(data[offset..offset + 2].try_into().unwrap());
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        if be32(0) != SGI_VOLUME_HEADER_MAGIC {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "SGI volume header magic is 0x{:08x}, not 0x{:08x}",
+                be32(0),
+                SGI_VOLUME_HEADER_MAGIC
+            )));
+        }
+
+        let computed_sum = data[..SGI_VOLUME_HEADER_SIZE]
+            .chunks_exact(4)
+            .fold(0u32, |acc, word| acc.wrapping_add(u32::from_be_bytes(word.try_into().unwrap())));
+
+        let volume_directory = data[72..72 + 16 * SGI_VOLUME_DIRECTORY_ENTRIES]
+            .chunks_exact(16)
+            .map(|entry| SgiVolumeDirectoryEntry {
+                name: decode_label(&entry[0..8]).unwrap_or_default(),
+                block: u32::from_be_bytes(entry[8..12].try_into().unwrap()),
+                size: u32::from_be_bytes(entry[12..16].try_into().unwrap()),
+            })
+            .filter(|entry| !entry.name.is_empty())
+            .collect();
+
+        let partitions = data[312..312 + 12 * SGI_PARTITION_COUNT]
+            .chunks_exact(12)
+            .map(|entry| SgiPartition {
+                block_count: u32::from_be_bytes(entry[0..4].try_into().unwrap()),
+                first_block: u32::from_be_bytes(entry[4..8].try_into().unwrap()),
+                partition_type: u32::from_be_bytes(entry[8..12].try_into().unwrap()),
+            })
+            .collect();
+
+        Ok(Self {
+            root_partition: be16(4) as i16,
+            swap_partition: be16(6) as i16,
+            boot_file: decode_label(&data[8..24]).unwrap_or_default(),
+            cylinders: be16(28),
+            heads: be16(32),
+            sectors_per_track: be16(38),
+            bytes_per_sector: be16(40),
+            interleave: be16(42),
+            flags: be32(44),
+            volume_directory,
+            partitions,
+            checksum: be32(504),
+            computed_sum,
+        })
+    }
+
+    /// Read the volume header from the first block of `reader`, or return `None` if there is no SGI volume header.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        match read_probe_bytes(reader, 0, SGI_VOLUME_HEADER_SIZE)? {
+            Some(data) if data[0..4] == SGI_VOLUME_HEADER_MAGIC.to_be_bytes() => Ok(Some(Self::new(&data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn is_checksum_valid(&self) -> bool {
+        self.computed_sum == 0
+    }
+
+    /// The used partitions, numbered by slot (starting from 0, as IRIX does).
+    pub fn get_partitions(&self) -> Vec<Partition> {
+        self.partitions
+            .iter()
+            .enumerate()
+            .filter(|(_, partition)| partition.is_used())
+            .map(|(i, partition)| Partition {
+                index: i,
+                scheme: PartitionScheme::Sgi,
+                partition_type: PartitionType::Sgi(partition.partition_type),
+                name: None,
+                unique_guid: None,
+                flags: 0,
+                sector_size: SGI_BLOCK_SIZE,
+                start_lba: partition.first_block as u64,
+                end_lba: partition.first_block as u64 + partition.block_count as u64 - 1,
+            })
+            .collect()
+    }
+}
+
+impl Display for SgiVolumeHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Root partition: {}\nSwap partition: {}\nBoot file: {}\nGeometry: {} cylinders, {} heads, \
+             {} sectors/track, {} bytes/sector\nInterleave: {}\nFlags: 0x{:08x}\nChecksum: 0x{:08x} ({})",
+            self.root_partition,
+            self.swap_partition,
+            self.boot_file,
+            self.cylinders,
+            self.heads,
+            self.sectors_per_track,
+            self.bytes_per_sector,
+            self.interleave,
+            self.flags,
+            self.checksum,
+            if self.is_checksum_valid() {
+                "valid".to_string()
+            } else {
+                format!("mismatch, words sum to 0x{:08x}", self.computed_sum)
+            },
+        )?;
+
+        for entry in self.volume_directory.iter() {
+            write!(f, "\n    File {}: block {}, {} bytes", entry.name, entry.block, entry.size)?;
+        }
+
+        for (i, partition) in self.partitions.iter().enumerate().filter(|(_, partition)| partition.is_used()) {
+            write!(
+                f,
+                "\n    Partition {}: type {} ({}), block {}, {} blocks",
+                i,
+                partition.partition_type,
+                get_sgi_partition_type_name(partition.partition_type),
+                partition.first_block,
+                partition.block_count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Detect an SGI disk volume header.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    Ok(SgiVolumeHeader::detect(reader)?.map(|header| {
+        let confidence = if header.is_checksum_valid() { ProbeConfidence::High } else { ProbeConfidence::Medium };
+        let mut result = ProbeResult::new("sgi", ProbeUsage::PartitionTable, confidence);
+        result.version = Some(format!("{} partitions", header.partitions.iter().filter(|p| p.is_used()).count()));
+        result
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn write_partition(header: &mut [u8], slot: usize, block_count: u32, first_block: u32, partition_type: u32) {
+        let entry = &mut header[312 + 12 * slot..312 + 12 * (slot + 1)];
+        entry[0..4].copy_from_slice(&block_count.to_be_bytes());
+        entry[4..8].copy_from_slice(&first_block.to_be_bytes());
+        entry[8..12].copy_from_slice(&partition_type.to_be_bytes());
+    }
+
+    #[test]
+    fn read_volume_header() {
+        let mut header = vec![0u8; SGI_VOLUME_HEADER_SIZE];
+        header[0..4].copy_from_slice(&SGI_VOLUME_HEADER_MAGIC.to_be_bytes());
+        header[8..13].copy_from_slice(b"/unix");
+        header[40..42].copy_from_slice(&512u16.to_be_bytes());
+        header[72..76].copy_from_slice(b"sash");
+        header[80..84].copy_from_slice(&2u32.to_be_bytes());
+        header[84..88].copy_from_slice(&4096u32.to_be_bytes());
+        write_partition(&mut header, 0, 8192, 4096, 10);
+        write_partition(&mut header, SGI_VOLUME_HEADER_PARTITION, 4096, 0, 0);
+        write_partition(&mut header, SGI_VOLUME_PARTITION, 12288, 0, 6);
+
+        let sum =
+            header.chunks_exact(4).fold(0u32, |acc, w| acc.wrapping_add(u32::from_be_bytes(w.try_into().unwrap())));
+        header[504..508].copy_from_slice(&0u32.wrapping_sub(sum).to_be_bytes());
+
+        let dvh = SgiVolumeHeader::detect(&mut Cursor::new(header)).unwrap().unwrap();
+        assert!(dvh.is_checksum_valid());
+        assert_eq!(dvh.boot_file, "/unix");
+        assert_eq!(dvh.volume_directory.len(), 1);
+        assert_eq!(dvh.volume_directory[0].name, "sash");
+
+        let partitions = dvh.get_partitions();
+        assert_eq!(partitions.len(), 3);
+        assert_eq!(partitions[0].partition_type.get_name(), "XFS");
+        assert_eq!(partitions[0].start_lba, 4096);
+        assert_eq!(partitions[0].end_lba, 12287);
+        assert_eq!(partitions[2].index, SGI_VOLUME_PARTITION);
+    }
+}
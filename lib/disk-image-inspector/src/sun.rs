@@ -0,0 +1,302 @@
+use std::{
+    convert::TryInto,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{Read, Seek},
+};
+
+use crate::{
+    errors::ImageError,
+    partition::{Partition, PartitionScheme, PartitionType},
+    probe::{decode_c_string, read_probe_bytes, ProbeConfidence, ProbeResult, ProbeUsage, ReadSeek},
+};
+
+pub const SUN_LABEL_SIZE: usize = 512;
+pub const SUN_LABEL_MAGIC: u16 = 0xdabe;
+pub const SUN_LABEL_MAGIC_OFFSET: usize = 508;
+pub const SUN_VTOC_SANITY: u32 = 0x600d_deee;
+pub const SUN_SLICE_COUNT: usize = 8;
+
+// Slice 2 conventionally covers the whole disk.
+pub const SUN_TAG_BACKUP: u16 = 0x05;
+
+pub const SUN_FLAG_UNMOUNTABLE: u16 = 0x01;
+pub const SUN_FLAG_READ_ONLY: u16 = 0x10;
+
+// From illumos usr/src/uts/common/sys/vtoc.h.
+pub const SUN_SLICE_TAGS: [(u16, &str); 14] = [
+    (0x00, "unassigned"),
+    (0x01, "boot"),
+    (0x02, "root"),
+    (0x03, "swap"),
+    (0x04, "usr"),
+    (SUN_TAG_BACKUP, "backup"),
+    (0x06, "stand"),
+    (0x07, "var"),
+    (0x08, "home"),
+    (0x09, "alternates"),
+    (0x0a, "cache"),
+    (0x0b, "reserved"),
+    (0x0c, "system"),
+    (0x18, "BIOS boot"),
+];
+
+/// The name of a Sun VTOC slice tag.
+pub fn get_sun_tag_name(tag: u16) -> &'static str {
+    SUN_SLICE_TAGS.iter().find(|(code, _)| *code == tag).map(|(_, name)| *name).unwrap_or("Unknown")
+}
+
+/// Names of the flags set on a Sun VTOC slice.
+pub fn describe_sun_flags(flags: u16) -> Vec<&'static str> {
+    let mut names = Vec::new();
+    if flags & SUN_FLAG_UNMOUNTABLE != 0 {
+        names.push("unmountable");
+    }
+    if flags & SUN_FLAG_READ_ONLY != 0 {
+        names.push("read-only");
+    }
+    names
+}
+
+/// A slice in a Sun VTOC, combining the geometry-based map entry with the VTOC tag and flags.
+#[derive(Clone, Debug)]
+pub struct SunSlice {
+    pub tag: u16,
+    pub flags: u16,
+    pub start_cylinder: u32,
+    pub sector_count: u32,
+}
+
+impl SunSlice {
+    pub fn is_used(&self) -> bool {
+        self.sector_count > 0
+    }
+}
+
+/// A Sun SMI disk label with its VTOC, as written by Solaris and SunOS on SPARC systems.
+#[derive(Clone, Debug)]
+pub struct SunVtoc {
+    pub ascii_label: String,
+    pub version: u32,
+    pub volume_name: String,
+    // Whether the VTOC extension (tags, flags, volume name) carries the sanity value; older labels only have the map.
+    pub has_vtoc: bool,
+    pub rpm: u16,
+    pub physical_cylinders: u16,
+    pub alternate_cylinders: u16,
+    pub interleave: u16,
+    pub data_cylinders: u16,
+    pub heads: u16,
+    pub sectors_per_track: u16,
+    pub checksum: u16,
+    /// Checksum computed over the label as read, with the `checksum` field zeroed.
+    pub computed_checksum: u16,
+    pub slices: Vec<SunSlice>,
+}
+
+impl SunVtoc {
+    pub fn new(data: &[u8]) -> Result<Self, ImageError> {
+        if data.len() < SUN_LABEL_SIZE {
+            return Err(ImageError::Truncated {
+                offset: 0,
+                expected: SUN_LABEL_SIZE,
+                actual: data.len(),
+            });
+        }
+
+        let be16 = |offset: usize| u16::from_be_bytes(data[offset..offset + 2].try_into().unwrap());
+        let be32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        if be16(SUN_LABEL_MAGIC_OFFSET) != SUN_LABEL_MAGIC {
+            return Err(ImageError::InvalidPartitionEntry(format!(
+                "Sun disk label magic is 0x{:04x}, not 0x{:04x}",
+                be16(SUN_LABEL_MAGIC_OFFSET),
+                SUN_LABEL_MAGIC
+            )));
+        }
+
+        // The checksum is the XOR of every other 16-bit word in the label.
+        let computed_checksum = data[..SUN_LABEL_SIZE - 2]
+            .chunks_exact(2)
+            .fold(0, |acc, word| acc ^ u16::from_be_bytes([word[0], word[1]]));
+
+        let has_vtoc = be32(188) == SUN_VTOC_SANITY;
+        let slices = (0..SUN_SLICE_COUNT)
+            .map(|i| SunSlice {
+                tag: if has_vtoc { be16(142 + 4 * i) } else { 0 },
+                flags: if has_vtoc { be16(144 + 4 * i) } else { 0 },
+                start_cylinder: be32(444 + 8 * i),
+                sector_count: be32(448 + 8 * i),
+            })
+            .collect();
+
+        Ok(Self {
+            ascii_label: decode_c_string(&data[0..128]),
+            version: be32(128),
+            volume_name: if has_vtoc { decode_c_string(&data[132..140]) } else { String::new() },
+            has_vtoc,
+            rpm: be16(420),
+            physical_cylinders: be16(422),
+            alternate_cylinders: be16(434),
+            interleave: be16(430),
+            data_cylinders: be16(432),
+            heads: be16(436),
+            sectors_per_track: be16(438),
+            checksum: be16(510),
+            computed_checksum,
+            slices,
+        })
+    }
+
+    /// Read the label from the first sector of `reader`, or return `None` if there is no Sun label.
+    pub fn detect<R: Read + Seek + ?Sized>(reader: &mut R) -> Result<Option<Self>, ImageError> {
+        match read_probe_bytes(reader, 0, SUN_LABEL_SIZE)? {
+            Some(data) if data[SUN_LABEL_MAGIC_OFFSET..SUN_LABEL_MAGIC_OFFSET + 2] == SUN_LABEL_MAGIC.to_be_bytes() => {
+                Ok(Some(Self::new(&data)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn is_checksum_valid(&self) -> bool {
+        self.checksum == self.computed_checksum
+    }
+
+    pub fn get_sectors_per_cylinder(&self) -> u64 {
+        self.heads as u64 * self.sectors_per_track as u64
+    }
+
+    pub fn get_slice_start_lba(&self, slice: &SunSlice) -> u64 {
+        slice.start_cylinder as u64 * self.get_sectors_per_cylinder()
+    }
+
+    /// The used slices as partitions, numbered by slice (starting from 0, as in `c0t0d0s0`).
+    pub fn get_partitions(&self) -> Vec<Partition> {
+        self.slices
+            .iter()
+            .enumerate()
+            .filter(|(_, slice)| slice.is_used())
+            .map(|(i, slice)| {
+                let start_lba = self.get_slice_start_lba(slice);
+                Partition {
+                    index: i,
+                    scheme: PartitionScheme::Sun,
+                    partition_type: PartitionType::Sun(slice.tag),
+                    name: None,
+                    unique_guid: None,
+                    flags: slice.flags as u64,
+                    sector_size: SUN_LABEL_SIZE as u32,
+                    start_lba,
+                    end_lba: start_lba + slice.sector_count as u64 - 1,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Display for SunVtoc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "Label: {}\nVolume: {}\nVTOC version: {}{}\nGeometry: {} cylinders ({} physical, {} alternate), {} heads, \
+             {} sectors/track\nRPM: {}\nInterleave: {}\nChecksum: 0x{:04x} ({})",
+            self.ascii_label,
+            self.volume_name,
+            self.version,
+            if self.has_vtoc { "" } else { " (no VTOC)" },
+            self.data_cylinders,
+            self.physical_cylinders,
+            self.alternate_cylinders,
+            self.heads,
+            self.sectors_per_track,
+            self.rpm,
+            self.interleave,
+            self.checksum,
+            if self.is_checksum_valid() {
+                "valid".to_string()
+            } else {
+                format!("mismatch, computed 0x{:04x}", self.computed_checksum)
+            },
+        )?;
+
+        for (i, slice) in self.slices.iter().enumerate().filter(|(_, slice)| slice.is_used()) {
+            write!(
+                f,
+                "\n    Slice {}: tag 0x{:02x} ({}), flags 0x{:02x}",
+                i,
+                slice.tag,
+                get_sun_tag_name(slice.tag),
+                slice.flags
+            )?;
+            let flag_names = describe_sun_flags(slice.flags);
+            if !flag_names.is_empty() {
+                write!(f, " ({})", flag_names.join(", "))?;
+            }
+            write!(
+                f,
+                ", cylinder {} (LBA {}), {} sectors",
+                slice.start_cylinder,
+                self.get_slice_start_lba(slice),
+                slice.sector_count
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Detect a Sun SMI disk label.
+pub fn probe(reader: &mut dyn ReadSeek) -> Result<Option<ProbeResult>, ImageError> {
+    Ok(SunVtoc::detect(reader)?.map(|label| {
+        let confidence = if label.is_checksum_valid() { ProbeConfidence::High } else { ProbeConfidence::Low };
+        let mut result = ProbeResult::new("sun", ProbeUsage::PartitionTable, confidence);
+        result.version = Some(format!("{} slices", label.slices.iter().filter(|slice| slice.is_used()).count()));
+        result.label = if label.volume_name.is_empty() { None } else { Some(label.volume_name) };
+        result
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_vtoc() {
+        let mut label = vec![0u8; SUN_LABEL_SIZE];
+        label[0..18].copy_from_slice(b"SUN0.5G cyl 1000 a");
+        label[128..132].copy_from_slice(&1u32.to_be_bytes());
+        label[132..136].copy_from_slice(b"disk");
+        label[140..142].copy_from_slice(&8u16.to_be_bytes());
+        label[188..192].copy_from_slice(&SUN_VTOC_SANITY.to_be_bytes());
+        label[432..434].copy_from_slice(&1000u16.to_be_bytes());
+        label[436..438].copy_from_slice(&16u16.to_be_bytes());
+        label[438..440].copy_from_slice(&64u16.to_be_bytes());
+
+        // Slice 0 is root from cylinder 1; slice 2 is the whole-disk backup slice.
+        label[142..144].copy_from_slice(&2u16.to_be_bytes());
+        label[444..448].copy_from_slice(&1u32.to_be_bytes());
+        label[448..452].copy_from_slice(&(100 * 1024u32).to_be_bytes());
+        label[150..152].copy_from_slice(&SUN_TAG_BACKUP.to_be_bytes());
+        label[152..154].copy_from_slice(&SUN_FLAG_UNMOUNTABLE.to_be_bytes());
+        label[464..468].copy_from_slice(&(1000 * 1024u32).to_be_bytes());
+
+        label[508..510].copy_from_slice(&SUN_LABEL_MAGIC.to_be_bytes());
+        let checksum =
+            label[..510].chunks_exact(2).fold(0u16, |acc, word| acc ^ u16::from_be_bytes([word[0], word[1]]));
+        label[510..512].copy_from_slice(&checksum.to_be_bytes());
+
+        let vtoc = SunVtoc::detect(&mut Cursor::new(label)).unwrap().unwrap();
+        assert!(vtoc.is_checksum_valid());
+        assert!(vtoc.has_vtoc);
+        assert_eq!(vtoc.volume_name, "disk");
+
+        let partitions = vtoc.get_partitions();
+        assert_eq!(partitions.len(), 2);
+        assert_eq!(partitions[0].index, 0);
+        assert_eq!(partitions[0].partition_type, PartitionType::Sun(2));
+        assert_eq!(partitions[0].start_lba, 1024);
+        assert_eq!(partitions[1].index, 2);
+        assert_eq!(partitions[1].get_size(), 1000 * 1024 * 512);
+        assert_eq!(describe_sun_flags(partitions[1].flags as u16), vec!["unmountable"]);
+    }
+}